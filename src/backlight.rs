@@ -0,0 +1,174 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::os::storage;
+
+/// Brightness and idle timeouts, in `SD_SETTINGS_PATH`.
+pub const SETTINGS_FILE: &str = "display.cfg";
+
+/// How much one brightness key changes the level, in percent.
+pub const BRIGHTNESS_STEP: u8 = 10;
+/// The lowest brightness the keys go to, so the screen can't be turned
+/// off by accident.
+pub const MIN_BRIGHTNESS: u8 = 10;
+
+/// The user's backlight settings, kept as JSON on the SD card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BacklightSettings {
+    /// Percent, while the keyboard is in use.
+    pub brightness: u8,
+    /// Percent, once no key was pressed for `dim_after_secs`.
+    pub dim_brightness: u8,
+    /// 0 never dims.
+    pub dim_after_secs: u32,
+    /// Seconds without a key until the backlight is off; 0 keeps it on.
+    pub off_after_secs: u32,
+}
+
+impl Default for BacklightSettings {
+    fn default() -> Self {
+        Self {
+            brightness: 100,
+            dim_brightness: 20,
+            dim_after_secs: 30,
+            off_after_secs: 120,
+        }
+    }
+}
+
+impl BacklightSettings {
+    /// `SD_SETTINGS_PATH/SETTINGS_FILE`.
+    pub fn sd_card_path() -> PathBuf {
+        storage::settings_path(SETTINGS_FILE)
+    }
+
+    /// The settings in `path`, or the defaults if it is missing or broken.
+    pub fn load(path: &Path) -> Self {
+        storage::load_settings::<Self>(path).clamped()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        storage::save_settings(path, self)
+    }
+
+    /// Moves `brightness` by `steps` times `BRIGHTNESS_STEP`, between
+    /// `MIN_BRIGHTNESS` and 100.
+    pub fn step_brightness(&mut self, steps: i32) {
+        let level = self.brightness as i32 + steps * BRIGHTNESS_STEP as i32;
+        self.brightness = level.clamp(MIN_BRIGHTNESS as i32, 100) as u8;
+    }
+
+    /// The backlight level in percent after `idle` without a key.
+    pub fn level(&self, idle: Duration) -> u8 {
+        let secs = idle.as_secs();
+        if self.off_after_secs > 0 && secs >= self.off_after_secs as u64 {
+            0
+        } else if self.dim_after_secs > 0 && secs >= self.dim_after_secs as u64 {
+            self.dim_brightness.min(self.brightness)
+        } else {
+            self.brightness
+        }
+    }
+
+    fn clamped(self) -> Self {
+        Self {
+            brightness: self.brightness.min(100),
+            dim_brightness: self.dim_brightness.min(100),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::os::storage::temp_dir;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn dims_then_turns_off() {
+        let settings = BacklightSettings::default();
+        assert_eq!(settings.level(secs(0)), 100);
+        assert_eq!(settings.level(secs(29)), 100);
+        assert_eq!(settings.level(secs(30)), 20);
+        assert_eq!(settings.level(secs(119)), 20);
+        assert_eq!(settings.level(secs(120)), 0);
+    }
+
+    #[test]
+    fn off_wins_over_dim() {
+        let settings = BacklightSettings {
+            dim_after_secs: 60,
+            off_after_secs: 30,
+            ..Default::default()
+        };
+        assert_eq!(settings.level(secs(29)), 100);
+        assert_eq!(settings.level(secs(30)), 0);
+        assert_eq!(settings.level(secs(60)), 0);
+    }
+
+    #[test]
+    fn zero_disables_a_timer() {
+        let never_off = BacklightSettings {
+            off_after_secs: 0,
+            ..Default::default()
+        };
+        assert_eq!(never_off.level(secs(3600)), 20);
+
+        let never_dim = BacklightSettings {
+            dim_after_secs: 0,
+            ..Default::default()
+        };
+        assert_eq!(never_dim.level(secs(119)), 100);
+        assert_eq!(never_dim.level(secs(120)), 0);
+
+        let always_on = BacklightSettings {
+            dim_after_secs: 0,
+            off_after_secs: 0,
+            ..Default::default()
+        };
+        assert_eq!(always_on.level(secs(3600)), 100);
+    }
+
+    #[test]
+    fn dimming_never_brightens() {
+        let settings = BacklightSettings {
+            brightness: 30,
+            dim_brightness: 80,
+            ..Default::default()
+        };
+        assert_eq!(settings.level(secs(30)), 30);
+    }
+
+    #[test]
+    fn settings_file() {
+        let path = temp_dir("backlight").join("settings").join(SETTINGS_FILE);
+        assert_eq!(BacklightSettings::load(&path), BacklightSettings::default());
+
+        let settings = BacklightSettings {
+            brightness: 70,
+            dim_brightness: 10,
+            dim_after_secs: 5,
+            off_after_secs: 0,
+        };
+        settings.save(&path).unwrap();
+        assert_eq!(BacklightSettings::load(&path), settings);
+
+        fs::write(&path, "{").unwrap();
+        assert_eq!(BacklightSettings::load(&path), BacklightSettings::default());
+        fs::write(&path, r#"{ "brightness": 250, "dimBrightness": 101 }"#).unwrap();
+        let loaded = BacklightSettings::load(&path);
+        assert_eq!(loaded.brightness, 100);
+        assert_eq!(loaded.dim_brightness, 100);
+        assert_eq!(loaded.off_after_secs, 120);
+    }
+}
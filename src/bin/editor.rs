@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use cardputer::{
+    editor::{Editor, EditorAction},
+    hotkeys,
+    os::{chainload, storage},
+    runtime,
+    shell::{self, Shell},
+    terminal::{FbTerminal, History, OwnedTerminal, TerminalSettings, TerminalStyle, Theme},
+    typing::{KeyboardEvent, Typing},
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[3J\x1b[H";
+
+fn main() {
+    runtime::init();
+
+    let (mut p, _modem) = runtime::take_cardputer();
+
+    let mut terminal = OwnedTerminal::<SCREEN_WIDTH, SCREEN_HEIGHT>::new(p.display);
+    // More columns for code than the classic font gives, unless another
+    // theme was picked in terminal.cfg.
+    let theme = TerminalSettings::load(&TerminalSettings::sd_card_path()).theme;
+    terminal.set_style(match theme {
+        Theme::Classic => TerminalStyle::compact(),
+        theme => TerminalStyle::from_theme(theme),
+    });
+    terminal
+        .command_line_mut()
+        .set_history(History::for_app("editor"));
+
+    // Only used to resolve and complete the file name.
+    let shell = Shell::new(storage::SD_ROOT);
+    terminal.set_completer(shell.file_completer());
+
+    if p.sd_card.is_none() {
+        terminal.println("No SD card found. Insert one and reboot.");
+    }
+    terminal.println("File to edit:");
+
+    let mut typing = Typing::from_sd_card();
+    let mut editor: Option<Editor> = None;
+    // The open save question came from the hotkey, so leave the app after it.
+    let mut leaving = false;
+
+    loop {
+        if let Some(hotkeys::SystemAction::ReturnToOs) = hotkeys::poll_action(&mut p.keyboard) {
+            match &mut editor {
+                Some(editor) if editor.is_modified() => {
+                    editor.request_exit();
+                    leaving = true;
+                    show(&mut terminal, editor);
+                }
+                _ => chainload::reboot_to_factory(),
+            }
+        }
+
+        while let Some(evt) = p.keyboard.read_events() {
+            let Some(event) = typing.eat_keyboard_events(evt) else {
+                continue;
+            };
+
+            match &mut editor {
+                Some(open) => {
+                    if open.handle_event(event) == EditorAction::Exit {
+                        if leaving {
+                            chainload::reboot_to_factory();
+                        }
+                        editor = None;
+                        terminal.set_command_line_visible(true);
+                        terminal.write(CLEAR_SCREEN);
+                        terminal.println("File to edit:");
+                        continue;
+                    }
+                    leaving &= open.is_confirming_exit();
+                    show(&mut terminal, open);
+                }
+                None => {
+                    if let Some(KeyboardEvent::Enter) = terminal.handle_event(event) {
+                        let line = terminal.command_line().get().trim().to_string();
+                        terminal.enter();
+                        if line.is_empty() {
+                            continue;
+                        }
+
+                        let path = match shell::parse_path(&line) {
+                            Ok(path) => shell.resolve(&path),
+                            Err(err) => {
+                                for line in err.to_lines() {
+                                    terminal.println(&line);
+                                }
+                                continue;
+                            }
+                        };
+                        match Editor::open(path) {
+                            Ok(mut open) => {
+                                terminal.set_command_line_visible(false);
+                                terminal.write(CLEAR_SCREEN);
+                                show(&mut terminal, &mut open);
+                                editor = Some(open);
+                            }
+                            Err(err) => terminal.println(&format!("{}: {}", line, err)),
+                        }
+                    }
+                }
+            }
+        }
+
+        terminal.draw();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn show(terminal: &mut FbTerminal<SCREEN_WIDTH, SCREEN_HEIGHT>, editor: &mut Editor) {
+    let screen = terminal.screen();
+    let frame = editor.render(screen.cols(), screen.rows());
+    terminal.write(&frame);
+}
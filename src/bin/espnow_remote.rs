@@ -1,14 +1,18 @@
+use std::time::Duration;
+
 use cardputer::{
     hotkeys,
     os::chainload,
     runtime,
-    terminal::OwnedTerminal,
+    terminal::{FbTerminal, OwnedTerminal},
     typing::{KeyboardEvent, Typing},
     SCREEN_HEIGHT, SCREEN_WIDTH,
 };
 
-use log::info;
+use log::{error, info, LevelFilter};
 
+use esp_idf_hal::modem::Modem;
+use esp_idf_svc::sys::EspError;
 use esp_idf_svc::wifi::{AccessPointConfiguration, EspWifi};
 use esp_idf_svc::{
     espnow::{EspNow, PeerInfo},
@@ -18,18 +22,64 @@ use esp_idf_svc::{
 
 #[allow(clippy::approx_constant)]
 fn main() {
-    runtime::init();
-
-    let sysloop = EspSystemEventLoop::take().unwrap();
+    let log = runtime::init_with_screen_log(LevelFilter::Warn);
 
     let (mut p, modem) = runtime::take_cardputer();
 
-    let mut terminal = OwnedTerminal::<SCREEN_WIDTH, SCREEN_HEIGHT>::new(&mut p.display);
+    let mut terminal = OwnedTerminal::<SCREEN_WIDTH, SCREEN_HEIGHT>::new(p.display);
     terminal.auto_draw(true);
+    terminal.show_log(log);
 
     terminal.println("Espnow Remote");
 
-    let mut wifi = EspWifi::new(modem, sysloop.clone(), None).unwrap();
+    // The Wi-Fi driver has to stay alive for ESP-NOW to work.
+    let (_wifi, espnow, peer_address) = match connect(modem, &mut terminal) {
+        Ok(connected) => connected,
+        Err(err) => {
+            error!("Setup failed: {:?}", err);
+            // Keep the error on screen until the user leaves.
+            loop {
+                if let Some(hotkeys::SystemAction::ReturnToOs) =
+                    hotkeys::poll_action(&mut p.keyboard)
+                {
+                    chainload::reboot_to_factory();
+                }
+                terminal.draw();
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    };
+
+    let mut typing = Typing::from_sd_card();
+
+    terminal.println("Ready. Type to send");
+
+    loop {
+        if let Some(hotkeys::SystemAction::ReturnToOs) = hotkeys::poll_action(&mut p.keyboard) {
+            chainload::reboot_to_factory();
+        }
+
+        while let Some(evt) = p.keyboard.read_events() {
+            if let Some(KeyboardEvent::Ascii(c)) = typing.eat_keyboard_events(evt) {
+                if let Err(err) = espnow.send(peer_address, &[c as u8]) {
+                    error!("Send failed: {:?}", err);
+                }
+            }
+        }
+
+        terminal.draw();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Starts Wi-Fi, waits for the receiver's access point and adds it as the
+/// ESP-NOW peer.
+fn connect(
+    modem: Modem,
+    terminal: &mut FbTerminal<SCREEN_WIDTH, SCREEN_HEIGHT>,
+) -> Result<(EspWifi<'static>, EspNow<'static>, [u8; 6]), EspError> {
+    let sysloop = EspSystemEventLoop::take()?;
+    let mut wifi = EspWifi::new(modem, sysloop, None)?;
 
     let client_cfg = ClientConfiguration {
         channel: Some(0),
@@ -41,16 +91,15 @@ fn main() {
         ..Default::default()
     };
 
-    wifi.set_configuration(&Configuration::Mixed(client_cfg, ap_cfg))
-        .unwrap();
+    wifi.set_configuration(&Configuration::Mixed(client_cfg, ap_cfg))?;
 
-    wifi.start().unwrap();
+    wifi.start()?;
 
     terminal.println("Wifi started");
     terminal.println("Scanning...");
 
     let peer_address = loop {
-        let peer_address = find_client(&mut wifi);
+        let peer_address = find_client(&mut wifi)?;
 
         if let Some(peer_address) = peer_address {
             break peer_address;
@@ -61,13 +110,11 @@ fn main() {
 
     terminal.println(&format!("found peer: {:?}", peer_address));
 
-    let espnow = EspNow::take().unwrap();
+    let espnow = EspNow::take()?;
 
-    espnow
-        .register_send_cb(|_, _| {
-            info!("send_cb");
-        })
-        .unwrap();
+    espnow.register_send_cb(|_, _| {
+        info!("send_cb");
+    })?;
 
     let peer_info = PeerInfo {
         peer_addr: peer_address,
@@ -76,34 +123,19 @@ fn main() {
         ..Default::default()
     };
 
-    espnow.add_peer(peer_info).unwrap();
-
-    let mut typing = Typing::new();
-
-    terminal.println("Ready. Type to send");
-
-    loop {
-        if let Some(hotkeys::SystemAction::ReturnToOs) = hotkeys::poll_action(&mut p.keyboard) {
-            chainload::reboot_to_factory();
-        }
+    espnow.add_peer(peer_info)?;
 
-        let evt = p.keyboard.read_events();
-        if let Some(evt) = evt {
-            if let Some(KeyboardEvent::Ascii(c)) = typing.eat_keyboard_events(evt) {
-                espnow.send(peer_address, &[c as u8]).unwrap();
-            }
-        }
-    }
+    Ok((wifi, espnow, peer_address))
 }
 
-fn find_client(wifi: &mut EspWifi) -> Option<[u8; 6]> {
-    let scan = wifi.scan().unwrap();
+fn find_client(wifi: &mut EspWifi) -> Result<Option<[u8; 6]>, EspError> {
+    let scan = wifi.scan()?;
 
     for ap in scan {
         if ap.ssid == "esp32" {
-            return Some(ap.bssid);
+            return Ok(Some(ap.bssid));
         }
     }
 
-    None
+    Ok(None)
 }
@@ -138,8 +138,10 @@ fn main() {
     let (cardputer, _modem) = runtime::take_cardputer();
     let cardputer::hal::CardputerPeripherals {
         display,
+        backlight: _,
         mut keyboard,
         speaker: _,
+        sd_card: _sd_card,
     } = cardputer;
 
     let mut buffers = OwnedDoubleBuffer::<SCREEN_WIDTH, SCREEN_HEIGHT>::new();
@@ -147,9 +149,6 @@ fn main() {
 
     let text_style = MonoTextStyle::new(&FONT_6X10, Rgb565::CSS_WHITE);
 
-    // Try mount SD
-    let _sd = storage::mount_sd_card();
-
     info!("creating 3d scene");
     //
     // ----------------- CUT HERE -----------------
@@ -243,7 +242,10 @@ fn main() {
         }
 
 
-        if let Some((keyboard::KeyEvent::Pressed, key)) = keyboard.read_events() {
+        while let Some((event, key)) = keyboard.read_events() {
+            if event != keyboard::KeyEvent::Pressed {
+                continue;
+            }
             match key {
                 keyboard::Key::LeftSquareBracket => {
                     if !stl_entries.is_empty() {
@@ -1,62 +1,78 @@
+use std::path::Path;
+use std::time::Duration;
+
 use cardputer::{
     hotkeys,
-    os::chainload,
+    os::{chainload, storage},
+    rink::{load_definitions, parse_assignment, unit_names, Variables},
     runtime,
-    terminal::OwnedTerminal,
+    terminal::{completion::WordListCompleter, History, OwnedTerminal},
     typing::{KeyboardEvent, Typing},
     SCREEN_HEIGHT, SCREEN_WIDTH,
 };
 
+const UNITS: &str = include_str!("definitions.units");
+
 #[allow(clippy::approx_constant)]
 fn main() {
     runtime::init();
 
     let (mut p, _modem) = runtime::take_cardputer();
 
-    let mut terminal = OwnedTerminal::<SCREEN_WIDTH, SCREEN_HEIGHT>::new(&mut p.display);
+    let mut terminal = OwnedTerminal::<SCREEN_WIDTH, SCREEN_HEIGHT>::new(p.display);
+    terminal
+        .command_line_mut()
+        .set_history(History::for_app("rink"));
+    terminal.set_completer(WordListCompleter::new(unit_names(UNITS)));
 
-    let mut typing = Typing::new();
+    let mut typing = Typing::from_sd_card();
 
     let mut ctx = simple_context_().unwrap();
+    for message in load_definitions(&mut ctx, storage::SD_RINK_PATH) {
+        terminal.println(&message);
+    }
+    let (mut variables, errors) =
+        Variables::load(Path::new(storage::SD_RINK_PATH).join("vars"), &mut ctx);
+    for error in errors {
+        terminal.println(&error);
+    }
 
     loop {
         if let Some(hotkeys::SystemAction::ReturnToOs) = hotkeys::poll_action(&mut p.keyboard) {
             chainload::reboot_to_factory();
         }
 
-        let evt = p.keyboard.read_events();
-        if let Some(evt) = evt {
-            if let Some(evts) = typing.eat_keyboard_events(evt) {
-                match evts {
-                    KeyboardEvent::Ascii(c) => {
-                        terminal.command_line_mut().push(c);
+        while let Some(evt) = p.keyboard.read_events() {
+            if let Some(KeyboardEvent::Enter) = typing
+                .eat_keyboard_events(evt)
+                .and_then(|event| terminal.handle_event(event))
+            {
+                let line = terminal.command_line().get().to_string();
+                terminal.enter();
+
+                if line.trim() == "vars" {
+                    for (name, expr) in variables.entries() {
+                        terminal.println(&format!("{} = {}", name, expr));
                     }
-                    KeyboardEvent::Backspace => {
-                        terminal.command_line_mut().pop();
+                } else if let Some((name, expr)) = parse_assignment(&line) {
+                    if let Err(err) = variables.set(&mut ctx, name, expr) {
+                        terminal.println(&err);
                     }
-                    KeyboardEvent::Enter => {
-                        let line = terminal.command_line_mut().get().to_string();
-                        let res = execute_command(&line, &mut ctx);
-                        terminal.enter();
-                        terminal.println(&res);
-                    }
-                    KeyboardEvent::ArrowUp => {
-                        terminal.command_line_mut().arrow_up();
-                    }
-                    _ => {}
+                } else {
+                    let res = execute_command(&line, &mut ctx);
+                    terminal.println_paged(&res);
                 }
             }
         }
 
         terminal.draw();
+        std::thread::sleep(Duration::from_millis(10));
     }
 }
 
 fn execute_command(command: &str, ctx: &mut rink_core::Context) -> String {
     use rink_core::*;
 
-    //let mut ctx = Context::new();
-
     let result = one_line(ctx, command);
 
     match result {
@@ -70,16 +86,14 @@ pub fn simple_context_() -> Result<rink_core::Context, String> {
 
     use rink_core::loader::gnu_units;
 
-    let units = include_str!("definitions.units");
-
-    let mut iter = gnu_units::TokenIterator::new(units).peekable();
+    let mut iter = gnu_units::TokenIterator::new(UNITS).peekable();
     let units = gnu_units::parse(&mut iter);
 
-    //let dates = parsing::datetime::parse_datefile(DATES_FILE);
+    let dates = parsing::datetime::parse_datefile(DATES_FILE);
 
     let mut ctx = Context::new();
     ctx.load(units)?;
-    //ctx.load_dates(dates);
+    ctx.load_dates(dates);
 
     Ok(ctx)
 }
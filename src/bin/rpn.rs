@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use cardputer::{
+    hotkeys,
+    os::chainload,
+    rpn::{self, Calculator},
+    runtime,
+    terminal::{completion::WordListCompleter, FbTerminal, History, OwnedTerminal},
+    typing::{KeyboardEvent, Typing},
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+fn main() {
+    runtime::init();
+
+    let (mut p, _modem) = runtime::take_cardputer();
+
+    let mut terminal = OwnedTerminal::<SCREEN_WIDTH, SCREEN_HEIGHT>::new(p.display);
+    terminal
+        .command_line_mut()
+        .set_history(History::for_app("rpn"));
+    terminal.set_completer(WordListCompleter::new(rpn::completion_words()));
+
+    let mut typing = Typing::from_sd_card();
+    let mut calc = Calculator::new();
+    let mut message = "Enter numbers, then + - * / ^\r\nchs makes X negative".to_string();
+    show(&mut terminal, &calc, &message);
+
+    loop {
+        if let Some(hotkeys::SystemAction::ReturnToOs) = hotkeys::poll_action(&mut p.keyboard) {
+            chainload::reboot_to_factory();
+        }
+
+        while let Some(evt) = p.keyboard.read_events() {
+            let Some(event) = typing.eat_keyboard_events(evt) else {
+                continue;
+            };
+
+            // An operator key right after a number applies at once, like on
+            // an HP calculator. Anywhere else it is typed, e.g. in `1e-3`.
+            if let KeyboardEvent::Ascii(op @ ('+' | '-' | '*' | '/' | '^' | '%')) = event {
+                let line = terminal.command_line().get().trim().to_string();
+                if line.is_empty() || calc.parse_number(&line).is_some() {
+                    terminal.command_line_mut().clear();
+                    message = run(&mut calc, &format!("{} {}", line, op));
+                    show(&mut terminal, &calc, &message);
+                    continue;
+                }
+            }
+
+            if let Some(KeyboardEvent::Enter) = terminal.handle_event(event) {
+                let line = terminal.command_line().get().to_string();
+                terminal.enter();
+                message = if line.trim().is_empty() {
+                    calc.enter();
+                    String::new()
+                } else {
+                    run(&mut calc, &line)
+                };
+                show(&mut terminal, &calc, &message);
+            }
+        }
+
+        terminal.draw();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn run(calc: &mut Calculator, line: &str) -> String {
+    match calc.eval(line) {
+        Ok(()) => String::new(),
+        Err(err) => err.to_string(),
+    }
+}
+
+/// Repaints the modes at the top, the last error under them and the stack
+/// at the bottom, with X just above the command line.
+fn show(terminal: &mut FbTerminal<SCREEN_WIDTH, SCREEN_HEIGHT>, calc: &Calculator, message: &str) {
+    let cols = terminal.screen().cols();
+    let rows = terminal.screen().rows();
+
+    let mut frame = format!(
+        "\x1b[2J\x1b[3J\x1b[H\x1b[7m{:<1$}\x1b[0m",
+        calc.status(),
+        cols
+    );
+    if rows > 5 && !message.is_empty() {
+        frame.push_str(&format!("\x1b[2;1H\x1b[31m{}\x1b[0m", message));
+    }
+    for (i, line) in calc.stack_lines(cols).iter().enumerate() {
+        let row = (rows + i + 1).saturating_sub(4).max(2);
+        frame.push_str(&format!("\x1b[{};1H{}", row, line));
+    }
+    terminal.write(&frame);
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use cardputer::{
+    hotkeys,
+    os::{chainload, storage},
+    runtime,
+    shell::{Command, Output, Shell},
+    terminal::{History, OwnedTerminal},
+    typing::{KeyboardEvent, Typing},
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+fn main() {
+    runtime::init();
+
+    let (mut p, _modem) = runtime::take_cardputer();
+
+    let mut terminal = OwnedTerminal::<SCREEN_WIDTH, SCREEN_HEIGHT>::new(p.display);
+    terminal
+        .command_line_mut()
+        .set_history(History::for_app("shell"));
+
+    let mut shell = Shell::new(storage::SD_ROOT);
+    shell.set_width(terminal.screen().cols());
+    shell.set_disk_usage(storage::sd_card_usage);
+    terminal.set_completer(shell.completer());
+
+    if p.sd_card.is_some() {
+        terminal.println("SD card mounted. Type help for commands.");
+    } else {
+        terminal.println("No SD card found. Insert one and reboot.");
+    }
+
+    let mut typing = Typing::from_sd_card();
+
+    loop {
+        if let Some(hotkeys::SystemAction::ReturnToOs) = hotkeys::poll_action(&mut p.keyboard) {
+            chainload::reboot_to_factory();
+        }
+
+        while let Some(evt) = p.keyboard.read_events() {
+            if let Some(KeyboardEvent::Enter) = typing
+                .eat_keyboard_events(evt)
+                .and_then(|event| terminal.handle_event(event))
+            {
+                let line = terminal.command_line().get().to_string();
+                terminal.enter();
+
+                let result = Command::parse(&line).and_then(|command| match command {
+                    Some(command) => shell.execute(command),
+                    None => Ok(Output::Lines(Vec::new())),
+                });
+                match result {
+                    // `cat` and `help` can be taller than the screen.
+                    Ok(Output::Lines(lines)) if !lines.is_empty() => {
+                        terminal.println_paged(&lines.join("\n"));
+                    }
+                    Ok(Output::Lines(_)) => {}
+                    Ok(Output::Clear) => terminal.write("\x1b[2J\x1b[3J\x1b[H"),
+                    Ok(Output::Run(path)) => {
+                        if let Err(err) = chainload::flash_and_reboot(terminal.buffers_mut(), &path)
+                        {
+                            for line in err.to_lines() {
+                                terminal.println(&line);
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        for line in err.to_lines() {
+                            terminal.println(&line);
+                        }
+                    }
+                }
+            }
+        }
+
+        terminal.draw();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,54 @@
+use std::{env, fs, process};
+
+use cardputer::{
+    keyboard::Key,
+    sim::{SimDisplay, Simulator},
+};
+
+const USAGE: &str = "usage: sim <sd card dir> [frames dir] [up|down|enter|back|refresh]...";
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let Some(sd_root) = args.next() else {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    };
+    let frames_dir = args.next().unwrap_or_else(|| "sim-frames".to_string());
+    if let Err(err) = fs::create_dir_all(&frames_dir) {
+        eprintln!("{}: {}", frames_dir, err);
+        process::exit(1);
+    }
+
+    let mut sim = Simulator::new(sd_root, SimDisplay::with_output_dir(&frames_dir));
+    for name in args {
+        match key_from_name(&name) {
+            Some(key) => sim.tap(key),
+            None => {
+                eprintln!("Unknown key: {}\n{}", name, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+    sim.run();
+
+    for path in sim.launched() {
+        println!("Launched {}", path.display());
+    }
+    println!(
+        "{} frames written to {}",
+        sim.display().frame_count(),
+        frames_dir
+    );
+}
+
+/// The launcher's keys by what they do.
+fn key_from_name(name: &str) -> Option<Key> {
+    match name {
+        "up" => Some(Key::Semicolon),
+        "down" => Some(Key::Period),
+        "enter" => Some(Key::Enter),
+        "back" => Some(Key::Backspace),
+        "refresh" => Some(Key::Tab),
+        _ => None,
+    }
+}
@@ -1,4 +1,5 @@
 use std::f32::consts::PI;
+use std::time::Duration;
 
 use cardputer::{
     hotkeys,
@@ -17,9 +18,9 @@ fn main() {
     // esp_idf_hal::i2s::I2sDriver::new_std_tx(i2s, config, bclk, dout, mclk, ws)
     let (mut p, _modem) = runtime::take_cardputer();
 
-    let mut terminal = OwnedTerminal::<SCREEN_WIDTH, SCREEN_HEIGHT>::new(&mut p.display);
+    let mut terminal = OwnedTerminal::<SCREEN_WIDTH, SCREEN_HEIGHT>::new(p.display);
 
-    let mut typing = Typing::new();
+    let mut typing = Typing::from_sd_card();
 
     // Enable the speaker,
     // TODO: is there reason to not do this in hal.rs?
@@ -32,44 +33,30 @@ fn main() {
             chainload::reboot_to_factory();
         }
 
-        let evt = p.keyboard.read_events();
-        if let Some(evt) = evt {
-            if let Some(evts) = typing.eat_keyboard_events(evt) {
-                match evts {
-                    KeyboardEvent::Ascii(c) => {
-                        terminal.command_line_mut().push(c);
+        while let Some(evt) = p.keyboard.read_events() {
+            if let Some(KeyboardEvent::Enter) = typing
+                .eat_keyboard_events(evt)
+                .and_then(|event| terminal.handle_event(event))
+            {
+                let text = terminal.command_line_mut().get().to_string();
+
+                match text.as_str() {
+                    "b" => {
+                        p.speaker
+                            .write_all(&wav, esp_idf_hal::delay::TickType::new_millis(100).into())
+                            .unwrap();
                     }
-                    KeyboardEvent::Backspace => {
-                        terminal.command_line_mut().pop();
+                    _ => {
+                        terminal.println("Commands: b to Beep");
                     }
-                    KeyboardEvent::Enter => {
-                        let text = terminal.command_line_mut().get().to_string();
-
-                        match text.as_str() {
-                            "b" => {
-                                p.speaker
-                                    .write_all(
-                                        &wav,
-                                        esp_idf_hal::delay::TickType::new_millis(100).into(),
-                                    )
-                                    .unwrap();
-                            }
-                            _ => {
-                                terminal.println("Commands: b to Beep");
-                            }
-                        }
-
-                        terminal.enter();
-                    }
-                    KeyboardEvent::ArrowUp => {
-                        terminal.command_line_mut().arrow_up();
-                    }
-                    _ => {}
                 }
+
+                terminal.enter();
             }
         }
 
         terminal.draw();
+        std::thread::sleep(Duration::from_millis(10));
     }
 }
 
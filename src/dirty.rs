@@ -0,0 +1,187 @@
+/// A rectangle of a frame, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn full(width: usize, height: usize) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    /// The region widened to whole rows, so it can go out in one transfer.
+    pub fn full_rows(&self, frame_width: usize) -> Self {
+        Self {
+            x: 0,
+            width: frame_width,
+            ..*self
+        }
+    }
+}
+
+/// Remembers the last frame sent to the panel to find the part of the next
+/// one that changed. Apps redraw every frame from scratch, so tracking what
+/// was drawn would cover the whole screen; comparing finds e.g. just the
+/// clock in the launcher's status bar.
+pub struct DirtyTracker {
+    width: usize,
+    previous: Vec<u16>,
+    /// False until a frame is on the panel, or after a failed write.
+    valid: bool,
+}
+
+impl DirtyTracker {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            previous: vec![0; width * height],
+            valid: false,
+        }
+    }
+
+    /// The next frame is reported as changed everywhere.
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+
+    /// The bounding box of the pixels in `frame` that differ from the frame
+    /// given last time, or `None` if nothing changed. `frame` becomes the
+    /// frame to compare with, so only pass frames that reach the panel.
+    pub fn update(&mut self, frame: &[u16]) -> Option<Region> {
+        let width = self.width;
+        let height = self.previous.len() / width;
+
+        if !self.valid {
+            self.previous.copy_from_slice(frame);
+            self.valid = true;
+            return Some(Region::full(width, height));
+        }
+
+        let rows = || {
+            frame
+                .chunks_exact(width)
+                .zip(self.previous.chunks_exact(width))
+        };
+        let top = rows().position(|(new, old)| new != old)?;
+        let bottom = height - 1 - rows().rev().position(|(new, old)| new != old)?;
+
+        let mut left = width;
+        let mut right = 0;
+        for (new, old) in rows().take(bottom + 1).skip(top) {
+            let differs = |(a, b): (&u16, &u16)| a != b;
+            if let Some(x) = new.iter().zip(old).position(differs) {
+                left = left.min(x);
+                right = right.max(width - 1 - new.iter().zip(old).rev().position(differs).unwrap());
+            }
+        }
+
+        let start = top * width;
+        let end = (bottom + 1) * width;
+        self.previous[start..end].copy_from_slice(&frame[start..end]);
+
+        Some(Region {
+            x: left,
+            y: top,
+            width: right + 1 - left,
+            height: bottom + 1 - top,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 8;
+    const HEIGHT: usize = 6;
+
+    fn tracker() -> (DirtyTracker, Vec<u16>) {
+        let mut tracker = DirtyTracker::new(WIDTH, HEIGHT);
+        let frame = vec![0x1234; WIDTH * HEIGHT];
+        assert_eq!(tracker.update(&frame), Some(Region::full(WIDTH, HEIGHT)));
+        (tracker, frame)
+    }
+
+    #[test]
+    fn first_frame_is_full() {
+        let mut tracker = DirtyTracker::new(WIDTH, HEIGHT);
+        // Even a frame matching the blank starting buffer goes out whole.
+        let frame = vec![0; WIDTH * HEIGHT];
+        assert_eq!(tracker.update(&frame), Some(Region::full(WIDTH, HEIGHT)));
+    }
+
+    #[test]
+    fn unchanged_frame_is_none() {
+        let (mut tracker, frame) = tracker();
+        assert_eq!(tracker.update(&frame), None);
+    }
+
+    #[test]
+    fn single_pixel() {
+        let (mut tracker, mut frame) = tracker();
+        frame[3 * WIDTH + 5] = 0xffff;
+        let region = Region {
+            x: 5,
+            y: 3,
+            width: 1,
+            height: 1,
+        };
+        assert_eq!(tracker.update(&frame), Some(region));
+        assert_eq!(tracker.update(&frame), None);
+    }
+
+    #[test]
+    fn distant_corners() {
+        let (mut tracker, mut frame) = tracker();
+        frame[WIDTH + 6] = 0;
+        frame[4 * WIDTH + 1] = 0;
+        let region = Region {
+            x: 1,
+            y: 1,
+            width: 6,
+            height: 4,
+        };
+        assert_eq!(tracker.update(&frame), Some(region));
+
+        frame[0] = 1;
+        frame[WIDTH * HEIGHT - 1] = 1;
+        assert_eq!(tracker.update(&frame), Some(Region::full(WIDTH, HEIGHT)));
+    }
+
+    #[test]
+    fn invalidate_sends_the_full_frame() {
+        let (mut tracker, mut frame) = tracker();
+        frame[2 * WIDTH + 2] = 0;
+        assert!(tracker.update(&frame).is_some());
+
+        // The write failed, so the panel may show anything.
+        tracker.invalidate();
+        assert_eq!(tracker.update(&frame), Some(Region::full(WIDTH, HEIGHT)));
+        assert_eq!(tracker.update(&frame), None);
+    }
+
+    #[test]
+    fn full_rows() {
+        let region = Region {
+            x: 3,
+            y: 2,
+            width: 2,
+            height: 4,
+        };
+        let rows = Region {
+            x: 0,
+            y: 2,
+            width: WIDTH,
+            height: 4,
+        };
+        assert_eq!(region.full_rows(WIDTH), rows);
+    }
+}
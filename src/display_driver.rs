@@ -14,6 +14,8 @@ use display_interface::WriteOnlyDataCommand;
 use embedded_hal::delay::DelayNs;
 use embedded_hal::digital::OutputPin;
 
+use crate::dirty::Region;
+
 #[repr(u8)]
 pub enum Instruction {
     NOP = 0x00,
@@ -59,26 +61,25 @@ where
     bl: Option<BL>,
     // Current orientation
     orientation: Orientation,
+    // Where full frames go, see `set_frame_window`
+    frame_window: Option<(u16, u16, u16, u16)>,
+    // Whether a partial write moved the address window off the frame window
+    window_moved: bool,
 }
 
 ///
 /// Display orientation.
 ///
 #[repr(u8)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Default)]
 pub enum Orientation {
+    #[default]
     Portrait = 0b0000_0000,         // no inverting
     Landscape = 0b0110_0000,        // invert column and page/column order
     PortraitSwapped = 0b1100_0000,  // invert page and column order
     LandscapeSwapped = 0b1010_0000, // invert page and page/column order
 }
 
-impl Default for Orientation {
-    fn default() -> Self {
-        Self::Portrait
-    }
-}
-
 ///
 /// Tearing effect output setting.
 ///
@@ -130,6 +131,8 @@ where
             rst,
             bl,
             orientation: Orientation::default(),
+            frame_window: None,
+            window_moved: false,
         }
     }
 
@@ -313,7 +316,26 @@ where
         self.write_data(&ex.to_be_bytes())?;
         self.write_command(Instruction::RASET)?;
         self.write_data(&sy.to_be_bytes())?;
-        self.write_data(&ey.to_be_bytes())
+        self.write_data(&ey.to_be_bytes())?;
+        self.window_moved = true;
+        Ok(())
+    }
+
+    ///
+    /// Sets the address window and keeps it as the one full frames are
+    /// written to. Partial frame writes are placed relative to it.
+    ///
+    pub fn set_frame_window(
+        &mut self,
+        sx: u16,
+        sy: u16,
+        ex: u16,
+        ey: u16,
+    ) -> Result<(), Error<PinE>> {
+        self.set_address_window(sx, sy, ex, ey)?;
+        self.frame_window = Some((sx, sy, ex, ey));
+        self.window_moved = false;
+        Ok(())
     }
 
     ///
@@ -334,12 +356,39 @@ where
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FbWriteError {
     Error,
 }
 pub trait FramebufferTarget {
     fn eat_framebuffer(&mut self, buf: &[u16]) -> Result<(), FbWriteError>;
+
+    /// Presents only `region` of `buf`, a frame `width` pixels wide. Targets
+    /// that can't write a part of the screen present the whole frame.
+    fn eat_region(
+        &mut self,
+        buf: &[u16],
+        width: usize,
+        region: Region,
+    ) -> Result<(), FbWriteError> {
+        let _ = (width, region);
+        self.eat_framebuffer(buf)
+    }
+}
+
+impl<T: FramebufferTarget + ?Sized> FramebufferTarget for Box<T> {
+    fn eat_framebuffer(&mut self, buf: &[u16]) -> Result<(), FbWriteError> {
+        (**self).eat_framebuffer(buf)
+    }
+
+    fn eat_region(
+        &mut self,
+        buf: &[u16],
+        width: usize,
+        region: Region,
+    ) -> Result<(), FbWriteError> {
+        (**self).eat_region(buf, width, region)
+    }
 }
 
 impl<DI, RST, BL, PinE> FramebufferTarget for ST7789<DI, RST, BL>
@@ -349,10 +398,231 @@ where
     BL: OutputPin<Error = PinE>,
 {
     fn eat_framebuffer(&mut self, buf: &[u16]) -> Result<(), FbWriteError> {
+        if self.window_moved {
+            if let Some((sx, sy, ex, ey)) = self.frame_window {
+                self.set_frame_window(sx, sy, ex, ey)
+                    .map_err(|_| FbWriteError::Error)?;
+            }
+        }
         self.write_command(Instruction::RAMWR)
             .map_err(|_| FbWriteError::Error)?;
         self.di
             .send_data(DataFormat::U16(buf))
             .map_err(|_| FbWriteError::Error)
     }
+
+    fn eat_region(
+        &mut self,
+        buf: &[u16],
+        width: usize,
+        region: Region,
+    ) -> Result<(), FbWriteError> {
+        // Without a frame window there is no telling where the region goes.
+        let Some((fx, fy, _, _)) = self.frame_window else {
+            return self.eat_framebuffer(buf);
+        };
+
+        // Each row of a narrow region is its own transfer; wide ones are
+        // cheaper to send as whole rows in one go.
+        let region = if region.width * 2 > width {
+            region.full_rows(width)
+        } else {
+            region
+        };
+
+        let sx = fx + region.x as u16;
+        let sy = fy + region.y as u16;
+        self.set_address_window(
+            sx,
+            sy,
+            sx + region.width as u16 - 1,
+            sy + region.height as u16 - 1,
+        )
+        .map_err(|_| FbWriteError::Error)?;
+        self.write_command(Instruction::RAMWR)
+            .map_err(|_| FbWriteError::Error)?;
+
+        if region.width == width {
+            let rows = &buf[region.y * width..(region.y + region.height) * width];
+            return self
+                .di
+                .send_data(DataFormat::U16(rows))
+                .map_err(|_| FbWriteError::Error);
+        }
+        for y in region.y..region.y + region.height {
+            let start = y * width + region.x;
+            self.di
+                .send_data(DataFormat::U16(&buf[start..start + region.width]))
+                .map_err(|_| FbWriteError::Error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use display_interface::DisplayError;
+
+    #[derive(Debug, PartialEq)]
+    enum Sent {
+        Command(u8),
+        Bytes(Vec<u8>),
+        Pixels(Vec<u16>),
+    }
+
+    /// Records everything sent to the panel.
+    #[derive(Default)]
+    struct RecordingInterface {
+        sent: Vec<Sent>,
+    }
+
+    impl WriteOnlyDataCommand for RecordingInterface {
+        fn send_commands(&mut self, cmd: DataFormat<'_>) -> Result<(), DisplayError> {
+            let U8Iter(bytes) = cmd else {
+                panic!("commands are sent as bytes");
+            };
+            self.sent.extend(bytes.map(Sent::Command));
+            Ok(())
+        }
+
+        fn send_data(&mut self, buf: DataFormat<'_>) -> Result<(), DisplayError> {
+            self.sent.push(match buf {
+                U8Iter(bytes) => Sent::Bytes(bytes.collect()),
+                DataFormat::U16(pixels) => Sent::Pixels(pixels.to_vec()),
+                U16BEIter(pixels) => Sent::Pixels(pixels.collect()),
+                _ => panic!("unexpected data format"),
+            });
+            Ok(())
+        }
+    }
+
+    struct NoPin;
+
+    impl embedded_hal::digital::ErrorType for NoPin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for NoPin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    const WIDTH: usize = 4;
+    const HEIGHT: usize = 3;
+
+    fn frame() -> Vec<u16> {
+        (0..(WIDTH * HEIGHT) as u16).collect()
+    }
+
+    /// A driver for a 4x3 frame placed at (40, 53) on the panel.
+    fn display() -> ST7789<RecordingInterface, NoPin, NoPin> {
+        let mut display = ST7789::new(RecordingInterface::default(), None, None);
+        display.set_frame_window(40, 53, 43, 55).unwrap();
+        display
+    }
+
+    fn sent(display: &mut ST7789<RecordingInterface, NoPin, NoPin>) -> Vec<Sent> {
+        std::mem::take(&mut display.di.sent)
+    }
+
+    fn window(sx: u16, sy: u16, ex: u16, ey: u16) -> Vec<Sent> {
+        vec![
+            Sent::Command(Instruction::CASET as u8),
+            Sent::Bytes(sx.to_be_bytes().to_vec()),
+            Sent::Bytes(ex.to_be_bytes().to_vec()),
+            Sent::Command(Instruction::RASET as u8),
+            Sent::Bytes(sy.to_be_bytes().to_vec()),
+            Sent::Bytes(ey.to_be_bytes().to_vec()),
+            Sent::Command(Instruction::RAMWR as u8),
+        ]
+    }
+
+    #[test]
+    fn narrow_region_is_sent_row_by_row() {
+        let mut display = display();
+        sent(&mut display);
+        let region = Region {
+            x: 1,
+            y: 1,
+            width: 1,
+            height: 2,
+        };
+        display.eat_region(&frame(), WIDTH, region).unwrap();
+
+        let mut expected = window(41, 54, 41, 55);
+        expected.push(Sent::Pixels(vec![5]));
+        expected.push(Sent::Pixels(vec![9]));
+        assert_eq!(sent(&mut display), expected);
+    }
+
+    #[test]
+    fn wide_region_is_sent_as_full_rows() {
+        let mut display = display();
+        sent(&mut display);
+        let region = Region {
+            x: 1,
+            y: 0,
+            width: 3,
+            height: 2,
+        };
+        display.eat_region(&frame(), WIDTH, region).unwrap();
+
+        let mut expected = window(40, 53, 43, 54);
+        expected.push(Sent::Pixels(frame()[..2 * WIDTH].to_vec()));
+        assert_eq!(sent(&mut display), expected);
+    }
+
+    #[test]
+    fn full_frame_after_a_region_resets_the_window() {
+        let mut display = display();
+        let region = Region {
+            x: 2,
+            y: 2,
+            width: 1,
+            height: 1,
+        };
+        display.eat_region(&frame(), WIDTH, region).unwrap();
+        sent(&mut display);
+
+        display.eat_framebuffer(&frame()).unwrap();
+        let mut expected = window(40, 53, 43, 55);
+        expected.push(Sent::Pixels(frame()));
+        assert_eq!(sent(&mut display), expected);
+
+        // The window is still in place for the next full frame.
+        display.eat_framebuffer(&frame()).unwrap();
+        assert_eq!(
+            sent(&mut display),
+            [
+                Sent::Command(Instruction::RAMWR as u8),
+                Sent::Pixels(frame())
+            ]
+        );
+    }
+
+    #[test]
+    fn region_without_frame_window_sends_the_whole_frame() {
+        let mut display = ST7789::<_, NoPin, NoPin>::new(RecordingInterface::default(), None, None);
+        let region = Region {
+            x: 1,
+            y: 1,
+            width: 1,
+            height: 1,
+        };
+        display.eat_region(&frame(), WIDTH, region).unwrap();
+        assert_eq!(
+            sent(&mut display),
+            [
+                Sent::Command(Instruction::RAMWR as u8),
+                Sent::Pixels(frame())
+            ]
+        );
+    }
 }
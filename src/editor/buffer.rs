@@ -0,0 +1,256 @@
+/// A place in a `TextBuffer`. `col` counts characters, not bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, col: usize) -> Self {
+        Self { line, col }
+    }
+}
+
+/// The text being edited, as a list of lines without their line endings.
+///
+/// There is always at least one line. The line ending style and whether
+/// the file ended with a newline are remembered, so saving an unchanged
+/// file gives back the same bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextBuffer {
+    lines: Vec<String>,
+    crlf: bool,
+    trailing_newline: bool,
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        Self {
+            lines: vec![String::new()],
+            crlf: false,
+            trailing_newline: true,
+        }
+    }
+
+    pub fn from_text(text: &str) -> Self {
+        let crlf = text.contains("\r\n");
+        let trailing_newline = text.ends_with('\n');
+        let body = text.strip_suffix('\n').unwrap_or(text);
+        let lines = body
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+            .collect();
+        Self {
+            lines,
+            crlf,
+            trailing_newline,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let newline = if self.crlf { "\r\n" } else { "\n" };
+        let mut text = self.lines.join(newline);
+        if self.trailing_newline {
+            text.push_str(newline);
+        }
+        text
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn line(&self, line: usize) -> &str {
+        self.lines.get(line).map_or("", String::as_str)
+    }
+
+    pub fn line_len(&self, line: usize) -> usize {
+        self.line(line).chars().count()
+    }
+
+    /// The nearest position that exists in the buffer.
+    pub fn clamp(&self, pos: Position) -> Position {
+        let line = pos.line.min(self.lines.len() - 1);
+        Position::new(line, pos.col.min(self.line_len(line)))
+    }
+
+    /// Inserts `c` at `pos` and returns the position after it.
+    pub fn insert_char(&mut self, pos: Position, c: char) -> Position {
+        let pos = self.clamp(pos);
+        let at = self.byte_index(pos);
+        self.lines[pos.line].insert(at, c);
+        Position::new(pos.line, pos.col + 1)
+    }
+
+    /// Splits the line at `pos` and returns the start of the new line.
+    pub fn insert_newline(&mut self, pos: Position) -> Position {
+        let pos = self.clamp(pos);
+        let at = self.byte_index(pos);
+        let rest = self.lines[pos.line].split_off(at);
+        self.lines.insert(pos.line + 1, rest);
+        Position::new(pos.line + 1, 0)
+    }
+
+    /// Deletes the character before `pos`, joining with the previous line at
+    /// the start of a line. Returns where the cursor goes.
+    pub fn delete_before(&mut self, pos: Position) -> Position {
+        let pos = self.clamp(pos);
+        if pos.col > 0 {
+            let prev = Position::new(pos.line, pos.col - 1);
+            let at = self.byte_index(prev);
+            self.lines[pos.line].remove(at);
+            prev
+        } else if pos.line > 0 {
+            let line = self.lines.remove(pos.line);
+            let prev = Position::new(pos.line - 1, self.line_len(pos.line - 1));
+            self.lines[pos.line - 1].push_str(&line);
+            prev
+        } else {
+            pos
+        }
+    }
+
+    /// Deletes the character at `pos`, joining with the next line at the end of a line.
+    pub fn delete_at(&mut self, pos: Position) {
+        let pos = self.clamp(pos);
+        if pos.col < self.line_len(pos.line) {
+            let at = self.byte_index(pos);
+            self.lines[pos.line].remove(at);
+        } else if pos.line + 1 < self.lines.len() {
+            let next = self.lines.remove(pos.line + 1);
+            self.lines[pos.line].push_str(&next);
+        }
+    }
+
+    /// The first match of `query` after `from`, wrapping around to the start.
+    pub fn find(&self, query: &str, from: Position) -> Option<Position> {
+        if query.is_empty() {
+            return None;
+        }
+        let from = self.clamp(from);
+        let count = self.lines.len();
+        // The line `from` is on comes up twice: after `from`, and before it once wrapped.
+        for i in 0..=count {
+            let line = (from.line + i) % count;
+            let text = &self.lines[line];
+            let skip = if i == 0 { from.col + 1 } else { 0 };
+            let start = text
+                .char_indices()
+                .nth(skip)
+                .map_or(text.len(), |(at, _)| at);
+            if let Some(at) = text[start..].find(query) {
+                let col = text[..start + at].chars().count();
+                let found = Position::new(line, col);
+                if i < count || found <= from {
+                    return Some(found);
+                }
+            }
+        }
+        None
+    }
+
+    fn byte_index(&self, pos: Position) -> usize {
+        let line = &self.lines[pos.line];
+        line.char_indices()
+            .nth(pos.col)
+            .map_or(line.len(), |(at, _)| at)
+    }
+}
+
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(buffer: &TextBuffer) -> Vec<&str> {
+        (0..buffer.line_count()).map(|i| buffer.line(i)).collect()
+    }
+
+    #[test]
+    fn insert_and_split_lines() {
+        let mut buffer = TextBuffer::new();
+        let mut pos = Position::default();
+        for c in "héllo".chars() {
+            pos = buffer.insert_char(pos, c);
+        }
+        assert_eq!(pos, Position::new(0, 5));
+
+        let pos = buffer.insert_newline(Position::new(0, 2));
+        assert_eq!(pos, Position::new(1, 0));
+        assert_eq!(lines(&buffer), ["hé", "llo"]);
+
+        let pos = buffer.insert_char(Position::new(9, 9), '!');
+        assert_eq!(pos, Position::new(1, 4));
+        assert_eq!(lines(&buffer), ["hé", "llo!"]);
+    }
+
+    #[test]
+    fn delete_before_joins_with_previous_line() {
+        let mut buffer = TextBuffer::from_text("ab\ncd\n");
+        assert_eq!(
+            buffer.delete_before(Position::new(1, 1)),
+            Position::new(1, 0)
+        );
+        assert_eq!(lines(&buffer), ["ab", "d"]);
+
+        assert_eq!(
+            buffer.delete_before(Position::new(1, 0)),
+            Position::new(0, 2)
+        );
+        assert_eq!(lines(&buffer), ["abd"]);
+
+        assert_eq!(
+            buffer.delete_before(Position::new(0, 0)),
+            Position::new(0, 0)
+        );
+        assert_eq!(lines(&buffer), ["abd"]);
+    }
+
+    #[test]
+    fn delete_at_joins_with_next_line() {
+        let mut buffer = TextBuffer::from_text("aé\ncd");
+        buffer.delete_at(Position::new(0, 1));
+        assert_eq!(lines(&buffer), ["a", "cd"]);
+
+        buffer.delete_at(Position::new(0, 1));
+        assert_eq!(lines(&buffer), ["acd"]);
+
+        buffer.delete_at(Position::new(0, 3));
+        assert_eq!(lines(&buffer), ["acd"]);
+    }
+
+    #[test]
+    fn find_wraps_around() {
+        let buffer = TextBuffer::from_text("one two\nthree two\nfour\n");
+        let found = buffer.find("two", Position::new(0, 0));
+        assert_eq!(found, Some(Position::new(0, 4)));
+        let found = buffer.find("two", Position::new(0, 4));
+        assert_eq!(found, Some(Position::new(1, 6)));
+        let found = buffer.find("two", Position::new(1, 6));
+        assert_eq!(found, Some(Position::new(0, 4)));
+        let found = buffer.find("one", Position::new(0, 0));
+        assert_eq!(found, Some(Position::new(0, 0)));
+        assert_eq!(buffer.find("five", Position::new(1, 0)), None);
+        assert_eq!(buffer.find("", Position::new(1, 0)), None);
+    }
+
+    #[test]
+    fn text_round_trips() {
+        for text in ["", "a", "a\n", "a\nb", "a\r\nb\r\n", "a\r\n\r\n", "\n\n"] {
+            assert_eq!(TextBuffer::from_text(text).to_text(), text);
+        }
+        assert_eq!(lines(&TextBuffer::from_text("a\r\nb\r\n")), ["a", "b"]);
+    }
+
+    #[test]
+    fn new_lines_keep_the_line_ending() {
+        let mut buffer = TextBuffer::from_text("ab\r\n");
+        buffer.insert_newline(Position::new(0, 1));
+        assert_eq!(buffer.to_text(), "a\r\nb\r\n");
+    }
+}
@@ -0,0 +1,544 @@
+mod buffer;
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::keyboard::Key;
+use crate::os::storage::save_atomic;
+use crate::typing::KeyboardEvent;
+pub use buffer::{Position, TextBuffer};
+
+const HINTS: &str = "^S save ^W find ^X exit";
+
+/// What the app should do after a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorAction {
+    Continue,
+    Exit,
+}
+
+/// A question on the status line that takes the keys until it is answered.
+enum Prompt {
+    Search(String),
+    ConfirmExit,
+}
+
+/// A nano-style editor for one file.
+///
+/// Keys come in through `handle_event`, and `render` gives escape sequences
+/// that paint the text, the line numbers and a status line on an `FbTerminal`.
+pub struct Editor {
+    buffer: TextBuffer,
+    path: PathBuf,
+    cursor: Position,
+    /// The column the cursor goes back to when moving through shorter lines.
+    wanted_col: usize,
+    /// First line and column on screen.
+    top: usize,
+    left: usize,
+    /// Text rows on screen at the last `render`, for PageUp/PageDown.
+    page: usize,
+    modified: bool,
+    prompt: Option<Prompt>,
+    last_search: String,
+    /// Shown on the status line until the next key.
+    message: Option<String>,
+}
+
+impl Editor {
+    /// Opens `path`, or starts an empty buffer if it does not exist yet.
+    /// A file that is not UTF-8 is refused, since saving it would replace
+    /// every invalid byte.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let (buffer, message) = match fs::read(&path) {
+            Ok(bytes) => {
+                let text = String::from_utf8(bytes).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "not a UTF-8 text file")
+                })?;
+                (TextBuffer::from_text(&text), None)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                (TextBuffer::new(), Some("New file".to_string()))
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            buffer,
+            path,
+            cursor: Position::default(),
+            wanted_col: 0,
+            top: 0,
+            left: 0,
+            page: 1,
+            modified: false,
+            prompt: None,
+            last_search: String::new(),
+            message,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn buffer(&self) -> &TextBuffer {
+        &self.buffer
+    }
+
+    pub fn cursor(&self) -> Position {
+        self.cursor
+    }
+
+    /// There are changes that have not been saved.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        save_atomic(&self.path, self.buffer.to_text().as_bytes())?;
+        self.modified = false;
+        Ok(())
+    }
+
+    /// The question asked by `request_exit` is waiting for an answer.
+    pub fn is_confirming_exit(&self) -> bool {
+        matches!(self.prompt, Some(Prompt::ConfirmExit))
+    }
+
+    /// Exits right away if everything is saved, otherwise asks first.
+    pub fn request_exit(&mut self) -> EditorAction {
+        if self.modified {
+            self.prompt = Some(Prompt::ConfirmExit);
+            EditorAction::Continue
+        } else {
+            EditorAction::Exit
+        }
+    }
+
+    /// Arrows, Home/End and PageUp/PageDown move; Ctrl+A and Ctrl+E go to
+    /// the start and end of the line. Ctrl+S saves, Ctrl+W or Ctrl+F
+    /// searches, and Ctrl+X exits.
+    pub fn handle_event(&mut self, event: KeyboardEvent) -> EditorAction {
+        self.message = None;
+        if self.prompt.is_some() {
+            return self.handle_prompt_event(event);
+        }
+
+        let line_len = self.buffer.line_len(self.cursor.line);
+        match event {
+            KeyboardEvent::Ascii(c) => self.edit(|buffer, pos| buffer.insert_char(pos, c)),
+            KeyboardEvent::Tab => self.edit(|buffer, pos| buffer.insert_char(pos, '\t')),
+            KeyboardEvent::Enter => self.edit(|buffer, pos| buffer.insert_newline(pos)),
+            KeyboardEvent::Backspace => self.edit(|buffer, pos| buffer.delete_before(pos)),
+            KeyboardEvent::Delete => self.edit(|buffer, pos| {
+                buffer.delete_at(pos);
+                pos
+            }),
+            KeyboardEvent::ArrowLeft if self.cursor.col > 0 => {
+                self.move_to(Position::new(self.cursor.line, self.cursor.col - 1))
+            }
+            KeyboardEvent::ArrowLeft if self.cursor.line > 0 => {
+                let line = self.cursor.line - 1;
+                self.move_to(Position::new(line, self.buffer.line_len(line)))
+            }
+            KeyboardEvent::ArrowRight if self.cursor.col < line_len => {
+                self.move_to(Position::new(self.cursor.line, self.cursor.col + 1))
+            }
+            KeyboardEvent::ArrowRight if self.cursor.line + 1 < self.buffer.line_count() => {
+                self.move_to(Position::new(self.cursor.line + 1, 0))
+            }
+            KeyboardEvent::ArrowUp => self.move_lines(-1),
+            KeyboardEvent::ArrowDown => self.move_lines(1),
+            KeyboardEvent::PageUp => self.move_lines(-(self.page as isize)),
+            KeyboardEvent::PageDown => self.move_lines(self.page as isize),
+            KeyboardEvent::Home => self.move_to(Position::new(self.cursor.line, 0)),
+            KeyboardEvent::End => self.move_to(Position::new(self.cursor.line, line_len)),
+            KeyboardEvent::Chord {
+                ctrl: true,
+                alt: false,
+                opt: false,
+                key,
+            } => match key {
+                Key::A => self.move_to(Position::new(self.cursor.line, 0)),
+                Key::E => self.move_to(Position::new(self.cursor.line, line_len)),
+                Key::S => self.save_with_message(),
+                Key::W | Key::F => self.prompt = Some(Prompt::Search(self.last_search.clone())),
+                Key::X => return self.request_exit(),
+                _ => {}
+            },
+            _ => {}
+        }
+        EditorAction::Continue
+    }
+
+    fn handle_prompt_event(&mut self, event: KeyboardEvent) -> EditorAction {
+        match self.prompt.take() {
+            Some(Prompt::Search(mut query)) => match event {
+                KeyboardEvent::Ascii(c) => {
+                    query.push(c);
+                    self.prompt = Some(Prompt::Search(query));
+                }
+                KeyboardEvent::Backspace => {
+                    query.pop();
+                    self.prompt = Some(Prompt::Search(query));
+                }
+                KeyboardEvent::Enter => self.search(query),
+                KeyboardEvent::Esc | KeyboardEvent::CtrlC => {}
+                _ => self.prompt = Some(Prompt::Search(query)),
+            },
+            Some(Prompt::ConfirmExit) => match event {
+                KeyboardEvent::Ascii('y' | 'Y') => {
+                    self.save_with_message();
+                    if !self.modified {
+                        return EditorAction::Exit;
+                    }
+                }
+                KeyboardEvent::Ascii('n' | 'N') => return EditorAction::Exit,
+                _ => {}
+            },
+            None => {}
+        }
+        EditorAction::Continue
+    }
+
+    fn edit(&mut self, change: impl FnOnce(&mut TextBuffer, Position) -> Position) {
+        let cursor = change(&mut self.buffer, self.cursor);
+        self.modified = true;
+        self.move_to(cursor);
+    }
+
+    fn move_to(&mut self, pos: Position) {
+        self.cursor = self.buffer.clamp(pos);
+        self.wanted_col = self.cursor.col;
+    }
+
+    fn move_lines(&mut self, lines: isize) {
+        let last = self.buffer.line_count() - 1;
+        let line = self.cursor.line.saturating_add_signed(lines).min(last);
+        self.cursor = self.buffer.clamp(Position::new(line, self.wanted_col));
+    }
+
+    fn save_with_message(&mut self) {
+        self.message = Some(match self.save() {
+            Ok(()) => format!("Saved {} lines", self.buffer.line_count()),
+            Err(err) => format!("Save failed: {}", err),
+        });
+    }
+
+    fn search(&mut self, query: String) {
+        match self.buffer.find(&query, self.cursor) {
+            Some(pos) => self.move_to(pos),
+            None if !query.is_empty() => self.message = Some(format!("Not found: {}", query)),
+            None => {}
+        }
+        self.last_search = query;
+    }
+
+    /// Escape sequences that paint the visible part of the file over a
+    /// `cols` x `rows` screen, scrolling to keep the cursor in view.
+    pub fn render(&mut self, cols: usize, rows: usize) -> String {
+        let text_rows = rows.saturating_sub(1).max(1);
+        let gutter = self.buffer.line_count().to_string().len() + 1;
+        let text_cols = cols.saturating_sub(gutter).max(1);
+        self.page = text_rows;
+        self.scroll_to_cursor(text_cols, text_rows);
+
+        let mut out = String::new();
+        for row in 0..text_rows {
+            let line = self.top + row;
+            // Cleared before painting: erasing after a full row would also
+            // blank its last cell, as the cursor stays on it.
+            let _ = write!(out, "\x1b[{};1H\x1b[2K", row + 1);
+            if line < self.buffer.line_count() {
+                let _ = write!(out, "\x1b[90m{:>1$} \x1b[39m", line + 1, gutter - 1);
+                self.render_line(&mut out, line, text_cols);
+            }
+        }
+
+        let status = self.status_line(cols);
+        let _ = write!(out, "\x1b[{};1H\x1b[7m{:<2$}\x1b[0m", rows, status, cols);
+        out
+    }
+
+    fn render_line(&self, out: &mut String, line: usize, width: usize) {
+        let chars = self.buffer.line(line).chars().chain(std::iter::once(' '));
+        for (col, c) in chars.enumerate().skip(self.left).take(width) {
+            let c = if c.is_control() { ' ' } else { c };
+            if self.prompt.is_none() && Position::new(line, col) == self.cursor {
+                let _ = write!(out, "\x1b[7m{}\x1b[27m", c);
+            } else if col < self.buffer.line_len(line) {
+                out.push(c);
+            }
+        }
+    }
+
+    fn scroll_to_cursor(&mut self, cols: usize, rows: usize) {
+        if self.cursor.line < self.top {
+            self.top = self.cursor.line;
+        } else if self.cursor.line >= self.top + rows {
+            self.top = self.cursor.line + 1 - rows;
+        }
+        if self.cursor.col < self.left {
+            self.left = self.cursor.col;
+        } else if self.cursor.col >= self.left + cols {
+            self.left = self.cursor.col + 1 - cols;
+        }
+    }
+
+    fn status_line(&self, cols: usize) -> String {
+        let status = match (&self.prompt, &self.message) {
+            (Some(Prompt::Search(query)), _) => format!("Search: {}", query),
+            (Some(Prompt::ConfirmExit), _) => "Save changes? (y/n)".to_string(),
+            (None, Some(message)) => message.clone(),
+            (None, None) => {
+                let name = self.path.file_name().map_or_else(
+                    || self.path.display().to_string(),
+                    |name| name.to_string_lossy().to_string(),
+                );
+                let modified = if self.modified { "*" } else { "" };
+                let position = format!("{}:{}", self.cursor.line + 1, self.cursor.col + 1);
+                let left = format!("{}{} {}", name, modified, position);
+                // The hints go on the right when there is room for them.
+                let width = left.chars().count();
+                if width + 1 + HINTS.len() <= cols {
+                    format!("{}{:>2$}", left, HINTS, cols - width)
+                } else {
+                    left
+                }
+            }
+        };
+        status.chars().take(cols).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os::storage::temp_dir;
+    use crate::terminal::ansi::Screen;
+
+    #[test]
+    fn exit_asks_when_modified() {
+        let dir = temp_dir("editor-exit");
+        let mut editor = Editor::open(dir.join("new.txt")).unwrap();
+        assert_eq!(editor.request_exit(), EditorAction::Exit);
+
+        editor.handle_event(KeyboardEvent::Ascii('x'));
+        assert_eq!(editor.request_exit(), EditorAction::Continue);
+        assert!(editor.is_confirming_exit());
+        assert_eq!(
+            editor.handle_event(KeyboardEvent::Ascii('y')),
+            EditorAction::Exit
+        );
+        assert_eq!(fs::read(dir.join("new.txt")).unwrap(), b"x\n");
+    }
+
+    /// Opens a file holding `text` in a fresh directory.
+    fn open_text(name: &str, text: &str) -> Editor {
+        let path = temp_dir(&format!("editor-{}", name)).join("notes.txt");
+        fs::write(&path, text).unwrap();
+        Editor::open(path).unwrap()
+    }
+
+    fn numbered_lines(count: usize) -> String {
+        (1..=count).map(|i| format!("line {}\n", i)).collect()
+    }
+
+    fn ctrl(key: Key) -> KeyboardEvent {
+        KeyboardEvent::Chord {
+            ctrl: true,
+            alt: false,
+            opt: false,
+            key,
+        }
+    }
+
+    fn press(editor: &mut Editor, events: &[KeyboardEvent]) {
+        for &event in events {
+            editor.handle_event(event);
+        }
+    }
+
+    fn search(editor: &mut Editor, query: &str) {
+        editor.handle_event(ctrl(Key::W));
+        // The prompt starts with the last query.
+        for _ in 0..editor.last_search.chars().count() {
+            editor.handle_event(KeyboardEvent::Backspace);
+        }
+        for c in query.chars() {
+            editor.handle_event(KeyboardEvent::Ascii(c));
+        }
+        editor.handle_event(KeyboardEvent::Enter);
+    }
+
+    /// The screen after `render`, one string per row.
+    fn screen(editor: &mut Editor, cols: usize, rows: usize) -> Vec<String> {
+        let mut screen = Screen::new(cols, rows);
+        screen.write_str(&editor.render(cols, rows));
+        (0..rows).map(|row| screen.row_text(row)).collect()
+    }
+
+    #[test]
+    fn open_refuses_invalid_utf8() {
+        let path = temp_dir("editor-binary").join("image.bin");
+        fs::write(&path, b"ok \xff\xfe").unwrap();
+        let err = Editor::open(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), b"ok \xff\xfe");
+    }
+
+    #[test]
+    fn search_wraps_around() {
+        let mut editor = open_text("search", "foo\nbar foo\nbaz\n");
+        search(&mut editor, "foo");
+        assert_eq!(editor.cursor(), Position::new(1, 4));
+        search(&mut editor, "foo");
+        assert_eq!(editor.cursor(), Position::new(0, 0));
+
+        // Enter on the prompt repeats the last search.
+        press(&mut editor, &[ctrl(Key::F), KeyboardEvent::Enter]);
+        assert_eq!(editor.cursor(), Position::new(1, 4));
+    }
+
+    #[test]
+    fn search_reports_no_match() {
+        let mut editor = open_text("not-found", "foo\nbar\n");
+        press(&mut editor, &[KeyboardEvent::ArrowDown]);
+        search(&mut editor, "nope");
+        assert_eq!(editor.cursor(), Position::new(1, 0));
+        assert_eq!(editor.status_line(39), "Not found: nope");
+
+        // The message is gone with the next key.
+        editor.handle_event(KeyboardEvent::ArrowUp);
+        assert!(editor.status_line(39).starts_with("notes.txt 1:1"));
+    }
+
+    #[test]
+    fn up_and_down_keep_the_column() {
+        let mut editor = open_text("column", "long line\nab\n\nlonger line\n");
+        press(&mut editor, &[KeyboardEvent::End, KeyboardEvent::ArrowDown]);
+        assert_eq!(editor.cursor(), Position::new(1, 2));
+        editor.handle_event(KeyboardEvent::ArrowDown);
+        assert_eq!(editor.cursor(), Position::new(2, 0));
+        editor.handle_event(KeyboardEvent::ArrowDown);
+        assert_eq!(editor.cursor(), Position::new(3, 9));
+
+        // Moving sideways sets a new column.
+        press(
+            &mut editor,
+            &[
+                KeyboardEvent::ArrowLeft,
+                KeyboardEvent::ArrowUp,
+                KeyboardEvent::ArrowUp,
+                KeyboardEvent::ArrowUp,
+            ],
+        );
+        assert_eq!(editor.cursor(), Position::new(0, 8));
+    }
+
+    #[test]
+    fn page_keys_move_by_the_rendered_rows() {
+        let mut editor = open_text("page", &numbered_lines(30));
+        // One line at a time until the first render.
+        editor.handle_event(KeyboardEvent::PageDown);
+        assert_eq!(editor.cursor().line, 1);
+
+        editor.render(39, 12);
+        assert_eq!(editor.page, 11);
+        editor.handle_event(KeyboardEvent::PageDown);
+        assert_eq!(editor.cursor().line, 12);
+        editor.handle_event(KeyboardEvent::PageDown);
+        assert_eq!(editor.cursor().line, 23);
+        editor.handle_event(KeyboardEvent::PageDown);
+        assert_eq!(editor.cursor().line, 29);
+        editor.handle_event(KeyboardEvent::PageUp);
+        assert_eq!(editor.cursor().line, 18);
+    }
+
+    #[test]
+    fn render_scrolls_to_the_cursor() {
+        let mut editor = open_text("scroll", &numbered_lines(30));
+        for _ in 0..20 {
+            editor.handle_event(KeyboardEvent::ArrowDown);
+        }
+        let rows = screen(&mut editor, 39, 12);
+        assert_eq!(editor.top, 10);
+        assert_eq!(rows[0].trim_end(), "11 line 11");
+        assert_eq!(rows[10].trim_end(), "21 line 21");
+
+        for _ in 0..15 {
+            editor.handle_event(KeyboardEvent::ArrowUp);
+        }
+        let rows = screen(&mut editor, 39, 12);
+        assert_eq!(editor.top, 5);
+        assert_eq!(rows[0].trim_end(), " 6 line 6");
+
+        // Sideways, the cursor stays in the last text column.
+        let mut editor = open_text("scroll-wide", &format!("{}\n", "x".repeat(50)));
+        editor.handle_event(KeyboardEvent::End);
+        editor.render(39, 12);
+        assert_eq!(editor.left, 50 + 1 - (39 - 2));
+        editor.handle_event(KeyboardEvent::Home);
+        editor.render(39, 12);
+        assert_eq!(editor.left, 0);
+    }
+
+    #[test]
+    fn render_keeps_the_last_column() {
+        let line: String = ('a'..='z').cycle().take(50).collect();
+        let mut editor = open_text("full-width", &format!("{}\n", line));
+        let rows = screen(&mut editor, 39, 12);
+        assert_eq!(rows[0], format!("1 {}", &line[..37]));
+
+        // Scrolled sideways, the cursor is in the last column.
+        editor.handle_event(KeyboardEvent::End);
+        let mut screen = Screen::new(39, 12);
+        screen.write_str(&editor.render(39, 12));
+        assert_eq!(screen.row_text(0), format!("1 {}", &line[14..]));
+        assert!(screen.row(0)[38].style.inverse);
+    }
+
+    #[test]
+    fn gutter_fits_the_last_line_number() {
+        let mut editor = open_text("gutter", &numbered_lines(9));
+        assert_eq!(screen(&mut editor, 39, 12)[0].trim_end(), "1 line 1");
+
+        let mut editor = open_text("gutter-wide", &numbered_lines(10));
+        let rows = screen(&mut editor, 39, 12);
+        assert_eq!(rows[0].trim_end(), " 1 line 1");
+        assert_eq!(rows[9].trim_end(), "10 line 10");
+        assert_eq!(rows[10].trim_end(), "");
+    }
+
+    #[test]
+    fn status_line_layout() {
+        let mut editor = open_text("status", "one\n");
+        let rows = screen(&mut editor, 39, 12);
+        assert_eq!(rows[11], "notes.txt 1:1   ^S save ^W find ^X exit");
+
+        editor.handle_event(KeyboardEvent::Ascii('x'));
+        assert_eq!(
+            editor.status_line(39),
+            "notes.txt* 1:2  ^S save ^W find ^X exit"
+        );
+        // Without room for the hints only the name and position are left.
+        assert_eq!(editor.status_line(30), "notes.txt* 1:2");
+        assert_eq!(editor.status_line(6), "notes.");
+
+        editor.handle_event(ctrl(Key::W));
+        assert_eq!(editor.status_line(39), "Search: ");
+    }
+
+    #[test]
+    fn status_line_counts_characters() {
+        let path = temp_dir("editor-status-utf8").join("r\u{e9}sum\u{e9}.txt");
+        fs::write(&path, "one\n").unwrap();
+        let editor = Editor::open(path).unwrap();
+        let status = editor.status_line(39);
+        assert_eq!(status, "r\u{e9}sum\u{e9}.txt 1:1  ^S save ^W find ^X exit");
+        assert_eq!(status.chars().count(), 39);
+    }
+}
@@ -1,15 +1,29 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
 use display_interface_spi::SPIInterface;
 
 use esp_idf_hal::{
     delay::Ets,
-    gpio::{self, IOPin, InputPin, Output, OutputPin, PinDriver},
-    ledc::{self, LedcChannel, LedcTimer},
+    gpio::{self, AnyOutputPin, IOPin, InputPin, Output, OutputPin, PinDriver},
+    ledc::{self, config::TimerConfig, LedcChannel, LedcDriver, LedcTimer, LedcTimerDriver},
     peripheral::Peripheral,
     prelude::*,
     spi::{self, Dma, SpiAnyPins, SpiDeviceDriver, SpiDriver, SpiDriverConfig},
 };
+use log::warn;
+
+use crate::{
+    backlight::BacklightSettings,
+    display_driver,
+    fs::SdCard,
+    keyboard::{Activity, CardputerKeyboard},
+    os::storage::mount_sd_card,
+};
 
-use crate::{display_driver, keyboard::CardputerKeyboard};
+/// How often the idle timer looks at the keyboard.
+const IDLE_TICK_MS: u64 = 100;
 
 #[allow(clippy::too_many_arguments)]
 pub fn prepare_display<SPI: SpiAnyPins>(
@@ -20,16 +34,13 @@ pub fn prepare_display<SPI: SpiAnyPins>(
     cs: Option<impl Peripheral<P = impl OutputPin> + 'static>,
     rst: impl Peripheral<P = impl OutputPin> + 'static,
     dc: impl Peripheral<P = impl OutputPin> + 'static,
-    bl: impl Peripheral<P = impl OutputPin> + 'static,
-    _ledc_timer: impl Peripheral<P = impl LedcTimer> + 'static,
-    _ledc_channel: impl Peripheral<P = impl LedcChannel> + 'static,
 ) -> display_driver::ST7789<
     SPIInterface<
         SpiDeviceDriver<'static, SpiDriver<'static>>,
         PinDriver<'static, impl OutputPin, Output>,
     >,
     esp_idf_hal::gpio::PinDriver<'static, impl OutputPin, esp_idf_hal::gpio::Output>,
-    esp_idf_hal::gpio::PinDriver<'static, impl OutputPin, esp_idf_hal::gpio::Output>,
+    esp_idf_hal::gpio::PinDriver<'static, AnyOutputPin, esp_idf_hal::gpio::Output>,
 > {
     let config = esp_idf_hal::spi::config::Config::new()
         .baudrate(80.MHz().into())
@@ -50,26 +61,151 @@ pub fn prepare_display<SPI: SpiAnyPins>(
 
     let spi_interface = SPIInterface::new(device, pin_dc);
 
-    // let ledc_config = esp_idf_svc::hal::ledc::config::TimerConfig::new().frequency(25.kHz().into());
-    // let timer = LedcTimerDriver::new(ledc_timer, &ledc_config).unwrap();
+    let rst_pin = PinDriver::output(rst).unwrap();
 
-    // let backlight_pwm = LedcDriver::new(ledc_channel, timer, bl).unwrap();
-    // backlight_pwm.set_duty(backlight_pwm.get_max_duty()).unwrap();
+    // The backlight is driven with PWM instead, see `prepare_backlight`.
+    display_driver::ST7789::new(
+        spi_interface,
+        Some(rst_pin),
+        None::<PinDriver<'static, AnyOutputPin, Output>>,
+    )
+}
 
-    let rst_pin = PinDriver::output(rst).unwrap();
-    let bl_pin = PinDriver::output(bl).unwrap();
+/// Drives the backlight pin `bl` with a 25kHz PWM from the LEDC, starting
+/// at the default brightness.
+pub fn prepare_backlight<C, T>(
+    ledc_timer: impl Peripheral<P = T> + 'static,
+    ledc_channel: impl Peripheral<P = C> + 'static,
+    bl: impl Peripheral<P = impl OutputPin> + 'static,
+) -> Backlight
+where
+    C: LedcChannel<SpeedMode = <T as LedcTimer>::SpeedMode>,
+    T: LedcTimer + 'static,
+{
+    let ledc_config = TimerConfig::new().frequency(25.kHz().into());
+    // Dropping the timer driver stops the timer, so it lives as long as the app.
+    let timer: &'static LedcTimerDriver<'static, T> = Box::leak(Box::new(
+        LedcTimerDriver::new(ledc_timer, &ledc_config).unwrap(),
+    ));
 
-    display_driver::ST7789::new(spi_interface, Some(rst_pin), Some(bl_pin))
+    let pwm = LedcDriver::new(ledc_channel, timer, bl).unwrap();
+    Backlight::new(pwm)
 }
 
-pub struct CardputerPeripherals<P: OutputPin, Q: OutputPin, R: OutputPin> {
+struct BacklightState {
+    pwm: LedcDriver<'static>,
+    settings: BacklightSettings,
+    /// What the PWM is set to, in percent.
+    level: u8,
+}
+
+impl BacklightState {
+    fn apply(&mut self, percent: u8) {
+        let percent = percent.min(100);
+        let duty = self.pwm.get_max_duty() * percent as u32 / 100;
+        match self.pwm.set_duty(duty) {
+            Ok(()) => self.level = percent,
+            Err(err) => warn!("Failed to set the backlight to {}%: {}", percent, err),
+        }
+    }
+}
+
+/// The display backlight, 0 to 100%. Clones control the same backlight.
+#[derive(Clone)]
+pub struct Backlight {
+    state: Arc<Mutex<BacklightState>>,
+}
+
+impl Backlight {
+    fn new(pwm: LedcDriver<'static>) -> Self {
+        let settings = BacklightSettings::default();
+        let mut state = BacklightState {
+            pwm,
+            settings,
+            level: 0,
+        };
+        state.apply(settings.brightness);
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// The level right now in percent, lower than `brightness` while dimmed
+    /// and 0 while off.
+    pub fn level(&self) -> u8 {
+        self.state.lock().unwrap().level
+    }
+
+    /// The brightness while the keyboard is in use, in percent.
+    pub fn brightness(&self) -> u8 {
+        self.state.lock().unwrap().settings.brightness
+    }
+
+    /// Sets the brightness while the keyboard is in use and shows it at once.
+    pub fn set_brightness(&self, percent: u8) {
+        let mut state = self.state.lock().unwrap();
+        state.settings.brightness = percent.min(100);
+        let brightness = state.settings.brightness;
+        state.apply(brightness);
+    }
+
+    pub fn settings(&self) -> BacklightSettings {
+        self.state.lock().unwrap().settings
+    }
+
+    pub fn set_settings(&self, settings: BacklightSettings) {
+        let mut state = self.state.lock().unwrap();
+        state.settings = settings;
+        state.apply(settings.brightness);
+    }
+
+    /// Uses the settings saved on the SD card, or the defaults without one.
+    /// Call it once the card is mounted.
+    pub fn load_settings(&self) {
+        self.set_settings(BacklightSettings::load(&BacklightSettings::sd_card_path()));
+    }
+
+    pub fn save_settings(&self) -> io::Result<()> {
+        self.settings().save(&BacklightSettings::sd_card_path())
+    }
+
+    /// Sets the level the settings give after `idle` without a key.
+    pub fn update(&self, idle: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let level = state.settings.level(idle);
+        if level != state.level {
+            state.apply(level);
+        }
+    }
+
+    /// Dims the backlight and turns it off when no key was pressed for the
+    /// times in the settings, from a thread of its own. The next key turns
+    /// it back on; the app still gets that key.
+    pub fn start_idle_timer(&self, activity: Activity) {
+        let backlight = self.clone();
+        std::thread::Builder::new()
+            .name("backlight".to_string())
+            .stack_size(4096)
+            .spawn(move || loop {
+                backlight.update(activity.idle_time(Instant::now()));
+                std::thread::sleep(Duration::from_millis(IDLE_TICK_MS));
+            })
+            .unwrap();
+    }
+}
+
+pub struct CardputerPeripherals<P: OutputPin, Q: OutputPin> {
     pub display: display_driver::ST7789<
         SPIInterface<SpiDeviceDriver<'static, SpiDriver<'static>>, PinDriver<'static, P, Output>>,
         esp_idf_hal::gpio::PinDriver<'static, Q, esp_idf_hal::gpio::Output>,
-        esp_idf_hal::gpio::PinDriver<'static, R, esp_idf_hal::gpio::Output>,
+        esp_idf_hal::gpio::PinDriver<'static, AnyOutputPin, esp_idf_hal::gpio::Output>,
     >,
+    pub backlight: Backlight,
     pub keyboard: CardputerKeyboard<'static>,
     pub speaker: esp_idf_hal::i2s::I2sDriver<'static, esp_idf_hal::i2s::I2sTx>,
+    /// Mounted at `SD_ROOT`; `None` without a card. Keep it for as long as
+    /// the card is used.
+    pub sd_card: Option<SdCard>,
 }
 
 pub fn cardputer_peripherals<'a>(
@@ -77,7 +213,7 @@ pub fn cardputer_peripherals<'a>(
     spi2: spi::SPI2,
     ledc: ledc::LEDC,
     i2s: esp_idf_hal::i2s::I2S0,
-) -> CardputerPeripherals<impl OutputPin, impl OutputPin, impl OutputPin> {
+) -> CardputerPeripherals<impl OutputPin, impl OutputPin> {
     // display
 
     let mut display = prepare_display(
@@ -88,9 +224,6 @@ pub fn cardputer_peripherals<'a>(
         Some(pins.gpio37),
         pins.gpio33,
         pins.gpio34,
-        pins.gpio38,
-        ledc.timer0,
-        ledc.channel0,
     );
 
     let mut delay = Ets;
@@ -101,9 +234,11 @@ pub fn cardputer_peripherals<'a>(
         .set_orientation(display_driver::Orientation::Landscape)
         .unwrap();
     display
-        .set_address_window(0 + 40, 0 + 53, 240 - 1 + 40, 135 + 53)
+        .set_frame_window(0 + 40, 0 + 53, 240 - 1 + 40, 135 + 53)
         .unwrap();
 
+    let backlight = prepare_backlight(ledc.timer0, ledc.channel0, pins.gpio38);
+
     // keyboard
 
     let mux_pins: [PinDriver<'_, gpio::AnyOutputPin, Output>; 3] = [
@@ -146,10 +281,16 @@ pub fn cardputer_peripherals<'a>(
     )
     .unwrap();
 
+    // SD card
+
+    let sd_card = mount_sd_card();
+
     //(display, keyboard, speaker)
     CardputerPeripherals {
         display,
+        backlight,
         keyboard,
         speaker,
+        sd_card,
     }
 }
@@ -1,4 +1,4 @@
-use crate::keyboard::{CardputerKeyboard, Key};
+use crate::keyboard::{Key, KeyMatrix, Keyboard};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SystemAction {
@@ -24,7 +24,46 @@ pub fn action_from_keys(keys: &[Key]) -> Option<SystemAction> {
     }
 }
 
-pub fn poll_action(keyboard: &mut CardputerKeyboard<'_>) -> Option<SystemAction> {
+pub fn poll_action(keyboard: &mut Keyboard<impl KeyMatrix>) -> Option<SystemAction> {
     let keys = keyboard.read_keys();
     action_from_keys(&keys)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::ScriptedKeyMatrix;
+
+    /// `poll_action` once per scan of `frames`.
+    fn poll_all(frames: &[&[Key]]) -> Vec<Option<SystemAction>> {
+        let mut keyboard = Keyboard::with_matrix(ScriptedKeyMatrix::from_keys(frames));
+        frames.iter().map(|_| poll_action(&mut keyboard)).collect()
+    }
+
+    #[test]
+    fn ctrl_backspace_returns_to_os() {
+        assert_eq!(
+            poll_all(&[&[], &[Key::Ctrl], &[Key::Ctrl, Key::Backspace], &[]]),
+            [None, None, Some(SystemAction::ReturnToOs), None]
+        );
+        // Other keys held at the same time don't matter.
+        assert_eq!(
+            action_from_keys(&[Key::Shift, Key::Backspace, Key::A, Key::Ctrl]),
+            Some(SystemAction::ReturnToOs)
+        );
+    }
+
+    #[test]
+    fn other_chords_do_nothing() {
+        assert_eq!(
+            poll_all(&[
+                &[Key::Ctrl, Key::X],
+                &[Key::Backspace],
+                &[Key::Alt, Key::Backspace],
+                &[Key::Fn, Key::Backspace],
+            ]),
+            [None, None, None, None]
+        );
+        assert_eq!(action_from_keys(&[]), None);
+    }
+}
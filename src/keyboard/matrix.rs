@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+
+#[cfg(target_os = "espidf")]
+use esp_idf_hal::gpio::{AnyIOPin, AnyOutputPin, PinDriver};
+
+use super::{encode_keys, Key, KeyboardState};
+
+/// Something that can report which keys of the Cardputer matrix are down.
+pub trait KeyMatrix {
+    /// Scans every row and returns the raw state.
+    fn scan(&mut self) -> KeyboardState;
+}
+
+/// The real matrix: a 3-bit row mux and 7 column inputs with pull-ups.
+#[cfg(target_os = "espidf")]
+pub struct GpioKeyMatrix<'a> {
+    mux: [PinDriver<'a, AnyOutputPin, esp_idf_hal::gpio::Output>; 3],
+    columns: [PinDriver<'a, AnyIOPin, esp_idf_hal::gpio::Input>; 7],
+}
+
+#[cfg(target_os = "espidf")]
+impl<'a> GpioKeyMatrix<'a> {
+    pub fn new(
+        mux: [PinDriver<'a, AnyOutputPin, esp_idf_hal::gpio::Output>; 3],
+        columns: [PinDriver<'a, AnyIOPin, esp_idf_hal::gpio::Input>; 7],
+    ) -> Self {
+        Self { mux, columns }
+    }
+
+    pub fn init(&mut self) {
+        for pin in self.columns.iter_mut() {
+            pin.set_pull(esp_idf_hal::gpio::Pull::Up).unwrap();
+        }
+    }
+
+    pub fn read_columns(&self) -> u8 {
+        let mut result = 0;
+        for (i, column) in self.columns.iter().enumerate() {
+            if column.is_low() {
+                result |= 1 << i;
+            }
+        }
+        result
+    }
+
+    pub fn set_mux(&mut self, index: u8) {
+        for i in 0..3 {
+            if index & (1 << i) != 0 {
+                self.mux[i].set_high().unwrap();
+            } else {
+                self.mux[i].set_low().unwrap();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "espidf")]
+impl KeyMatrix for GpioKeyMatrix<'_> {
+    fn scan(&mut self) -> KeyboardState {
+        let mut result = [0; 8];
+        for i in 0..8 {
+            self.set_mux(i);
+            result[i as usize] = self.read_columns();
+        }
+        result
+    }
+}
+
+/// An in-memory matrix that replays recorded snapshots, one per scan.
+///
+/// When the script runs out, the last snapshot stays down.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedKeyMatrix {
+    frames: VecDeque<KeyboardState>,
+    current: KeyboardState,
+}
+
+impl ScriptedKeyMatrix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// One scan per entry, each listing the keys that are down.
+    pub fn from_keys(frames: &[&[Key]]) -> Self {
+        let mut matrix = Self::new();
+        for keys in frames {
+            matrix.push_keys(keys);
+        }
+        matrix
+    }
+
+    pub fn push(&mut self, state: KeyboardState) {
+        self.frames.push_back(state);
+    }
+
+    pub fn push_keys(&mut self, keys: &[Key]) {
+        self.push(encode_keys(keys));
+    }
+
+    /// Presses `key` for one scan and releases it on the next.
+    pub fn tap(&mut self, key: Key) {
+        self.push_keys(&[key]);
+        self.push_keys(&[]);
+    }
+
+    /// Types `keys` one after another, releasing each before the next.
+    pub fn tap_all(&mut self, keys: &[Key]) {
+        for &key in keys {
+            self.tap(key);
+        }
+    }
+
+    /// True once every recorded snapshot has been scanned.
+    pub fn is_finished(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl KeyMatrix for ScriptedKeyMatrix {
+    fn scan(&mut self) -> KeyboardState {
+        if let Some(next) = self.frames.pop_front() {
+            self.current = next;
+        }
+        self.current
+    }
+}
@@ -0,0 +1,770 @@
+pub mod matrix;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "espidf")]
+use esp_idf_hal::gpio::{AnyIOPin, AnyOutputPin, PinDriver};
+
+use crate::clock::{Clock, SystemClock};
+#[cfg(target_os = "espidf")]
+pub use matrix::GpioKeyMatrix;
+pub use matrix::{KeyMatrix, ScriptedKeyMatrix};
+
+/// One byte per mux row, one bit per column (bit set = key down).
+pub type KeyboardState = [u8; 8];
+
+/// The most events the queue holds. Once it is full, each new event drops
+/// the oldest one.
+const MAX_QUEUED_EVENTS: usize = 64;
+
+/// The keyboard on the Cardputer's GPIO matrix.
+#[cfg(target_os = "espidf")]
+pub type CardputerKeyboard<'a> = Keyboard<GpioKeyMatrix<'a>>;
+
+/// Key decoding on top of any `KeyMatrix`.
+pub struct Keyboard<M> {
+    matrix: M,
+    events: KeyEventQueue,
+    clock: Box<dyn Clock + Send>,
+    activity: Activity,
+}
+
+#[cfg(target_os = "espidf")]
+impl<'a> Keyboard<GpioKeyMatrix<'a>> {
+    pub fn new(
+        mux: [PinDriver<'a, AnyOutputPin, esp_idf_hal::gpio::Output>; 3],
+        columns: [PinDriver<'a, AnyIOPin, esp_idf_hal::gpio::Input>; 7],
+    ) -> Self {
+        Self::with_matrix(GpioKeyMatrix::new(mux, columns))
+    }
+
+    pub fn init(&mut self) {
+        self.matrix.init();
+    }
+}
+
+impl<M: KeyMatrix> Keyboard<M> {
+    pub fn with_matrix(matrix: M) -> Self {
+        Self {
+            matrix,
+            events: KeyEventQueue::new(),
+            clock: Box::new(SystemClock),
+            activity: Activity::new(SystemClock.now()),
+        }
+    }
+
+    pub fn matrix_mut(&mut self) -> &mut M {
+        &mut self.matrix
+    }
+
+    pub fn set_timing(&mut self, timing: KeyTiming) {
+        self.events.set_timing(timing);
+    }
+
+    pub fn timing(&self) -> KeyTiming {
+        self.events.timing()
+    }
+
+    pub fn set_clock(&mut self, clock: impl Clock + Send + 'static) {
+        self.activity.touch(clock.now());
+        self.clock = Box::new(clock);
+    }
+
+    /// When a key was last seen down, for dimming the backlight.
+    pub fn activity(&self) -> Activity {
+        self.activity.clone()
+    }
+
+    /// How long no key has been down.
+    pub fn idle_time(&self) -> Duration {
+        self.activity.idle_time(self.clock.now())
+    }
+
+    /// Reads the raw state of the keyboard.
+    pub fn read_keys_raw(&mut self) -> KeyboardState {
+        let keys = self.matrix.scan();
+        if keys.iter().any(|&row| row != 0) {
+            self.activity.touch(self.clock.now());
+        }
+        keys
+    }
+
+    /// Reads the state of the keyboard and returns a list of pressed keys.
+    pub fn read_keys(&mut self) -> Vec<Key> {
+        decode_keys(&self.read_keys_raw())
+    }
+
+    /// Returns the derivative of the (debounced) keyboard state since the last call.
+    /// The changes are queued as well, so `read_events` still sees them.
+    pub fn read_events_raw(&mut self) -> KeyboardState {
+        let keys = self.read_keys_raw();
+        let now = self.clock.now();
+        self.events.update(keys, now)
+    }
+
+    /// Scans the matrix and queues every change since the previous scan,
+    /// plus any repeat or long-press that became due.
+    pub fn poll(&mut self) {
+        self.read_events_raw();
+    }
+
+    /// Returns the oldest queued event.
+    pub fn read_events(&mut self) -> Option<(KeyEvent, Key)> {
+        self.read_timed_event()
+            .map(|event| (event.event, event.key))
+    }
+
+    /// Same as `read_events`, but keeps the time at which the change was seen.
+    pub fn read_timed_event(&mut self) -> Option<TimedKeyEvent> {
+        self.poll();
+        self.events.pop()
+    }
+
+    /// Returns every queued event, oldest first.
+    pub fn drain_events(&mut self) -> Vec<TimedKeyEvent> {
+        self.poll();
+        self.events.drain().collect()
+    }
+}
+
+/// The last time a key was down. Clones share it, so another thread can
+/// follow the keyboard.
+#[derive(Debug, Clone)]
+pub struct Activity {
+    last: Arc<Mutex<Instant>>,
+}
+
+impl Activity {
+    fn new(now: Instant) -> Self {
+        Self {
+            last: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    fn touch(&self, now: Instant) {
+        *self.last.lock().unwrap() = now;
+    }
+
+    pub fn last(&self) -> Instant {
+        *self.last.lock().unwrap()
+    }
+
+    pub fn idle_time(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimedKeyEvent {
+    pub event: KeyEvent,
+    pub key: Key,
+    pub time: Instant,
+}
+
+/// Timing parameters for debouncing and typematic repeat.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyTiming {
+    /// After a key changes state, further changes of that key are ignored for this long.
+    pub debounce: Duration,
+    /// Hold time before the first `Repeat`, or `None` to disable repeat.
+    pub repeat_delay: Option<Duration>,
+    /// Time between two `Repeat` events once repeating.
+    pub repeat_interval: Duration,
+    /// Hold time after which one `LongPress` is sent, or `None` to disable it.
+    pub long_press: Option<Duration>,
+}
+
+impl KeyTiming {
+    /// Raw edges only: no debouncing, no repeat and no long press.
+    pub const RAW: Self = Self {
+        debounce: Duration::ZERO,
+        repeat_delay: None,
+        repeat_interval: Duration::ZERO,
+        long_press: None,
+    };
+}
+
+impl Default for KeyTiming {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(5),
+            repeat_delay: Some(Duration::from_millis(450)),
+            repeat_interval: Duration::from_millis(60),
+            long_press: Some(Duration::from_millis(800)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct HeldKey {
+    key: Key,
+    since: Instant,
+    next_repeat: Option<Instant>,
+    long_press_sent: bool,
+}
+
+/// Turns successive matrix snapshots into an ordered queue of key events,
+/// applying debouncing, typematic repeat and long-press detection.
+///
+/// This holds no hardware state and takes the time as an argument,
+/// so it can be fed synthetic snapshots.
+#[derive(Debug)]
+pub struct KeyEventQueue {
+    state: KeyboardState,
+    queue: VecDeque<TimedKeyEvent>,
+    timing: KeyTiming,
+    last_change: Vec<Option<Instant>>,
+    held: Vec<HeldKey>,
+}
+
+impl KeyEventQueue {
+    pub fn new() -> Self {
+        Self::with_timing(KeyTiming::default())
+    }
+
+    pub fn with_timing(timing: KeyTiming) -> Self {
+        Self {
+            state: [0; 8],
+            queue: VecDeque::new(),
+            timing,
+            last_change: vec![None; KEY_MAP.len()],
+            held: Vec::new(),
+        }
+    }
+
+    pub fn set_timing(&mut self, timing: KeyTiming) {
+        self.timing = timing;
+    }
+
+    pub fn timing(&self) -> KeyTiming {
+        self.timing
+    }
+
+    /// Diffs `snapshot` against the debounced state and queues the accepted
+    /// changes, then queues any repeat or long-press that is due at `time`.
+    /// Returns the bits that changed.
+    pub fn update(&mut self, snapshot: KeyboardState, time: Instant) -> KeyboardState {
+        let mut accepted = self.state;
+        for i in 0..8 {
+            for j in 0..7 {
+                let bit = 1 << j;
+                if (snapshot[i] ^ self.state[i]) & bit == 0 {
+                    continue;
+                }
+                let settled = match self.last_change[i * 7 + j] {
+                    Some(at) => time.saturating_duration_since(at) >= self.timing.debounce,
+                    None => true,
+                };
+                if settled {
+                    accepted[i] ^= bit;
+                    self.last_change[i * 7 + j] = Some(time);
+                }
+            }
+        }
+
+        let mut changed = [0; 8];
+        for i in 0..8 {
+            changed[i] = accepted[i] ^ self.state[i];
+        }
+
+        for (event, key) in decode_changes(&self.state, &accepted) {
+            match event {
+                KeyEvent::Pressed => self.hold(key, time),
+                _ => self.held.retain(|held| held.key != key),
+            }
+            self.push(event, key, time);
+        }
+        self.state = accepted;
+
+        self.queue_timed_events(time);
+
+        changed
+    }
+
+    fn hold(&mut self, key: Key, time: Instant) {
+        let mut next_repeat = None;
+        if !key.is_modifier() {
+            // Only the most recently pressed key repeats.
+            for held in self.held.iter_mut() {
+                held.next_repeat = None;
+            }
+            next_repeat = self.timing.repeat_delay.map(|delay| time + delay);
+        }
+        self.held.push(HeldKey {
+            key,
+            since: time,
+            next_repeat,
+            long_press_sent: false,
+        });
+    }
+
+    fn queue_timed_events(&mut self, time: Instant) {
+        let mut due = Vec::new();
+        for held in self.held.iter_mut() {
+            if let Some(long_press) = self.timing.long_press {
+                if !held.long_press_sent && time.saturating_duration_since(held.since) >= long_press
+                {
+                    held.long_press_sent = true;
+                    due.push((KeyEvent::LongPress, held.key));
+                }
+            }
+            if let Some(next) = held.next_repeat {
+                if time >= next {
+                    held.next_repeat = Some(time + self.timing.repeat_interval);
+                    due.push((KeyEvent::Repeat, held.key));
+                }
+            }
+        }
+        for (event, key) in due {
+            self.push(event, key, time);
+        }
+    }
+
+    fn push(&mut self, event: KeyEvent, key: Key, time: Instant) {
+        if self.queue.len() == MAX_QUEUED_EVENTS {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(TimedKeyEvent { event, key, time });
+    }
+
+    pub fn pop(&mut self) -> Option<TimedKeyEvent> {
+        self.queue.pop_front()
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = TimedKeyEvent> + '_ {
+        self.queue.drain(..)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// The debounced matrix state.
+    pub fn state(&self) -> KeyboardState {
+        self.state
+    }
+}
+
+impl Default for KeyEventQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the keys that are down in `state`, in matrix order.
+pub fn decode_keys(state: &KeyboardState) -> Vec<Key> {
+    let mut result = Vec::new();
+    for (i, byte) in state.iter().enumerate() {
+        for j in 0..7 {
+            if byte & (1 << j) != 0 {
+                result.push(KEY_MAP[i * 7 + j]);
+            }
+        }
+    }
+    result
+}
+
+/// Builds the snapshot in which exactly `keys` are down.
+pub fn encode_keys(keys: &[Key]) -> KeyboardState {
+    let mut result = [0; 8];
+    for key in keys {
+        if let Some(index) = KEY_MAP.iter().position(|k| k == key) {
+            result[index / 7] |= 1 << (index % 7);
+        }
+    }
+    result
+}
+
+/// Returns the events that lead from `previous` to `current`.
+///
+/// A single scan cannot tell which change happened first, so releases are
+/// listed before presses: when typing fast, the old key usually comes up in
+/// the same scan in which the next one goes down. Modifiers go down before
+/// and come up after the other keys, so a chord caught in one scan still
+/// reaches `Typing` with its modifiers held.
+pub fn decode_changes(previous: &KeyboardState, current: &KeyboardState) -> Vec<(KeyEvent, Key)> {
+    let mut changes = Vec::new();
+    for i in 0..8 {
+        let changed = previous[i] ^ current[i];
+        for j in 0..7 {
+            if changed & (1 << j) != 0 {
+                let event = if current[i] & (1 << j) != 0 {
+                    KeyEvent::Pressed
+                } else {
+                    KeyEvent::Released
+                };
+                changes.push((event, KEY_MAP[i * 7 + j]));
+            }
+        }
+    }
+    changes.sort_by_key(|&(event, key)| match (event, key.is_modifier()) {
+        (KeyEvent::Released, false) => 0,
+        (KeyEvent::Released, true) => 1,
+        (_, true) => 2,
+        (_, false) => 3,
+    });
+    changes
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Space, // register 0 msb
+    Period,
+    M,
+    B,
+    C,
+    Z,
+    Opt,
+
+    Enter, // register 1 msb
+    Semicolon,
+    K,
+    H,
+    F,
+    S,
+    Shift,
+
+    BackSlash,
+    LeftSquareBracket,
+    O,
+    U,
+    T,
+    E,
+    Q,
+
+    Backspace,
+    Underscore,
+    _9,
+    _7,
+    _5,
+    _3,
+    _1,
+
+    Slash, //register 5 msb
+    Comma,
+    N,
+    V,
+    X,
+    Alt,
+    Ctrl,
+
+    Quote,
+    L,
+    J,
+    G,
+    D,
+    A,
+    Fn,
+
+    RightSquareBracket,
+    P,
+    I,
+    Y,
+    R,
+    W,
+    Tab,
+
+    Equal, // register 7 msb
+    _0,
+    _8,
+    _6,
+    _4,
+    _2,
+    Tilde,
+}
+
+impl Key {
+    /// Every key of the matrix.
+    pub fn all() -> &'static [Key] {
+        &KEY_MAP
+    }
+
+    pub fn is_modifier(self) -> bool {
+        matches!(self, Key::Shift | Key::Ctrl | Key::Alt | Key::Opt | Key::Fn)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyEvent {
+    Pressed,
+    Released,
+    /// The key is still held after the repeat delay; sent again every repeat interval.
+    Repeat,
+    /// The key has been held for the long-press time; sent once per press.
+    LongPress,
+}
+
+const KEY_MAP: [Key; 56] = [
+    Key::Opt,
+    Key::Z,
+    Key::C,
+    Key::B,
+    Key::M,
+    Key::Period,
+    Key::Space,
+    Key::Shift,
+    Key::S,
+    Key::F,
+    Key::H,
+    Key::K,
+    Key::Semicolon,
+    Key::Enter,
+    Key::Q,
+    Key::E,
+    Key::T,
+    Key::U,
+    Key::O,
+    Key::LeftSquareBracket,
+    Key::BackSlash,
+    Key::_1,
+    Key::_3,
+    Key::_5,
+    Key::_7,
+    Key::_9,
+    Key::Underscore,
+    Key::Backspace,
+    Key::Ctrl,
+    Key::Alt,
+    Key::X,
+    Key::V,
+    Key::N,
+    Key::Comma,
+    Key::Slash,
+    Key::Fn,
+    Key::A,
+    Key::D,
+    Key::G,
+    Key::J,
+    Key::L,
+    Key::Quote,
+    Key::Tab,
+    Key::W,
+    Key::R,
+    Key::Y,
+    Key::I,
+    Key::P,
+    Key::RightSquareBracket,
+    Key::Tilde,
+    Key::_2,
+    Key::_4,
+    Key::_6,
+    Key::_8,
+    Key::_0,
+    Key::Equal,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn keyboard(frames: &[&[Key]]) -> (Keyboard<ScriptedKeyMatrix>, ManualClock) {
+        let clock = ManualClock::new();
+        let mut keyboard = Keyboard::with_matrix(ScriptedKeyMatrix::from_keys(frames));
+        keyboard.set_clock(clock.clone());
+        keyboard.set_timing(KeyTiming::RAW);
+        (keyboard, clock)
+    }
+
+    fn events(events: Vec<TimedKeyEvent>) -> Vec<(KeyEvent, Key)> {
+        events.into_iter().map(|e| (e.event, e.key)).collect()
+    }
+
+    #[test]
+    fn one_scan_queues_every_change() {
+        let (mut keyboard, _) = keyboard(&[&[Key::A, Key::B], &[Key::B, Key::C]]);
+        keyboard.poll();
+        keyboard.poll();
+        assert_eq!(
+            events(keyboard.drain_events()),
+            [
+                (KeyEvent::Pressed, Key::B),
+                (KeyEvent::Pressed, Key::A),
+                (KeyEvent::Released, Key::A),
+                (KeyEvent::Pressed, Key::C),
+            ]
+        );
+        assert_eq!(keyboard.read_events(), None);
+    }
+
+    #[test]
+    fn events_keep_their_scan_time() {
+        let (mut keyboard, clock) = keyboard(&[&[Key::A], &[]]);
+        let start = clock.now();
+        keyboard.poll();
+        clock.advance(Duration::from_millis(30));
+        keyboard.poll();
+
+        let pressed = keyboard.read_timed_event().unwrap();
+        assert_eq!((pressed.event, pressed.time), (KeyEvent::Pressed, start));
+        let released = keyboard.read_timed_event().unwrap();
+        assert_eq!(released.event, KeyEvent::Released);
+        assert_eq!(released.time - start, Duration::from_millis(30));
+    }
+
+    #[test]
+    fn full_queue_drops_the_oldest() {
+        let mut queue = KeyEventQueue::with_timing(KeyTiming::RAW);
+        let time = Instant::now();
+        let taps = MAX_QUEUED_EVENTS / 2 + 3;
+        for i in 0..taps {
+            let key = if i == 0 { Key::A } else { Key::B };
+            queue.update(encode_keys(&[key]), time);
+            queue.update(encode_keys(&[]), time);
+        }
+
+        assert_eq!(queue.len(), MAX_QUEUED_EVENTS);
+        let events: Vec<_> = queue.drain().map(|e| (e.event, e.key)).collect();
+        assert!(!events.contains(&(KeyEvent::Pressed, Key::A)));
+        assert_eq!(events[0], (KeyEvent::Pressed, Key::B));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn modifiers_wrap_other_keys() {
+        let changes = decode_changes(
+            &encode_keys(&[]),
+            &encode_keys(&[Key::A, Key::Ctrl, Key::Shift]),
+        );
+        assert_eq!(changes.len(), 3);
+        assert!(changes[..2]
+            .iter()
+            .all(|(event, key)| *event == KeyEvent::Pressed && key.is_modifier()));
+        assert_eq!(changes[2], (KeyEvent::Pressed, Key::A));
+
+        let changes = decode_changes(&encode_keys(&[Key::Ctrl, Key::A]), &encode_keys(&[Key::B]));
+        assert_eq!(
+            changes,
+            [
+                (KeyEvent::Released, Key::A),
+                (KeyEvent::Released, Key::Ctrl),
+                (KeyEvent::Pressed, Key::B),
+            ]
+        );
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn timing() -> KeyTiming {
+        KeyTiming {
+            debounce: ms(5),
+            repeat_delay: Some(ms(400)),
+            repeat_interval: ms(50),
+            long_press: Some(ms(800)),
+        }
+    }
+
+    /// Holds `keys` from `from` to `to` ms, scanning every 10 ms, and
+    /// returns the events with their time in ms.
+    fn hold(
+        keyboard: &mut Keyboard<ScriptedKeyMatrix>,
+        clock: &ManualClock,
+        start: Instant,
+        keys: &[Key],
+        from: u64,
+        to: u64,
+    ) -> Vec<(u64, KeyEvent, Key)> {
+        for _ in (from..to).step_by(10) {
+            keyboard.matrix_mut().push_keys(keys);
+        }
+        let mut seen = Vec::new();
+        while clock.now() - start < ms(to) {
+            keyboard.poll();
+            clock.advance(ms(10));
+        }
+        // Not `drain_events`, which would scan once more.
+        for event in keyboard.events.drain() {
+            let at = (event.time - start).as_millis() as u64;
+            seen.push((at, event.event, event.key));
+        }
+        seen
+    }
+
+    #[test]
+    fn bounces_are_ignored() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mut queue = KeyEventQueue::with_timing(timing());
+        let down = encode_keys(&[Key::A]);
+        let up = encode_keys(&[]);
+
+        queue.update(down, start);
+        queue.update(up, start + ms(2));
+        queue.update(down, start + ms(4));
+        queue.update(up, start + ms(20));
+        queue.update(down, start + ms(22));
+        let events: Vec<_> = queue
+            .drain()
+            .map(|e| (e.event, (e.time - start).as_millis()))
+            .collect();
+        assert_eq!(events, [(KeyEvent::Pressed, 0), (KeyEvent::Released, 20)]);
+        assert_eq!(queue.state(), up);
+    }
+
+    #[test]
+    fn held_key_repeats_and_long_presses() {
+        let (mut keyboard, clock) = keyboard(&[]);
+        keyboard.set_timing(timing());
+        let start = clock.now();
+
+        let events = hold(&mut keyboard, &clock, start, &[Key::A], 0, 900);
+        let repeats: Vec<u64> = events
+            .iter()
+            .filter(|(_, event, _)| *event == KeyEvent::Repeat)
+            .map(|(at, _, _)| *at)
+            .collect();
+        assert_eq!(events[0], (0, KeyEvent::Pressed, Key::A));
+        assert_eq!(repeats, [400, 450, 500, 550, 600, 650, 700, 750, 800, 850]);
+        let long_presses: Vec<_> = events
+            .iter()
+            .filter(|(_, event, _)| *event == KeyEvent::LongPress)
+            .collect();
+        assert_eq!(long_presses, [&(800, KeyEvent::LongPress, Key::A)]);
+
+        let events = hold(&mut keyboard, &clock, start, &[], 900, 1000);
+        assert_eq!(events, [(900, KeyEvent::Released, Key::A)]);
+    }
+
+    #[test]
+    fn only_the_last_key_repeats_and_modifiers_never_do() {
+        let (mut keyboard, clock) = keyboard(&[]);
+        keyboard.set_timing(KeyTiming {
+            long_press: None,
+            ..timing()
+        });
+        let start = clock.now();
+
+        hold(&mut keyboard, &clock, start, &[Key::Shift, Key::A], 0, 200);
+        let events = hold(
+            &mut keyboard,
+            &clock,
+            start,
+            &[Key::Shift, Key::A, Key::B],
+            200,
+            660,
+        );
+        let repeated: Vec<Key> = events
+            .iter()
+            .filter(|(_, event, _)| *event == KeyEvent::Repeat)
+            .map(|(_, _, key)| *key)
+            .collect();
+        assert_eq!(events[0], (200, KeyEvent::Pressed, Key::B));
+        assert_eq!(repeated, [Key::B, Key::B]);
+    }
+
+    #[test]
+    fn raw_timing_sends_edges_only() {
+        let (mut keyboard, clock) = keyboard(&[]);
+        let start = clock.now();
+        let events = hold(&mut keyboard, &clock, start, &[Key::A], 0, 2000);
+        assert_eq!(events, [(0, KeyEvent::Pressed, Key::A)]);
+    }
+}
@@ -0,0 +1,542 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use log::{error, info};
+use serde::Deserialize;
+
+use crate::keyboard::Key;
+use crate::os::storage::list_files_with_extension;
+
+/// The extension of layout files in `SD_LAYOUTS_PATH`.
+pub const LAYOUT_EXTENSION: &str = "kbl";
+
+/// Which character table a key press is looked up in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Layer {
+    Base,
+    Shift,
+    Fn,
+    Opt,
+}
+
+/// The characters a single physical key produces on each layer.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct KeyChars {
+    pub base: Option<char>,
+    pub shift: Option<char>,
+    pub fn_: Option<char>,
+    pub opt: Option<char>,
+}
+
+impl KeyChars {
+    pub fn get(&self, layer: Layer) -> Option<char> {
+        match layer {
+            Layer::Base => self.base,
+            Layer::Shift => self.shift,
+            Layer::Fn => self.fn_,
+            Layer::Opt => self.opt,
+        }
+    }
+
+    fn set(&mut self, layer: Layer, c: Option<char>) {
+        match layer {
+            Layer::Base => self.base = c,
+            Layer::Shift => self.shift = c,
+            Layer::Fn => self.fn_ = c,
+            Layer::Opt => self.opt = c,
+        }
+    }
+}
+
+/// Maps physical keys to characters.
+#[derive(Debug, Clone)]
+pub struct KeyboardLayout {
+    name: String,
+    keys: HashMap<Key, KeyChars>,
+}
+
+impl KeyboardLayout {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            keys: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Everything `key` produces; keys without characters return all `None`.
+    pub fn chars(&self, key: Key) -> KeyChars {
+        self.keys.get(&key).copied().unwrap_or_default()
+    }
+
+    pub fn char_for(&self, key: Key, layer: Layer) -> Option<char> {
+        self.chars(key).get(layer)
+    }
+
+    pub fn set(&mut self, key: Key, chars: KeyChars) {
+        self.keys.insert(key, chars);
+    }
+
+    pub fn set_char(&mut self, key: Key, layer: Layer, c: Option<char>) {
+        self.keys.entry(key).or_default().set(layer, c);
+    }
+
+    /// US QWERTY, matching the legends printed on the keys.
+    pub fn us() -> Self {
+        builtin(&US)
+    }
+
+    pub fn qwertz() -> Self {
+        builtin(&QWERTZ)
+    }
+
+    pub fn azerty() -> Self {
+        builtin(&AZERTY)
+    }
+
+    pub fn dvorak() -> Self {
+        builtin(&DVORAK)
+    }
+}
+
+impl Default for KeyboardLayout {
+    fn default() -> Self {
+        Self::us()
+    }
+}
+
+/// The keys that produce characters, in the order of the layer strings below.
+const PRINTABLE_KEYS: [Key; 48] = [
+    Key::Tilde,
+    Key::_1,
+    Key::_2,
+    Key::_3,
+    Key::_4,
+    Key::_5,
+    Key::_6,
+    Key::_7,
+    Key::_8,
+    Key::_9,
+    Key::_0,
+    Key::Underscore,
+    Key::Equal,
+    Key::Q,
+    Key::W,
+    Key::E,
+    Key::R,
+    Key::T,
+    Key::Y,
+    Key::U,
+    Key::I,
+    Key::O,
+    Key::P,
+    Key::LeftSquareBracket,
+    Key::RightSquareBracket,
+    Key::BackSlash,
+    Key::A,
+    Key::S,
+    Key::D,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::Semicolon,
+    Key::Quote,
+    Key::Z,
+    Key::X,
+    Key::C,
+    Key::V,
+    Key::B,
+    Key::N,
+    Key::M,
+    Key::Comma,
+    Key::Period,
+    Key::Slash,
+    Key::Space,
+];
+
+struct BuiltinLayout {
+    name: &'static str,
+    /// One character per entry of `PRINTABLE_KEYS`.
+    base: &'static str,
+    shift: &'static str,
+    fn_: &'static [(Key, char)],
+    opt: &'static [(Key, char)],
+}
+
+const US: BuiltinLayout = BuiltinLayout {
+    name: "us",
+    base: "`1234567890-=qwertyuiop[]\\asdfghjkl;'zxcvbnm,./ ",
+    shift: "~!@#$%^&*()_+QWERTYUIOP{}|ASDFGHJKL:\"ZXCVBNM<>? ",
+    fn_: &[],
+    opt: &[],
+};
+
+/// German. The ISO key left of Y is missing, so `<`, `>` and `|` sit on Opt+Z/X/C.
+const QWERTZ: BuiltinLayout = BuiltinLayout {
+    name: "de",
+    base: "^1234567890ß´qwertzuiopü+#asdfghjklöäyxcvbnm,.- ",
+    shift: "°!\"§$%&/()=?`QWERTZUIOPÜ*'ASDFGHJKLÖÄYXCVBNM;:_ ",
+    fn_: &[],
+    opt: &[
+        (Key::_2, '²'),
+        (Key::_3, '³'),
+        (Key::_7, '{'),
+        (Key::_8, '['),
+        (Key::_9, ']'),
+        (Key::_0, '}'),
+        (Key::Underscore, '\\'),
+        (Key::Q, '@'),
+        (Key::E, '€'),
+        (Key::RightSquareBracket, '~'),
+        (Key::M, 'µ'),
+        (Key::Z, '<'),
+        (Key::X, '>'),
+        (Key::C, '|'),
+    ],
+};
+
+/// French. `<` and `>` sit on Opt+Z/X for the same reason as in `QWERTZ`.
+const AZERTY: BuiltinLayout = BuiltinLayout {
+    name: "fr",
+    base: "²&é\"'(-è_çà)=azertyuiop^$*qsdfghjklmùwxcvbn,;:! ",
+    shift: "³1234567890°+AZERTYUIOP¨£µQSDFGHJKLM%WXCVBN?./§ ",
+    fn_: &[],
+    opt: &[
+        (Key::_2, '~'),
+        (Key::_3, '#'),
+        (Key::_4, '{'),
+        (Key::_5, '['),
+        (Key::_6, '|'),
+        (Key::_7, '`'),
+        (Key::_8, '\\'),
+        (Key::_9, '^'),
+        (Key::_0, '@'),
+        (Key::Underscore, ']'),
+        (Key::Equal, '}'),
+        (Key::E, '€'),
+        (Key::RightSquareBracket, '¤'),
+        (Key::Z, '<'),
+        (Key::X, '>'),
+    ],
+};
+
+const DVORAK: BuiltinLayout = BuiltinLayout {
+    name: "dvorak",
+    base: "`1234567890[]',.pyfgcrl/=\\aoeuidhtns-;qjkxbmwvz ",
+    shift: "~!@#$%^&*(){}\"<>PYFGCRL?+|AOEUIDHTNS_:QJKXBMWVZ ",
+    fn_: &[],
+    opt: &[],
+};
+
+const BUILTIN_LAYOUTS: [&BuiltinLayout; 4] = [&US, &QWERTZ, &AZERTY, &DVORAK];
+
+fn builtin(def: &BuiltinLayout) -> KeyboardLayout {
+    assert!(
+        def.base.chars().count() == PRINTABLE_KEYS.len()
+            && def.shift.chars().count() == PRINTABLE_KEYS.len()
+    );
+
+    let mut layout = KeyboardLayout::new(def.name);
+    for ((&key, base), shift) in PRINTABLE_KEYS
+        .iter()
+        .zip(def.base.chars())
+        .zip(def.shift.chars())
+    {
+        layout.set(
+            key,
+            KeyChars {
+                base: Some(base),
+                shift: Some(shift),
+                ..Default::default()
+            },
+        );
+    }
+    for &(key, c) in def.fn_ {
+        layout.set_char(key, Layer::Fn, Some(c));
+    }
+    for &(key, c) in def.opt {
+        layout.set_char(key, Layer::Opt, Some(c));
+    }
+    layout
+}
+
+/// The layouts compiled into the firmware.
+pub fn builtin_layouts() -> Vec<KeyboardLayout> {
+    BUILTIN_LAYOUTS.iter().map(|def| builtin(def)).collect()
+}
+
+/// Finds a layout by name, ignoring case.
+pub fn find<'a>(layouts: &'a [KeyboardLayout], name: &str) -> Option<&'a KeyboardLayout> {
+    layouts
+        .iter()
+        .find(|layout| layout.name.eq_ignore_ascii_case(name))
+}
+
+/// The unshifted character printed on `key` (its US QWERTY character).
+pub fn legend(key: Key) -> Option<char> {
+    let index = PRINTABLE_KEYS.iter().position(|&k| k == key)?;
+    US.base.chars().nth(index)
+}
+
+/// Looks a key up by its variant name (`Semicolon`, `_1`, `Q`) or by the
+/// character printed on it (`;`, `1`, `q`).
+pub fn parse_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if let Some(&key) = PRINTABLE_KEYS
+            .iter()
+            .find(|&&key| legend(key) == Some(c.to_ascii_lowercase()))
+        {
+            return Some(key);
+        }
+    }
+
+    Key::all()
+        .iter()
+        .copied()
+        .find(|key| format!("{:?}", key).eq_ignore_ascii_case(name))
+}
+
+#[derive(Deserialize, Debug)]
+struct LayoutFile {
+    name: String,
+    /// Built-in layout to start from; keys not listed keep its characters.
+    #[serde(default)]
+    extends: Option<String>,
+    keys: HashMap<String, KeyFileEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KeyFileEntry {
+    base: Option<String>,
+    shift: Option<String>,
+    #[serde(rename = "fn")]
+    fn_: Option<String>,
+    opt: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum LayoutError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnknownKey(String),
+    UnknownBase(String),
+    /// A layer entry was not exactly one character.
+    NotAChar {
+        key: String,
+        value: String,
+    },
+    /// A layout without `extends` has to give all the keys that type characters.
+    WrongKeyCount {
+        expected: usize,
+        found: usize,
+    },
+    /// Two entries in `keys` are the same key, e.g. `"q"` and `"Q"`.
+    DuplicateKey(String),
+    /// A layout with this name is already loaded.
+    DuplicateName(String),
+}
+
+/// Parses a JSON layout, e.g.
+///
+/// ```json
+/// { "name": "my-de", "extends": "de", "keys": { "Q": { "base": "q", "opt": "@" } } }
+/// ```
+///
+/// Without `extends`, all 48 keys that type characters have to be listed.
+pub fn parse_layout(json: &str) -> Result<KeyboardLayout, LayoutError> {
+    let file: LayoutFile = serde_json::from_str(json).map_err(LayoutError::Json)?;
+
+    let mut layout = match &file.extends {
+        Some(base) => {
+            let mut layout = find(&builtin_layouts(), base)
+                .cloned()
+                .ok_or_else(|| LayoutError::UnknownBase(base.clone()))?;
+            layout.name = file.name.clone();
+            layout
+        }
+        None => KeyboardLayout::new(&file.name),
+    };
+
+    let mut seen = HashSet::new();
+    for (name, entry) in &file.keys {
+        let key = parse_key(name).ok_or_else(|| LayoutError::UnknownKey(name.clone()))?;
+        if !seen.insert(key) {
+            return Err(LayoutError::DuplicateKey(name.clone()));
+        }
+        for (layer, value) in [
+            (Layer::Base, &entry.base),
+            (Layer::Shift, &entry.shift),
+            (Layer::Fn, &entry.fn_),
+            (Layer::Opt, &entry.opt),
+        ] {
+            if let Some(value) = value {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => layout.set_char(key, layer, Some(c)),
+                    _ => {
+                        return Err(LayoutError::NotAChar {
+                            key: name.clone(),
+                            value: value.clone(),
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    let found = PRINTABLE_KEYS
+        .iter()
+        .filter(|key| seen.contains(key))
+        .count();
+    if file.extends.is_none() && found != PRINTABLE_KEYS.len() {
+        return Err(LayoutError::WrongKeyCount {
+            expected: PRINTABLE_KEYS.len(),
+            found,
+        });
+    }
+
+    Ok(layout)
+}
+
+pub fn load_layout_file(path: &Path) -> Result<KeyboardLayout, LayoutError> {
+    let json = std::fs::read_to_string(path).map_err(LayoutError::Io)?;
+    parse_layout(&json)
+}
+
+/// The built-in layouts followed by every `LAYOUT_EXTENSION` file in `dir`.
+/// Files that fail to parse or reuse a name are logged and skipped.
+pub fn load_layouts(dir: &str) -> Vec<KeyboardLayout> {
+    let mut layouts = builtin_layouts();
+    for entry in list_files_with_extension(dir, LAYOUT_EXTENSION) {
+        let loaded = load_layout_file(Path::new(&entry.path)).and_then(|layout| {
+            if find(&layouts, &layout.name).is_some() {
+                return Err(LayoutError::DuplicateName(layout.name));
+            }
+            Ok(layout)
+        });
+        match loaded {
+            Ok(layout) => {
+                info!("Loaded keyboard layout {} from {}", layout.name, entry.path);
+                layouts.push(layout);
+            }
+            Err(err) => error!("Failed to load layout {}: {:?}", entry.path, err),
+        }
+    }
+    layouts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os::storage::temp_dir;
+
+    /// A layout file giving every key the characters it has in `def`.
+    fn full_layout_json(name: &str, def: &BuiltinLayout) -> serde_json::Value {
+        let keys: serde_json::Map<String, serde_json::Value> = PRINTABLE_KEYS
+            .iter()
+            .zip(def.base.chars().zip(def.shift.chars()))
+            .map(|(key, (base, shift))| {
+                let entry =
+                    serde_json::json!({ "base": base.to_string(), "shift": shift.to_string() });
+                (format!("{:?}", key), entry)
+            })
+            .collect();
+        serde_json::json!({ "name": name, "keys": keys })
+    }
+
+    #[test]
+    fn parse_full_layout() {
+        let json = full_layout_json("my-dvorak", &DVORAK).to_string();
+        let layout = parse_layout(&json).unwrap();
+        assert_eq!(layout.name(), "my-dvorak");
+        for key in PRINTABLE_KEYS {
+            assert_eq!(
+                layout.chars(key),
+                KeyboardLayout::dvorak().chars(key),
+                "{:?}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn full_layout_needs_every_key() {
+        let mut json = full_layout_json("short", &US);
+        json["keys"].as_object_mut().unwrap().remove("Q");
+        assert!(matches!(
+            parse_layout(&json.to_string()),
+            Err(LayoutError::WrongKeyCount {
+                expected: 48,
+                found: 47
+            })
+        ));
+    }
+
+    #[test]
+    fn extended_layout_changes_some_keys() {
+        let json = r#"{ "name": "my-de", "extends": "DE", "keys": {
+            "q": { "opt": "@" }, "Semicolon": { "fn": "~" } } }"#;
+        let layout = parse_layout(json).unwrap();
+        assert_eq!(layout.char_for(Key::Q, Layer::Opt), Some('@'));
+        assert_eq!(layout.char_for(Key::Q, Layer::Base), Some('q'));
+        assert_eq!(layout.char_for(Key::Semicolon, Layer::Base), Some('ö'));
+        assert_eq!(layout.char_for(Key::Semicolon, Layer::Fn), Some('~'));
+        assert_eq!(layout.char_for(Key::Y, Layer::Base), Some('z'));
+    }
+
+    #[test]
+    fn bad_layouts() {
+        let parse = |keys: &str, extends: &str| {
+            parse_layout(&format!(
+                r#"{{ "name": "x", "extends": "{}", "keys": {{ {} }} }}"#,
+                extends, keys
+            ))
+        };
+        assert!(matches!(
+            parse(r#""q": { "base": "a" }, "Q": { "base": "b" }"#, "us"),
+            Err(LayoutError::DuplicateKey(_))
+        ));
+        assert!(matches!(
+            parse(r#""Enterprise": {}"#, "us"),
+            Err(LayoutError::UnknownKey(key)) if key == "Enterprise"
+        ));
+        assert!(matches!(
+            parse(r#""q": { "base": "ab" }"#, "us"),
+            Err(LayoutError::NotAChar { .. })
+        ));
+        assert!(matches!(
+            parse("", "colemak"),
+            Err(LayoutError::UnknownBase(_))
+        ));
+        assert!(matches!(parse_layout("{"), Err(LayoutError::Json(_))));
+    }
+
+    #[test]
+    fn load_skips_duplicate_names() {
+        let dir = temp_dir("layouts");
+        let extends = |name: &str, base: &str| {
+            format!(
+                r#"{{ "name": "{}", "extends": "{}", "keys": {{}} }}"#,
+                name, base
+            )
+        };
+        std::fs::write(dir.join("a.kbl"), extends("mine", "de")).unwrap();
+        std::fs::write(dir.join("b.kbl"), extends("Mine", "fr")).unwrap();
+        std::fs::write(dir.join("c.kbl"), extends("us", "dvorak")).unwrap();
+        std::fs::write(dir.join("d.kbl"), "not json").unwrap();
+        std::fs::write(dir.join("e.json"), extends("other", "de")).unwrap();
+
+        let layouts = load_layouts(dir.to_str().unwrap());
+        let names: Vec<&str> = layouts.iter().map(KeyboardLayout::name).collect();
+        assert_eq!(names, ["us", "de", "fr", "dvorak", "mine"]);
+        let mine = find(&layouts, "MINE").unwrap();
+        assert_eq!(mine.char_for(Key::Y, Layer::Base), Some('z'));
+    }
+}
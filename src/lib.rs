@@ -1,14 +1,29 @@
+pub mod backlight;
+pub mod clock;
+pub mod dirty;
 pub mod display_driver;
+pub mod editor;
+#[cfg(target_os = "espidf")]
 pub mod fs;
+#[cfg(target_os = "espidf")]
 pub mod hal;
 pub mod hotkeys;
 pub mod keyboard;
+pub mod layout;
+#[cfg(target_os = "espidf")]
 pub mod loader;
 pub mod os;
+pub mod rink;
+pub mod rpn;
+#[cfg(target_os = "espidf")]
 pub mod runtime;
+pub mod shell;
+#[cfg(feature = "sim")]
+pub mod sim;
 pub mod swapchain;
 pub mod terminal;
 pub mod typing;
+pub mod vt_keys;
 
 pub const SCREEN_WIDTH: usize = 240;
 pub const SCREEN_HEIGHT: usize = 135;
@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use esp_idf_svc::sys;
+
+use crate::hal::Backlight;
+use crate::runtime;
+use crate::swapchain::{DoubleBuffer, OwnedDoubleBuffer};
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use super::app::AppContext;
+use super::chainload::{self, ota_partition_available};
+use super::launcher::{Launcher, LauncherHost};
+use super::status::{BatteryGauge, StatusProvider, StatusSnapshot};
+use super::storage::{SD_APPS_PATH, SD_ROOT};
+use super::ui::render_status;
+use super::web::start_wifi_file_server;
+
+const UI_TICK_MS: u64 = 16;
+
+/// The launcher on the Cardputer: flashes apps into the OTA slot.
+struct Device {
+    status: StatusProvider,
+    backlight: Backlight,
+    /// Whether there is an SD card to keep settings on.
+    sd_ready: bool,
+}
+
+impl LauncherHost for Device {
+    fn status(&self) -> StatusSnapshot {
+        self.status.snapshot()
+    }
+
+    fn launch(
+        &mut self,
+        buffers: &mut DoubleBuffer<SCREEN_WIDTH, SCREEN_HEIGHT>,
+        path: &Path,
+    ) -> Result<(), Vec<String>> {
+        chainload::flash_and_reboot(buffers, path).map_err(|err| err.to_lines())
+    }
+
+    fn change_brightness(&mut self, steps: i32) -> Result<(), Vec<String>> {
+        let mut settings = self.backlight.settings();
+        settings.step_brightness(steps);
+        self.backlight.set_settings(settings);
+        if self.sd_ready {
+            self.backlight
+                .save_settings()
+                .map_err(|err| vec![format!("Failed to save: {}", err)])?;
+        }
+        Ok(())
+    }
+}
+
+/// Boot entry point for Cardputer-RustOS.
+pub fn boot() -> ! {
+    runtime::init();
+    unsafe {
+        let partition = sys::esp_ota_get_running_partition();
+        if !partition.is_null() && (*partition).type_ == sys::esp_partition_type_t_ESP_PARTITION_TYPE_APP && (*partition).subtype == sys::esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_APP_FACTORY {
+            sys::esp_ota_mark_app_valid_cancel_rollback();
+        }
+    }
+
+    let (cardputer, modem) = runtime::take_cardputer();
+
+    let crate::hal::CardputerPeripherals {
+        display,
+        backlight,
+        mut keyboard,
+        speaker: _,
+        sd_card,
+    } = cardputer;
+
+    let mut buffers = OwnedDoubleBuffer::<SCREEN_WIDTH, SCREEN_HEIGHT>::new();
+    buffers.start_thread(display);
+
+    render_status(
+        &mut buffers,
+        "Cardputer RustOS",
+        &["Starting Wi-Fi..."],
+        None,
+    );
+
+    let sd_ready = sd_card.is_some();
+    let ota_ready = ota_partition_available();
+
+    let wifi_state = start_wifi_file_server(modem, if sd_ready {
+        Some(PathBuf::from(SD_ROOT))
+    } else {
+        None
+    });
+    let mut device = Device {
+        status: StatusProvider::new(wifi_state, BatteryGauge::new()),
+        backlight,
+        sd_ready,
+    };
+
+    let root = PathBuf::from(SD_ROOT);
+    let start = if Path::new(SD_APPS_PATH).is_dir() {
+        PathBuf::from(SD_APPS_PATH)
+    } else {
+        root.clone()
+    };
+
+    let mut launcher = Launcher::new(root, start, AppContext::new(sd_ready, ota_ready));
+    launcher.refresh();
+
+    loop {
+        launcher.step(&mut buffers, &mut keyboard, &mut device);
+        if let Some(err) = buffers.take_error() {
+            log::error!("Failed to present frame: {:?}", err);
+        }
+        std::thread::sleep(Duration::from_millis(UI_TICK_MS));
+    }
+}
@@ -0,0 +1,141 @@
+use std::path::{Path, PathBuf};
+
+use crate::keyboard::{KeyEvent, KeyMatrix, Keyboard};
+use crate::swapchain::DoubleBuffer;
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use super::app::{AppContext, AppLaunch};
+use super::menu::{self, MenuAction, MenuItem, MenuState};
+use super::status::StatusSnapshot;
+use super::ui::{render_menu, render_status};
+
+/// What the launcher needs from the system it runs on.
+pub trait LauncherHost {
+    fn status(&self) -> StatusSnapshot;
+
+    /// Flashes the app at `path` and boots it. On the device this only
+    /// returns if it failed, with the lines to show.
+    fn launch(
+        &mut self,
+        buffers: &mut DoubleBuffer<SCREEN_WIDTH, SCREEN_HEIGHT>,
+        path: &Path,
+    ) -> Result<(), Vec<String>>;
+
+    /// Changes the backlight brightness by `steps` and keeps the new
+    /// setting. Fails with the lines to show.
+    fn change_brightness(&mut self, steps: i32) -> Result<(), Vec<String>>;
+}
+
+/// The app menu of the OS loader, one frame and one key action at a time.
+pub struct Launcher {
+    menu: MenuState,
+    context: AppContext,
+    /// Shown instead of the menu until a key is pressed: a title and its lines.
+    message: Option<(String, Vec<String>)>,
+}
+
+impl Launcher {
+    /// Browses the apps under `root`, starting in `start`.
+    pub fn new(root: PathBuf, start: PathBuf, context: AppContext) -> Self {
+        Self {
+            menu: MenuState::new(root, start),
+            context,
+            message: None,
+        }
+    }
+
+    pub fn menu(&self) -> &MenuState {
+        &self.menu
+    }
+
+    pub fn context(&self) -> &AppContext {
+        &self.context
+    }
+
+    /// The title of the message waiting for a key, if any.
+    pub fn message_title(&self) -> Option<&str> {
+        self.message.as_ref().map(|(title, _)| title.as_str())
+    }
+
+    pub fn show_message(&mut self, title: &str, lines: Vec<String>) {
+        self.message = Some((title.to_string(), lines));
+    }
+
+    /// Reads the current directory again, if there is an SD card.
+    pub fn refresh(&mut self) {
+        if self.context.sd_ready {
+            if let Err(err) = self.menu.refresh() {
+                self.show_message("SD Error", vec![format!("Failed to read: {}", err)]);
+            }
+        }
+    }
+
+    /// Draws the menu, or the pending message, and handles the next key action.
+    pub fn step(
+        &mut self,
+        buffers: &mut DoubleBuffer<SCREEN_WIDTH, SCREEN_HEIGHT>,
+        keyboard: &mut Keyboard<impl KeyMatrix>,
+        host: &mut impl LauncherHost,
+    ) {
+        if let Some((title, lines)) = &self.message {
+            render_status(buffers, title, lines, None);
+            while let Some((event, _)) = keyboard.read_events() {
+                if matches!(event, KeyEvent::Pressed) {
+                    self.message = None;
+                    break;
+                }
+            }
+            return;
+        }
+
+        render_menu(buffers, &self.menu, &self.context, &host.status());
+
+        if let Some(action) = menu::read_menu_action(keyboard) {
+            self.handle_action(action, buffers, host);
+        }
+    }
+
+    fn go_back(&mut self) {
+        if self.menu.go_back() {
+            self.refresh();
+        }
+    }
+
+    fn change_brightness(&mut self, host: &mut impl LauncherHost, steps: i32) {
+        if let Err(lines) = host.change_brightness(steps) {
+            self.show_message("Settings Error", lines);
+        }
+    }
+
+    fn handle_action(
+        &mut self,
+        action: MenuAction,
+        buffers: &mut DoubleBuffer<SCREEN_WIDTH, SCREEN_HEIGHT>,
+        host: &mut impl LauncherHost,
+    ) {
+        match action {
+            MenuAction::Up => self.menu.move_up(),
+            MenuAction::Down => self.menu.move_down(),
+            MenuAction::Refresh => self.refresh(),
+            MenuAction::Back => self.go_back(),
+            MenuAction::Brighter => self.change_brightness(host, 1),
+            MenuAction::Dimmer => self.change_brightness(host, -1),
+            MenuAction::Select => match self.menu.selected_item().cloned() {
+                Some(MenuItem::Back) => self.go_back(),
+                Some(MenuItem::Dir(path)) => {
+                    self.menu.enter_dir(path);
+                    self.refresh();
+                }
+                Some(MenuItem::App(path)) => {
+                    let launch = AppLaunch::from_path(path);
+                    if let Err(err) = self.context.validate_launch(&launch) {
+                        self.show_message("Launch Error", err.to_lines());
+                    } else if let Err(lines) = host.launch(buffers, &launch.path) {
+                        self.show_message("Flash Error", lines);
+                    }
+                }
+                None => {}
+            },
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use crate::keyboard::{CardputerKeyboard, Key, KeyEvent};
+use crate::keyboard::{Key, KeyEvent, KeyMatrix, Keyboard};
 
 #[derive(Clone, Debug)]
 pub enum MenuItem {
@@ -82,26 +82,33 @@ impl MenuState {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MenuAction {
     Up,
     Down,
     Select,
     Back,
     Refresh,
+    Brighter,
+    Dimmer,
 }
 
-pub fn read_menu_action(keyboard: &mut CardputerKeyboard<'static>) -> Option<MenuAction> {
-    if let Some((event, key)) = keyboard.read_events() {
-        if matches!(event, KeyEvent::Pressed) {
-            return match key {
-                Key::Semicolon | Key::W => Some(MenuAction::Up),
-                Key::Period | Key::S => Some(MenuAction::Down),
-                Key::Enter => Some(MenuAction::Select),
-                Key::Backspace | Key::Slash => Some(MenuAction::Back),
-                Key::Tab | Key::Fn => Some(MenuAction::Refresh),
-                _ => None,
-            };
+/// Returns the first action among the queued key events.
+/// Events after it stay queued for the next call.
+pub fn read_menu_action(keyboard: &mut Keyboard<impl KeyMatrix>) -> Option<MenuAction> {
+    while let Some((event, key)) = keyboard.read_events() {
+        let action = match (event, key) {
+            (KeyEvent::Pressed | KeyEvent::Repeat, Key::Semicolon | Key::W) => Some(MenuAction::Up),
+            (KeyEvent::Pressed | KeyEvent::Repeat, Key::Period | Key::S) => Some(MenuAction::Down),
+            (KeyEvent::Pressed, Key::Enter) => Some(MenuAction::Select),
+            (KeyEvent::Pressed, Key::Backspace | Key::Slash) => Some(MenuAction::Back),
+            (KeyEvent::Pressed, Key::Tab | Key::Fn) => Some(MenuAction::Refresh),
+            (KeyEvent::Pressed, Key::Equal) => Some(MenuAction::Brighter),
+            (KeyEvent::Pressed, Key::Underscore) => Some(MenuAction::Dimmer),
+            _ => None,
+        };
+        if action.is_some() {
+            return action;
         }
     }
     None
@@ -183,3 +190,95 @@ pub fn menu_path_display(menu: &MenuState) -> String {
         format!("/{}", rel.to_string_lossy())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::keyboard::{KeyTiming, ScriptedKeyMatrix};
+
+    fn keyboard(matrix: ScriptedKeyMatrix) -> (Keyboard<ScriptedKeyMatrix>, ManualClock) {
+        let clock = ManualClock::new();
+        let mut keyboard = Keyboard::with_matrix(matrix);
+        keyboard.set_clock(clock.clone());
+        (keyboard, clock)
+    }
+
+    /// Scans once per call, like the launcher's loop.
+    fn actions(keyboard: &mut Keyboard<ScriptedKeyMatrix>, clock: &ManualClock) -> Vec<MenuAction> {
+        let mut actions = Vec::new();
+        while !keyboard.matrix_mut().is_finished() {
+            actions.extend(read_menu_action(keyboard));
+            clock.advance(Duration::from_millis(10));
+        }
+        actions.extend(read_menu_action(keyboard));
+        actions
+    }
+
+    #[test]
+    fn taps_map_to_actions() {
+        let mut matrix = ScriptedKeyMatrix::new();
+        matrix.tap_all(&[
+            Key::Period,
+            Key::S,
+            Key::Semicolon,
+            Key::W,
+            Key::Q,
+            Key::Enter,
+            Key::Backspace,
+            Key::Slash,
+            Key::Tab,
+            Key::Fn,
+            Key::Equal,
+            Key::Underscore,
+        ]);
+        let (mut keyboard, clock) = keyboard(matrix);
+        assert_eq!(
+            actions(&mut keyboard, &clock),
+            [
+                MenuAction::Down,
+                MenuAction::Down,
+                MenuAction::Up,
+                MenuAction::Up,
+                MenuAction::Select,
+                MenuAction::Back,
+                MenuAction::Back,
+                MenuAction::Refresh,
+                MenuAction::Refresh,
+                MenuAction::Brighter,
+                MenuAction::Dimmer,
+            ]
+        );
+    }
+
+    #[test]
+    fn later_events_stay_queued() {
+        let matrix = ScriptedKeyMatrix::from_keys(&[&[Key::Period, Key::Slash], &[]]);
+        let (mut keyboard, _) = keyboard(matrix);
+        keyboard.set_timing(KeyTiming::RAW);
+        assert_eq!(read_menu_action(&mut keyboard), Some(MenuAction::Down));
+        assert_eq!(read_menu_action(&mut keyboard), Some(MenuAction::Back));
+        assert_eq!(read_menu_action(&mut keyboard), None);
+    }
+
+    #[test]
+    fn arrows_repeat_but_select_does_not() {
+        let mut matrix = ScriptedKeyMatrix::new();
+        for _ in 0..60 {
+            matrix.push_keys(&[Key::Semicolon]);
+        }
+        for _ in 0..60 {
+            matrix.push_keys(&[Key::Enter]);
+        }
+        matrix.push_keys(&[]);
+        let (mut keyboard, clock) = keyboard(matrix);
+        let actions = actions(&mut keyboard, &clock);
+
+        // Pressed, then repeats from 450 ms every 60 ms while held for 600 ms.
+        let ups = actions.iter().filter(|&&a| a == MenuAction::Up).count();
+        assert_eq!(ups, 4);
+        assert_eq!(actions[ups..], [MenuAction::Select]);
+    }
+}
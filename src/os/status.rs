@@ -1,7 +1,10 @@
+#[cfg(target_os = "espidf")]
 use std::time::{Duration, Instant};
 
+#[cfg(target_os = "espidf")]
 use esp_idf_svc::systime::EspSystemTime;
 
+#[cfg(target_os = "espidf")]
 use super::web::{WifiMode, WifiStateHandle};
 
 #[derive(Clone, Debug, Default)]
@@ -11,12 +14,14 @@ pub struct StatusSnapshot {
     pub battery_text: String,
 }
 
+#[cfg(target_os = "espidf")]
 pub struct StatusProvider {
     wifi: WifiStateHandle,
     battery: BatteryGauge,
     started_at: Instant,
 }
 
+#[cfg(target_os = "espidf")]
 impl StatusProvider {
     pub fn new(wifi: WifiStateHandle, battery: BatteryGauge) -> Self {
         Self {
@@ -69,6 +74,7 @@ impl StatusProvider {
     }
 }
 
+#[cfg(target_os = "espidf")]
 fn format_hms(duration: Duration) -> String {
     let total_seconds = duration.as_secs() % 86_400;
     let hours = total_seconds / 3600;
@@ -1,18 +1,41 @@
-use std::fs::read_dir;
+#[cfg(target_os = "espidf")]
+use std::ffi::CString;
+use std::fs::{self, read_dir};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
 
+#[cfg(target_os = "espidf")]
 use esp_idf_svc::sys;
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
 
+#[cfg(target_os = "espidf")]
 use crate::fs::SdCard;
 
+// The SD card's FAT driver only sees 8.3 names: a longer name comes back cut
+// short, e.g. `.units` as `.UNI`. Files kept in these directories therefore
+// have names of up to eight characters and extensions of up to three.
 pub const SD_ROOT: &str = "/sdcard";
 pub const SD_APPS_PATH: &str = "/sdcard/apps";
 pub const SD_MODELS_PATH: &str = "/sdcard/3d";
+pub const SD_LAYOUTS_PATH: &str = "/sdcard/layouts";
+pub const SD_HISTORY_PATH: &str = "/sdcard/history";
+pub const SD_RINK_PATH: &str = "/sdcard/rink";
+pub const SD_SETTINGS_PATH: &str = "/sdcard/settings";
+
+/// Sizes in bytes, as `df` shows them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub total: u64,
+    pub free: u64,
+}
 
 pub struct SdFileEntry {
     pub name: String,
     pub path: String,
 }
 
+#[cfg(target_os = "espidf")]
 pub fn mount_sd_card() -> Option<SdCard> {
     SdCard::new(
         SD_ROOT,
@@ -25,6 +48,21 @@ pub fn mount_sd_card() -> Option<SdCard> {
     .ok()
 }
 
+#[cfg(target_os = "espidf")]
+pub fn sd_card_usage() -> io::Result<DiskUsage> {
+    let base_path = CString::new(SD_ROOT).unwrap();
+    let mut total = 0u64;
+    let mut free = 0u64;
+    let err = unsafe { sys::esp_vfs_fat_info(base_path.as_ptr(), &mut total, &mut free) };
+    if err != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("esp_vfs_fat_info failed: {}", err),
+        ));
+    }
+    Ok(DiskUsage { total, free })
+}
+
 pub fn list_files_with_extension(dir: &str, extension: &str) -> Vec<SdFileEntry> {
     let mut entries = Vec::new();
     let Ok(dir_iter) = read_dir(dir) else {
@@ -34,7 +72,7 @@ pub fn list_files_with_extension(dir: &str, extension: &str) -> Vec<SdFileEntry>
     let ext_str = extension.trim_start_matches('.');
     for entry in dir_iter.flatten() {
         let path = entry.path();
-        if path.extension().and_then(|ext| ext.to_str()).map_or(false, |ext| {
+        if path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| {
             ext.eq_ignore_ascii_case(ext_str)
         }) {
             let name = path
@@ -49,6 +87,142 @@ pub fn list_files_with_extension(dir: &str, extension: &str) -> Vec<SdFileEntry>
         }
     }
 
-    entries.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    entries.sort_by_key(|entry| entry.name.to_lowercase());
     entries
 }
+
+/// The settings file `name` in `SD_SETTINGS_PATH`.
+pub fn settings_path(name: &str) -> PathBuf {
+    Path::new(SD_SETTINGS_PATH).join(name)
+}
+
+/// The JSON settings in `path`, or the defaults if it is missing or broken.
+pub fn load_settings<T: DeserializeOwned + Default>(path: &Path) -> T {
+    let Ok(json) = fs::read_to_string(path) else {
+        return T::default();
+    };
+    serde_json::from_str(&json).unwrap_or_else(|err| {
+        warn!("Ignoring {}: {}", path.display(), err);
+        T::default()
+    })
+}
+
+/// Saves `settings` to `path` as JSON, creating its directory if needed.
+pub fn save_settings<T: Serialize>(path: &Path, settings: &T) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    save_atomic(path, json.as_bytes())
+}
+
+/// A new, empty directory for a test, named after `name` and the process.
+#[cfg(any(test, feature = "sim"))]
+#[doc(hidden)]
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cardputer-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes `contents` to a temporary file next to `path` and then moves it
+/// over `path`, so a failed write leaves the old file as it was.
+pub fn save_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    save_atomic_with(path, contents, |from, to| fs::rename(from, to))
+}
+
+fn save_atomic_with(
+    path: &Path,
+    contents: &[u8],
+    rename: impl Fn(&Path, &Path) -> io::Result<()>,
+) -> io::Result<()> {
+    // 8.3 names, like everything else on the card.
+    let tmp = path.with_extension("$$$");
+    let backup = path.with_extension("$$~");
+    {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    let Err(err) = rename(&tmp, path) else {
+        return Ok(());
+    };
+
+    // FAT cannot rename onto an existing file. Move the old file aside
+    // rather than removing it, so it can be put back if the rename still
+    // fails. Until then the new text is in the temporary file.
+    if !path.exists() {
+        return Err(err);
+    }
+    let _ = fs::remove_file(&backup);
+    rename(path, &backup)?;
+    if let Err(err) = rename(&tmp, path) {
+        rename(&backup, path)?;
+        return Err(err);
+    }
+    fs::remove_file(&backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Like FAT, refuses to rename onto an existing file.
+    fn fat_rename(from: &Path, to: &Path) -> io::Result<()> {
+        if to.exists() {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        fs::rename(from, to)
+    }
+
+    #[test]
+    fn save_creates_and_replaces() {
+        let dir = temp_dir("storage-replace");
+        let path = dir.join("notes.txt");
+        save_atomic(&path, b"one").unwrap();
+        save_atomic(&path, b"two").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"two");
+        assert_eq!(entries(&dir), ["notes.txt"]);
+    }
+
+    #[test]
+    fn save_replaces_without_rename_over() {
+        let dir = temp_dir("storage-fat");
+        let path = dir.join("notes.txt");
+        fs::write(&path, b"old").unwrap();
+        save_atomic_with(&path, b"new", fat_rename).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(entries(&dir), ["notes.txt"]);
+    }
+
+    #[test]
+    fn failed_rename_keeps_the_old_file() {
+        let dir = temp_dir("storage-failed");
+        let path = dir.join("notes.txt");
+        fs::write(&path, b"old").unwrap();
+        let tmp = path.with_extension("$$$");
+        let result = save_atomic_with(&path, b"new", |from, to| {
+            if from == tmp {
+                Err(io::ErrorKind::PermissionDenied.into())
+            } else {
+                fat_rename(from, to)
+            }
+        });
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        assert_eq!(fs::read(&tmp).unwrap(), b"new");
+        assert_eq!(entries(&dir), ["notes.$$$", "notes.txt"]);
+    }
+}
@@ -4,7 +4,7 @@ use embedded_graphics::prelude::*;
 use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
 use embedded_graphics::text::Text;
 
-use crate::keyboard::{CardputerKeyboard, KeyEvent};
+use crate::keyboard::{KeyEvent, KeyMatrix, Keyboard};
 use crate::swapchain::DoubleBuffer;
 use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
 
@@ -28,26 +28,36 @@ pub fn render_menu(
     context: &AppContext,
     status: &StatusSnapshot,
 ) {
-    let fbuf = buffers.swap_framebuffer();
-    let _ = fbuf.clear(Rgb565::BLACK);
+    draw_menu(buffers.swap_framebuffer(), menu, context, status);
+    buffers.send_framebuffer();
+}
+
+/// Draws the launcher screen over the whole of `target`.
+pub fn draw_menu(
+    target: &mut impl DrawTarget<Color = Rgb565>,
+    menu: &MenuState,
+    context: &AppContext,
+    status: &StatusSnapshot,
+) {
+    let _ = target.clear(Rgb565::BLACK);
 
     let title_style = MonoTextStyle::new(&FONT_6X10, Rgb565::CSS_WHITE);
     Text::new("Cardputer RustOS", Point::new(2, 10), title_style)
-        .draw(fbuf)
+        .draw(target)
         .ok();
 
-    draw_right_aligned(fbuf, &status.clock_text, 10, Rgb565::CSS_CYAN);
-    draw_right_aligned(fbuf, &status.wifi_text, 22, Rgb565::CSS_GREEN);
-    draw_right_aligned(fbuf, &status.battery_text, 34, Rgb565::CSS_YELLOW);
+    draw_right_aligned(target, &status.clock_text, 10, Rgb565::CSS_CYAN);
+    draw_right_aligned(target, &status.wifi_text, 22, Rgb565::CSS_GREEN);
+    draw_right_aligned(target, &status.battery_text, 34, Rgb565::CSS_YELLOW);
 
     let path_style = MonoTextStyle::new(&FONT_6X10, Rgb565::CSS_WHITE);
     let path_text = menu_path_display(menu);
     Text::new(&path_text, Point::new(2, 22), path_style)
-        .draw(fbuf)
+        .draw(target)
         .ok();
 
     draw_selectable_list(
-        fbuf,
+        target,
         &menu.items,
         menu.selected,
         LIST_TOP,
@@ -59,7 +69,7 @@ pub fn render_menu(
         "> ",
         "  ",
         "(empty)",
-        |item| display_name(item),
+        display_name,
     );
 
     let footer_style = MonoTextStyle::new(&FONT_6X10, Rgb565::CSS_WHITE);
@@ -68,14 +78,12 @@ pub fn render_menu(
     } else if !context.ota_ready {
         "OTA partitions missing"
     } else {
-        "Up/Down: ;/.  Enter: load  Back: Backspace"
+        ";/.:move Enter:load Bksp:back -/=:light"
     };
-    let footer_y = SCREEN_HEIGHT as i32 - 4;
+    let footer_y = target.bounding_box().size.height as i32 - 4;
     Text::new(footer, Point::new(2, footer_y), footer_style)
-        .draw(fbuf)
+        .draw(target)
         .ok();
-
-    buffers.send_framebuffer();
 }
 
 fn draw_right_aligned(
@@ -86,10 +94,11 @@ fn draw_right_aligned(
 ) {
     let style = MonoTextStyle::new(&FONT_6X10, color);
     let width = (text.len() as i32 * 6) + 2;
-    let x = (SCREEN_WIDTH as i32 - width).max(0);
+    let x = (target.bounding_box().size.width as i32 - width).max(0);
     Text::new(text, Point::new(x, y), style).draw(target).ok();
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn draw_selectable_list<T, F>(
     target: &mut impl DrawTarget<Color = Rgb565>,
     items: &[T],
@@ -111,11 +120,7 @@ where
     let len = items.len();
     let max_visible = max_visible.min(len.max(1));
     let half = max_visible / 2;
-    let mut start = if selected > half {
-        selected - half
-    } else {
-        0
-    };
+    let mut start = selected.saturating_sub(half);
     if len > max_visible {
         start = start.min(len - max_visible);
     } else {
@@ -147,30 +152,39 @@ pub fn render_status<T: AsRef<str>>(
     lines: &[T],
     progress: Option<FlashProgress>,
 ) {
-    let fbuf = buffers.swap_framebuffer();
-    let _ = fbuf.clear(Rgb565::BLACK);
+    draw_status(buffers.swap_framebuffer(), title, lines, progress);
+    buffers.send_framebuffer();
+}
+
+/// Draws a status screen over the whole of `target`: a title, some lines
+/// and optionally a progress bar.
+pub fn draw_status<T: AsRef<str>>(
+    target: &mut impl DrawTarget<Color = Rgb565>,
+    title: &str,
+    lines: &[T],
+    progress: Option<FlashProgress>,
+) {
+    let _ = target.clear(Rgb565::BLACK);
 
     let title_style = MonoTextStyle::new(&FONT_6X10, Rgb565::CSS_WHITE);
     Text::new(title, Point::new(2, 10), title_style)
-        .draw(fbuf)
+        .draw(target)
         .ok();
 
     let text_style = MonoTextStyle::new(&FONT_6X10, Rgb565::CSS_WHITE);
     for (idx, line) in lines.iter().enumerate() {
         let y = 28 + idx as i32 * ROW_HEIGHT;
         Text::new(line.as_ref(), Point::new(2, y), text_style)
-            .draw(fbuf)
+            .draw(target)
             .ok();
     }
 
     if let Some(progress) = progress {
-        render_progress_bar(fbuf, progress);
+        render_progress_bar(target, progress);
     }
-
-    buffers.send_framebuffer();
 }
 
-fn render_progress_bar(
+pub fn render_progress_bar(
     target: &mut impl DrawTarget<Color = Rgb565>,
     progress: FlashProgress,
 ) {
@@ -187,8 +201,8 @@ fn render_progress_bar(
     outline.draw(target).ok();
 
     if let Some(total) = progress.total {
-        if total > 0 {
-            let pct = (progress.written.saturating_mul(100) / total).min(100);
+        if let Some(pct) = progress.written.saturating_mul(100).checked_div(total) {
+            let pct = pct.min(100);
             let filled = (bar_width.saturating_sub(2) as usize * pct / 100) as u32;
             if filled > 0 {
                 let fill_rect = Rectangle::new(
@@ -214,7 +228,7 @@ fn render_progress_bar(
 
 pub fn show_message_and_wait<T: AsRef<str>>(
     buffers: &mut DoubleBuffer<SCREEN_WIDTH, SCREEN_HEIGHT>,
-    keyboard: &mut CardputerKeyboard<'static>,
+    keyboard: &mut Keyboard<impl KeyMatrix>,
     title: &str,
     lines: &[T],
 ) {
@@ -223,7 +237,7 @@ pub fn show_message_and_wait<T: AsRef<str>>(
     wait_for_keypress(keyboard);
 }
 
-fn wait_for_keypress(keyboard: &mut CardputerKeyboard<'static>) {
+fn wait_for_keypress(keyboard: &mut Keyboard<impl KeyMatrix>) {
     loop {
         if let Some((event, _)) = keyboard.read_events() {
             if matches!(event, KeyEvent::Pressed) {
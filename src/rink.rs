@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use rink_core::loader::gnu_units;
+use rink_core::Context;
+
+use crate::os::storage;
+
+/// The extension of unit files in `SD_RINK_PATH`.
+pub const UNITS_EXTENSION: &str = "unt";
+
+/// Adds the definitions in `text`, in GNU units format, to `ctx`.
+pub fn load_units(ctx: &mut Context, text: &str) -> Result<(), String> {
+    let mut iter = gnu_units::TokenIterator::new(text).peekable();
+    ctx.load(gnu_units::parse(&mut iter))
+}
+
+/// Loads the `UNITS_EXTENSION` files in `dir`, e.g. custom units or currencies, written
+/// like `definitions.units`. Returns a line for each file.
+pub fn load_definitions(ctx: &mut Context, dir: &str) -> Vec<String> {
+    storage::list_files_with_extension(dir, UNITS_EXTENSION)
+        .into_iter()
+        .map(|file| {
+            let loaded = fs::read_to_string(&file.path)
+                .map_err(|err| err.to_string())
+                .and_then(|text| load_units(ctx, &text));
+            match loaded {
+                Ok(()) => format!("Loaded {}", file.name),
+                Err(err) => format!("{}: {}", file.name, err),
+            }
+        })
+        .collect()
+}
+
+/// `name = expression`, where the name is a plain identifier.
+pub fn parse_assignment(line: &str) -> Option<(&str, &str)> {
+    let (name, expr) = line.split_once('=')?;
+    let (name, expr) = (name.trim(), expr.trim());
+    let mut chars = name.chars();
+    let valid_name = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    (valid_name && !expr.is_empty() && !expr.starts_with('=')).then_some((name, expr))
+}
+
+/// Variables set with `name = expression`. They become units of the
+/// context and are kept in a file, one assignment per line, to be set
+/// again on the next start.
+pub struct Variables {
+    path: PathBuf,
+    entries: BTreeMap<String, String>,
+}
+
+impl Variables {
+    /// Sets the variables saved in `path`. Returns the lines that failed as messages.
+    pub fn load(path: PathBuf, ctx: &mut Context) -> (Self, Vec<String>) {
+        let mut variables = Self {
+            path,
+            entries: BTreeMap::new(),
+        };
+        let mut errors = Vec::new();
+        let text = fs::read_to_string(&variables.path).unwrap_or_default();
+        let mut pending = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_assignment(line) {
+                Some(assignment) => pending.push(assignment),
+                None => errors.push(format!("Line {}: not name = expression", number + 1)),
+            }
+        }
+
+        // The file is sorted by name, so a variable can come before one it
+        // uses. Try the failed ones again as long as others still get set.
+        loop {
+            let count = pending.len();
+            let mut failed = Vec::new();
+            for (name, expr) in pending {
+                match define(ctx, name, expr) {
+                    Ok(()) => {
+                        variables.entries.insert(name.to_string(), expr.to_string());
+                    }
+                    Err(err) => failed.push((name, expr, err)),
+                }
+            }
+            if failed.is_empty() || failed.len() == count {
+                errors.extend(
+                    failed
+                        .into_iter()
+                        .map(|(name, _, err)| format!("{}: {}", name, err)),
+                );
+                break;
+            }
+            pending = failed
+                .into_iter()
+                .map(|(name, expr, _)| (name, expr))
+                .collect();
+        }
+        (variables, errors)
+    }
+
+    /// Names and expressions, sorted by name.
+    pub fn entries(&self) -> &BTreeMap<String, String> {
+        &self.entries
+    }
+
+    pub fn set(&mut self, ctx: &mut Context, name: &str, expr: &str) -> Result<(), String> {
+        define(ctx, name, expr)?;
+        self.entries.insert(name.to_string(), expr.to_string());
+        self.save()
+            .map_err(|err| format!("Could not save {}: {}", self.path.display(), err))
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for (name, expr) in &self.entries {
+            text.push_str(&format!("{} = {}\n", name, expr));
+        }
+        storage::save_atomic(&self.path, text.as_bytes())
+    }
+}
+
+fn define(ctx: &mut Context, name: &str, expr: &str) -> Result<(), String> {
+    // Evaluate first, so that a typo is reported instead of becoming a unit.
+    rink_core::one_line(ctx, expr)?;
+    load_units(ctx, &format!("{} {}", name, expr))
+}
+
+/// The unit and prefix names defined in a GNU units file, for Tab completion.
+pub fn unit_names(units: &str) -> Vec<String> {
+    units
+        .lines()
+        .filter(|line| !line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '!'))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|name| {
+            name.split('(')
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches('-')
+        })
+        .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_'))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os::storage::temp_dir;
+
+    #[test]
+    fn assignment_names() {
+        assert_eq!(parse_assignment("a = 1"), Some(("a", "1")));
+        assert_eq!(parse_assignment(" _len2=3 m "), Some(("_len2", "3 m")));
+        assert_eq!(parse_assignment("1a = 2"), None);
+        assert_eq!(parse_assignment("x = = y"), None);
+        assert_eq!(parse_assignment("x == y"), None);
+        assert_eq!(parse_assignment("a b = 1"), None);
+        assert_eq!(parse_assignment("a ="), None);
+        assert_eq!(parse_assignment("3 m -> ft"), None);
+    }
+
+    #[test]
+    fn variables_round_trip() {
+        let path = temp_dir("rink-vars").join("rink").join("vars");
+        let mut ctx = Context::new();
+        let (mut variables, errors) = Variables::load(path.clone(), &mut ctx);
+        assert!(errors.is_empty());
+        assert!(variables.entries().is_empty());
+
+        variables.set(&mut ctx, "b", "2").unwrap();
+        variables.set(&mut ctx, "a", "b * 3").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a = b * 3\nb = 2\n");
+
+        let mut ctx = Context::new();
+        let (loaded, errors) = Variables::load(path, &mut ctx);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(loaded.entries(), variables.entries());
+    }
+
+    #[test]
+    fn set_keeps_a_failed_expression_out() {
+        let path = temp_dir("rink-typo").join("vars");
+        let mut ctx = Context::new();
+        let (mut variables, _) = Variables::load(path.clone(), &mut ctx);
+        assert!(variables.set(&mut ctx, "a", "1 +").is_err());
+        assert!(variables.entries().is_empty());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn broken_lines_are_reported() {
+        let path = temp_dir("rink-broken").join("vars");
+        fs::write(&path, "a = 1\n\nnot an assignment\nb = 1 +\n").unwrap();
+        let mut ctx = Context::new();
+        let (variables, errors) = Variables::load(path, &mut ctx);
+        assert_eq!(variables.entries().keys().collect::<Vec<_>>(), ["a"]);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert_eq!(errors[0], "Line 3: not name = expression");
+        assert!(errors[1].starts_with("b: "), "{:?}", errors);
+    }
+
+    #[test]
+    fn unit_names_skip_comments_and_commands() {
+        let units = "\
+# a comment
+!set UNITS_ENGLISH US
+meter          !
+m              meter
+kilo-          1e3
+  continued    line
+sqrt(x)        x^(1/2)
+foot           12 inch
++odd           1
+";
+        assert_eq!(unit_names(units), ["meter", "m", "kilo", "sqrt", "foot"]);
+    }
+}
@@ -0,0 +1,790 @@
+use std::fmt;
+
+/// How numbers are typed and shown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Radix {
+    #[default]
+    Dec,
+    Hex,
+    Oct,
+    Bin,
+}
+
+impl Radix {
+    fn base(self) -> u32 {
+        match self {
+            Radix::Dec => 10,
+            Radix::Hex => 16,
+            Radix::Oct => 8,
+            Radix::Bin => 2,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Radix::Dec => "DEC",
+            Radix::Hex => "HEX",
+            Radix::Oct => "OCT",
+            Radix::Bin => "BIN",
+        }
+    }
+
+    /// Whether numbers are whole and `word_size` bits wide, as on an HP-16C.
+    pub fn is_integer(self) -> bool {
+        self != Radix::Dec
+    }
+}
+
+/// The unit of angles for the trigonometric functions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AngleMode {
+    #[default]
+    Deg,
+    Rad,
+}
+
+impl AngleMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            AngleMode::Deg => "DEG",
+            AngleMode::Rad => "RAD",
+        }
+    }
+}
+
+/// A number on the stack or in a register. In decimal mode every value is
+/// a `Float`; in the other radixes every value is an `Int`, so no bits of a
+/// 64-bit word get lost on the way through an `f64`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Float(f64),
+    /// Sign-extended from the word size, so it also reads as two's complement.
+    Int(i64),
+}
+
+impl Value {
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Value::Float(value) => value,
+            Value::Int(value) => value as f64,
+        }
+    }
+
+    fn is_zero(self) -> bool {
+        match self {
+            Value::Float(value) => value == 0.0,
+            Value::Int(value) => value == 0,
+        }
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::Float(0.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpnError {
+    UnknownWord(String),
+    DivideByZero,
+    /// The function is not defined for the argument, e.g. `sqrt` of a negative.
+    Domain(&'static str),
+    /// A bitwise operation on a number with a fraction or out of 64 bits.
+    NotAnInteger,
+    /// A function of real numbers, used in HEX, OCT or BIN mode.
+    NeedsDecimal(String),
+}
+
+impl fmt::Display for RpnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpnError::UnknownWord(word) => write!(f, "Unknown: {}", word),
+            RpnError::DivideByZero => write!(f, "Division by zero"),
+            RpnError::Domain(name) => write!(f, "Invalid argument for {}", name),
+            RpnError::NotAnInteger => write!(f, "Not an integer"),
+            RpnError::NeedsDecimal(word) => write!(f, "{} needs DEC mode", word),
+        }
+    }
+}
+
+/// Words `Calculator::eval` understands besides numbers. `sto`, `rcl` and
+/// `ws` take a number right after them, as in `sto3` or `ws16`, and are
+/// left out; `completion_words` has them.
+pub const WORDS: &[&str] = &[
+    "+", "-", "*", "/", "^", "%", "neg", "chs", "inv", "sq", "sqrt", "abs", "int", "frac", "sin",
+    "cos", "tan", "asin", "acos", "atan", "ln", "log", "exp", "pi", "and", "or", "xor", "not",
+    "shl", "shr", "swap", "drop", "dup", "rd", "clr", "lastx", "dec", "hex", "oct", "bin", "deg",
+    "rad",
+];
+
+pub const REGISTERS: usize = 10;
+
+/// The word size in HEX, OCT and BIN mode until `ws` changes it.
+pub const DEFAULT_WORD_SIZE: u32 = 64;
+
+/// An HP-style RPN calculator with a four level stack.
+///
+/// Pushing a number lifts the stack and loses T; an operation takes X and Y
+/// and drops the stack, which copies T into Z.
+///
+/// Decimal mode works on `f64`. HEX, OCT and BIN work on two's complement
+/// integers of `word_size` bits, wrapping on overflow; switching between the
+/// two converts the stack, the registers and LASTX.
+#[derive(Debug, Clone)]
+pub struct Calculator {
+    /// X, Y, Z, T.
+    stack: [Value; 4],
+    memory: [Value; REGISTERS],
+    last_x: Value,
+    radix: Radix,
+    angle: AngleMode,
+    word_size: u32,
+}
+
+impl Default for Calculator {
+    fn default() -> Self {
+        Self {
+            stack: Default::default(),
+            memory: Default::default(),
+            last_x: Value::default(),
+            radix: Radix::default(),
+            angle: AngleMode::default(),
+            word_size: DEFAULT_WORD_SIZE,
+        }
+    }
+}
+
+impl Calculator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// X, Y, Z and T, in that order.
+    pub fn stack(&self) -> [Value; 4] {
+        self.stack
+    }
+
+    pub fn x(&self) -> Value {
+        self.stack[0]
+    }
+
+    pub fn memory(&self) -> &[Value; REGISTERS] {
+        &self.memory
+    }
+
+    pub fn radix(&self) -> Radix {
+        self.radix
+    }
+
+    pub fn angle(&self) -> AngleMode {
+        self.angle
+    }
+
+    pub fn word_size(&self) -> u32 {
+        self.word_size
+    }
+
+    /// Pushes `value`, converted to the current mode.
+    pub fn push(&mut self, value: Value) {
+        let value = self.convert(value);
+        self.stack.copy_within(0..3, 1);
+        self.stack[0] = value;
+    }
+
+    /// ENTER on an empty line: copies X into Y.
+    pub fn enter(&mut self) {
+        self.push(self.x());
+    }
+
+    /// Switches the radix, converting every value when going between
+    /// decimal and the integer radixes.
+    pub fn set_radix(&mut self, radix: Radix) {
+        self.radix = radix;
+        self.convert_all();
+    }
+
+    /// Sets the integer word size, 1 to 64 bits. Integers keep their low bits.
+    pub fn set_word_size(&mut self, bits: u32) {
+        self.word_size = bits.clamp(1, 64);
+        self.convert_all();
+    }
+
+    /// Runs the blank-separated numbers and words in `line` from left to right.
+    /// Stops at the first error; what ran before it stays done.
+    pub fn eval(&mut self, line: &str) -> Result<(), RpnError> {
+        for word in line.split_whitespace() {
+            self.apply(word)?;
+        }
+        Ok(())
+    }
+
+    /// Runs one number or word. On error the stack is left as it was.
+    ///
+    /// Words win over numbers, so `dec` still switches back from hex.
+    pub fn apply(&mut self, word: &str) -> Result<(), RpnError> {
+        let lower = word.to_ascii_lowercase();
+        if !is_word(&lower) {
+            if let Some(value) = self.parse_number(word) {
+                self.push(value);
+                return Ok(());
+            }
+        }
+
+        let saved = self.clone();
+        let result = self.apply_word(&lower);
+        if result.is_err() {
+            *self = saved;
+        }
+        result
+    }
+
+    fn apply_word(&mut self, word: &str) -> Result<(), RpnError> {
+        match word {
+            "swap" => self.stack.swap(0, 1),
+            "drop" => self.drop(),
+            "dup" => self.enter(),
+            // Roll down: X goes to T.
+            "rd" => self.stack.rotate_left(1),
+            "clr" => self.stack = [self.convert(Value::default()); 4],
+            "lastx" => self.push(self.last_x),
+            "dec" => self.set_radix(Radix::Dec),
+            "hex" => self.set_radix(Radix::Hex),
+            "oct" => self.set_radix(Radix::Oct),
+            "bin" => self.set_radix(Radix::Bin),
+            "deg" => self.angle = AngleMode::Deg,
+            "rad" => self.angle = AngleMode::Rad,
+            word => {
+                if let Some(index) = register(word, "sto") {
+                    self.memory[index] = self.x();
+                } else if let Some(index) = register(word, "rcl") {
+                    self.push(self.memory[index]);
+                } else if let Some(bits) = word_size(word) {
+                    self.set_word_size(bits);
+                } else {
+                    match (self.stack[0], self.stack[1]) {
+                        (Value::Int(x), Value::Int(y)) => self.apply_integer(word, x, y)?,
+                        (x, y) => self.apply_float(word, x.as_f64(), y.as_f64())?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_float(&mut self, word: &str, x: f64, y: f64) -> Result<(), RpnError> {
+        match word {
+            "+" => self.binary(x + y),
+            "-" => self.binary(y - x),
+            "*" => self.binary(y * x),
+            "/" if x == 0.0 => return Err(RpnError::DivideByZero),
+            "/" => self.binary(y / x),
+            "%" if x == 0.0 => return Err(RpnError::DivideByZero),
+            "%" => self.binary(y % x),
+            "^" => self.binary(check("^", y.powf(x))?),
+            "neg" | "chs" => self.unary(-x),
+            "inv" if x == 0.0 => return Err(RpnError::DivideByZero),
+            "inv" => self.unary(1.0 / x),
+            "sq" => self.unary(x * x),
+            "sqrt" => self.unary(check("sqrt", x.sqrt())?),
+            "abs" => self.unary(x.abs()),
+            "int" => self.unary(x.trunc()),
+            "frac" => self.unary(x.fract()),
+            "sin" => self.unary(self.angle_to_radians(x).sin()),
+            "cos" => self.unary(self.angle_to_radians(x).cos()),
+            "tan" => self.unary(self.angle_to_radians(x).tan()),
+            "asin" => self.unary(self.radians_to_angle(check("asin", x.asin())?)),
+            "acos" => self.unary(self.radians_to_angle(check("acos", x.acos())?)),
+            "atan" => self.unary(self.radians_to_angle(x.atan())),
+            "ln" => self.unary(check("ln", x.ln())?),
+            "log" => self.unary(check("log", x.log10())?),
+            "exp" => self.unary(x.exp()),
+            "pi" => self.push(Value::Float(std::f64::consts::PI)),
+            "and" => self.binary((to_bits(y)? & to_bits(x)?) as f64),
+            "or" => self.binary((to_bits(y)? | to_bits(x)?) as f64),
+            "xor" => self.binary((to_bits(y)? ^ to_bits(x)?) as f64),
+            "not" => self.unary(!to_bits(x)? as f64),
+            "shl" => self.binary(shift_left(to_bits(y)?, to_bits(x)?, 64) as f64),
+            "shr" => self.binary(shift_right(to_bits(y)?, to_bits(x)?, 64) as f64),
+            word => return Err(RpnError::UnknownWord(word.to_string())),
+        }
+        Ok(())
+    }
+
+    /// `x` and `y` are already sign-extended from the word size; every
+    /// result is cut back to it.
+    fn apply_integer(&mut self, word: &str, x: i64, y: i64) -> Result<(), RpnError> {
+        let bits = self.word_size;
+        // The result, and whether it replaces both X and Y.
+        let (result, binary) = match word {
+            "+" => (y.wrapping_add(x), true),
+            "-" => (y.wrapping_sub(x), true),
+            "*" => (y.wrapping_mul(x), true),
+            "/" | "%" if x == 0 => return Err(RpnError::DivideByZero),
+            "/" => (y.wrapping_div(x), true),
+            "%" => (y.wrapping_rem(x), true),
+            "^" if x < 0 => return Err(RpnError::Domain("^")),
+            "^" => (y.wrapping_pow(u32::try_from(x).unwrap_or(u32::MAX)), true),
+            "neg" | "chs" => (x.wrapping_neg(), false),
+            "sq" => (x.wrapping_mul(x), false),
+            "abs" => (x.wrapping_abs(), false),
+            "int" => (x, false),
+            "frac" => (0, false),
+            "and" => (y & x, true),
+            "or" => (y | x, true),
+            "xor" => (y ^ x, true),
+            "not" => (!x, false),
+            "shl" | "shr" if x < 0 => return Err(RpnError::Domain("shift")),
+            "shl" => (shift_left(y as u64, x as u64, bits) as i64, true),
+            "shr" => (shift_right(y as u64, x as u64, bits) as i64, true),
+            "inv" | "sqrt" | "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "ln" | "log"
+            | "exp" | "pi" => return Err(RpnError::NeedsDecimal(word.to_string())),
+            word => return Err(RpnError::UnknownWord(word.to_string())),
+        };
+
+        self.last_x = self.stack[0];
+        if binary {
+            self.drop();
+        }
+        self.stack[0] = Value::Int(sign_extend(result, bits));
+        Ok(())
+    }
+
+    fn unary(&mut self, result: f64) {
+        self.last_x = self.stack[0];
+        self.stack[0] = Value::Float(result);
+    }
+
+    fn binary(&mut self, result: f64) {
+        self.last_x = self.stack[0];
+        self.drop();
+        self.stack[0] = Value::Float(result);
+    }
+
+    fn drop(&mut self) {
+        self.stack.copy_within(1..4, 0);
+    }
+
+    /// `value` as the current mode keeps it.
+    fn convert(&self, value: Value) -> Value {
+        match (self.radix.is_integer(), value) {
+            (true, Value::Float(value)) => {
+                Value::Int(sign_extend(float_to_int(value), self.word_size))
+            }
+            (true, Value::Int(value)) => Value::Int(sign_extend(value, self.word_size)),
+            (false, Value::Int(value)) => Value::Float(value as f64),
+            (false, value) => value,
+        }
+    }
+
+    fn convert_all(&mut self) {
+        for index in 0..self.stack.len() {
+            self.stack[index] = self.convert(self.stack[index]);
+        }
+        for index in 0..REGISTERS {
+            self.memory[index] = self.convert(self.memory[index]);
+        }
+        self.last_x = self.convert(self.last_x);
+    }
+
+    fn angle_to_radians(&self, angle: f64) -> f64 {
+        match self.angle {
+            AngleMode::Deg => angle.to_radians(),
+            AngleMode::Rad => angle,
+        }
+    }
+
+    fn radians_to_angle(&self, angle: f64) -> f64 {
+        match self.angle {
+            AngleMode::Deg => angle.to_degrees(),
+            AngleMode::Rad => angle,
+        }
+    }
+
+    /// A number in the current radix, or with a `0x`, `0o` or `0b` prefix in any.
+    /// Decimal numbers may have a fraction and an exponent. In the integer
+    /// radixes only the low `word_size` bits are kept.
+    pub fn parse_number(&self, word: &str) -> Option<Value> {
+        let (negative, digits) = match word.strip_prefix('-') {
+            Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (false, word),
+        };
+        let prefixed = [("0x", Radix::Hex), ("0o", Radix::Oct), ("0b", Radix::Bin)]
+            .iter()
+            .find_map(|(prefix, radix)| Some((digits.strip_prefix(prefix)?, *radix)));
+
+        if self.radix.is_integer() {
+            let (digits, radix) = prefixed.unwrap_or((digits, self.radix));
+            let value = u64::from_str_radix(digits, radix.base()).ok()? as i64;
+            let value = if negative {
+                value.wrapping_neg()
+            } else {
+                value
+            };
+            return Some(Value::Int(sign_extend(value, self.word_size)));
+        }
+
+        let value = match prefixed {
+            Some((digits, radix)) => u64::from_str_radix(digits, radix.base()).ok()? as f64,
+            None => {
+                let starts_like_number =
+                    digits.starts_with(|c: char| c.is_ascii_digit() || c == '.');
+                if !starts_like_number {
+                    return None;
+                }
+                digits.parse::<f64>().ok()?
+            }
+        };
+        Some(Value::Float(if negative { -value } else { value }))
+    }
+
+    /// `value` as shown in the current radix. Integers show their
+    /// `word_size` bits of two's complement.
+    pub fn format(&self, value: Value) -> String {
+        let bits = match value {
+            Value::Float(value) => return format_decimal(value),
+            Value::Int(value) => value as u64 & mask(self.word_size),
+        };
+        match self.radix {
+            Radix::Dec => value.as_f64().to_string(),
+            Radix::Hex => format!("0x{:X}", bits),
+            Radix::Oct => format!("0o{:o}", bits),
+            Radix::Bin => format!("0b{:b}", bits),
+        }
+    }
+
+    /// The modes, and which registers hold something.
+    pub fn status(&self) -> String {
+        let mut status = if self.radix.is_integer() {
+            format!("{} W{}", self.radix.name(), self.word_size)
+        } else {
+            format!("{} {}", self.radix.name(), self.angle.name())
+        };
+        let used: Vec<String> = (0..REGISTERS)
+            .filter(|&index| !self.memory[index].is_zero())
+            .map(|index| index.to_string())
+            .collect();
+        if !used.is_empty() {
+            status.push_str(&format!("  M{}", used.join(",")));
+        }
+        status
+    }
+
+    /// T, Z, Y and X from top to bottom, right-aligned to `cols`. Numbers
+    /// too wide for the line keep their low digits behind a `<`.
+    pub fn stack_lines(&self, cols: usize) -> [String; 4] {
+        let labels = ["T:", "Z:", "Y:", "X:"];
+        let room = cols.saturating_sub(2);
+        let mut lines: [String; 4] = Default::default();
+        for (line, (label, &value)) in lines
+            .iter_mut()
+            .zip(labels.iter().zip(self.stack.iter().rev()))
+        {
+            let mut value = self.format(value);
+            let len = value.chars().count();
+            if len > room {
+                let skip = len + 1 - room;
+                value = format!("<{}", value.chars().skip(skip).collect::<String>());
+            }
+            *line = format!("{}{:>2$}", label, value, room);
+        }
+        lines
+    }
+}
+
+fn check(name: &'static str, value: f64) -> Result<f64, RpnError> {
+    if value.is_nan() {
+        Err(RpnError::Domain(name))
+    } else {
+        Ok(value)
+    }
+}
+
+/// `WORDS`, plus `sto` and `rcl` for every register and `ws` for the usual
+/// word sizes, for Tab completion.
+pub fn completion_words() -> Vec<String> {
+    let registers = (0..REGISTERS).flat_map(|i| [format!("sto{}", i), format!("rcl{}", i)]);
+    let word_sizes = [8, 16, 32, 64].map(|bits| format!("ws{}", bits));
+    WORDS
+        .iter()
+        .map(|word| word.to_string())
+        .chain(registers)
+        .chain(word_sizes)
+        .collect()
+}
+
+fn is_word(word: &str) -> bool {
+    WORDS.contains(&word)
+        || register(word, "sto").is_some()
+        || register(word, "rcl").is_some()
+        || word_size(word).is_some()
+}
+
+/// `sto3` is register 3.
+fn register(word: &str, prefix: &str) -> Option<usize> {
+    let index: usize = word.strip_prefix(prefix)?.parse().ok()?;
+    (index < REGISTERS).then_some(index)
+}
+
+/// `ws16` sets a word size of 16 bits.
+fn word_size(word: &str) -> Option<u32> {
+    let bits: u32 = word.strip_prefix("ws")?.parse().ok()?;
+    (1..=64).contains(&bits).then_some(bits)
+}
+
+fn mask(bits: u32) -> u64 {
+    u64::MAX >> (64 - bits)
+}
+
+/// The low `bits` of `value`, with the top one of them copied upwards.
+fn sign_extend(value: i64, bits: u32) -> i64 {
+    let unused = 64 - bits;
+    (value << unused) >> unused
+}
+
+/// Whole numbers up to `u64::MAX` keep their bits; others are cut like
+/// an `as` cast.
+fn float_to_int(value: f64) -> i64 {
+    if value >= i64::MAX as f64 {
+        value as u64 as i64
+    } else {
+        value as i64
+    }
+}
+
+/// Shifts within a word of `bits`; shifting by the word size or more gives 0.
+fn shift_left(value: u64, by: u64, bits: u32) -> u64 {
+    if by >= bits as u64 {
+        0
+    } else {
+        (value << by) & mask(bits)
+    }
+}
+
+/// A logical shift: zeros come in at the top of the word.
+fn shift_right(value: u64, by: u64, bits: u32) -> u64 {
+    if by >= bits as u64 {
+        0
+    } else {
+        (value & mask(bits)) >> by
+    }
+}
+
+fn to_bits(value: f64) -> Result<u64, RpnError> {
+    if value.fract() != 0.0 || !(i64::MIN as f64..=u64::MAX as f64).contains(&value) {
+        return Err(RpnError::NotAnInteger);
+    }
+    Ok(if value < 0.0 {
+        value as i64 as u64
+    } else {
+        value as u64
+    })
+}
+
+/// Up to 12 significant digits, switching to an exponent for very large or
+/// small numbers.
+fn format_decimal(value: f64) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return value.to_string();
+    }
+    let magnitude = value.abs().log10().floor() as i32;
+    if !(-6..12).contains(&magnitude) {
+        let text = format!("{:.11e}", value);
+        let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+        return format!("{}e{}", trim_fraction(mantissa), exponent);
+    }
+    let decimals = (11 - magnitude).max(0) as usize;
+    trim_fraction(&format!("{:.*}", decimals, value)).to_string()
+}
+
+fn trim_fraction(number: &str) -> &str {
+    if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(line: &str) -> Calculator {
+        let mut calc = Calculator::new();
+        calc.eval(line).unwrap();
+        calc
+    }
+
+    fn floats(calc: &Calculator) -> [f64; 4] {
+        calc.stack().map(Value::as_f64)
+    }
+
+    #[test]
+    fn push_lifts_and_loses_t() {
+        let calc = eval("1 2 3 4 5");
+        assert_eq!(floats(&calc), [5.0, 4.0, 3.0, 2.0]);
+    }
+
+    #[test]
+    fn operations_drop_and_copy_t() {
+        let calc = eval("1 2 3 4 +");
+        assert_eq!(floats(&calc), [7.0, 2.0, 1.0, 1.0]);
+        assert_eq!(eval("10 4 -").x(), Value::Float(6.0));
+        assert_eq!(eval("2 10 ^").x(), Value::Float(1024.0));
+    }
+
+    #[test]
+    fn stack_words() {
+        assert_eq!(floats(&eval("1 2 swap")), [1.0, 2.0, 0.0, 0.0]);
+        assert_eq!(floats(&eval("1 2 drop")), [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(floats(&eval("1 dup")), [1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(floats(&eval("1 2 3 4 rd")), [3.0, 2.0, 1.0, 4.0]);
+        assert_eq!(floats(&eval("1 2 clr")), [0.0; 4]);
+        assert_eq!(floats(&eval("3 4 * lastx")), [4.0, 12.0, 0.0, 0.0]);
+
+        let mut calc = eval("7");
+        calc.enter();
+        assert_eq!(floats(&calc), [7.0, 7.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn errors_leave_the_stack_alone() {
+        let mut calc = eval("1 0");
+        assert_eq!(calc.eval("/"), Err(RpnError::DivideByZero));
+        assert_eq!(floats(&calc), [0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(
+            calc.eval("frob"),
+            Err(RpnError::UnknownWord("frob".to_string()))
+        );
+        assert_eq!(eval("-1").eval("sqrt"), Err(RpnError::Domain("sqrt")));
+    }
+
+    #[test]
+    fn completion_words_are_known() {
+        for word in completion_words() {
+            for mode in ["dec", "hex"] {
+                let mut calc = eval(&format!("{} 9 3", mode));
+                let result = calc.eval(&word);
+                assert!(
+                    !matches!(result, Err(RpnError::UnknownWord(_))),
+                    "{} in {}",
+                    word,
+                    mode
+                );
+            }
+        }
+        assert!(completion_words().contains(&"sto9".to_string()));
+        assert!(!completion_words().contains(&"sto".to_string()));
+        assert_eq!(floats(&eval("5 chs")), [-5.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn store_and_recall() {
+        let mut calc = eval("42 sto3 drop 1 rcl3");
+        assert_eq!(floats(&calc), [42.0, 1.0, 0.0, 0.0]);
+        assert_eq!(calc.memory()[3], Value::Float(42.0));
+        assert_eq!(calc.status(), "DEC DEG  M3");
+        assert!(calc.eval("sto10").is_err());
+    }
+
+    #[test]
+    fn radix_round_trips() {
+        let mut calc = Calculator::new();
+        for (radix, word, text) in [
+            (Radix::Hex, "ff", "0xFF"),
+            (Radix::Oct, "777", "0o777"),
+            (Radix::Bin, "1011", "0b1011"),
+            (Radix::Hex, "-1", "0xFFFFFFFFFFFFFFFF"),
+        ] {
+            calc.set_radix(radix);
+            let value = calc.parse_number(word).unwrap();
+            assert_eq!(calc.format(value), text);
+            assert_eq!(calc.parse_number(&calc.format(value)), Some(value));
+        }
+
+        calc.set_radix(Radix::Dec);
+        assert_eq!(calc.parse_number("0x10"), Some(Value::Float(16.0)));
+        assert_eq!(calc.parse_number("1.5e3"), Some(Value::Float(1500.0)));
+        assert_eq!(calc.format(Value::Float(0.1 + 0.2)), "0.3");
+        assert_eq!(calc.parse_number("abc"), None);
+    }
+
+    #[test]
+    fn words_win_over_hex_digits() {
+        let mut calc = eval("hex");
+        calc.eval("dec").unwrap();
+        assert_eq!(calc.radix(), Radix::Dec);
+        calc.eval("hex add").unwrap();
+        assert_eq!(calc.x(), Value::Int(0xADD));
+    }
+
+    #[test]
+    fn hex_keeps_all_64_bits() {
+        let mut calc = eval("hex 0x123456789ABCDEF0");
+        assert_eq!(calc.x(), Value::Int(0x123456789ABCDEF0));
+        assert_eq!(calc.format(calc.x()), "0x123456789ABCDEF0");
+
+        calc.eval("not").unwrap();
+        assert_eq!(calc.format(calc.x()), "0xEDCBA9876543210F");
+        calc.eval("not 4 shl").unwrap();
+        assert_eq!(calc.format(calc.x()), "0x23456789ABCDEF00");
+        calc.eval("8 shr").unwrap();
+        assert_eq!(calc.format(calc.x()), "0x23456789ABCDEF");
+        calc.eval("64 shl").unwrap();
+        assert_eq!(calc.x(), Value::Int(0));
+    }
+
+    #[test]
+    fn bitwise_ops_on_wide_values() {
+        let mut calc = eval("hex ffffffffffffffff 0x8000000000000001 and");
+        assert_eq!(calc.format(calc.x()), "0x8000000000000001");
+        calc.eval("0x7ffffffffffffffe or").unwrap();
+        assert_eq!(calc.format(calc.x()), "0xFFFFFFFFFFFFFFFF");
+        calc.eval("0xF0F0F0F0F0F0F0F0 xor").unwrap();
+        assert_eq!(calc.format(calc.x()), "0xF0F0F0F0F0F0F0F");
+    }
+
+    #[test]
+    fn integer_arithmetic_wraps() {
+        let mut calc = eval("hex 0xFFFFFFFFFFFFFFFF 1 +");
+        assert_eq!(calc.x(), Value::Int(0));
+        calc.eval("7 2 /").unwrap();
+        assert_eq!(calc.x(), Value::Int(3));
+        calc.eval("-7 2 %").unwrap();
+        assert_eq!(calc.x(), Value::Int(-1));
+        assert_eq!(
+            calc.eval("sqrt"),
+            Err(RpnError::NeedsDecimal("sqrt".to_string()))
+        );
+    }
+
+    #[test]
+    fn word_size_cuts_values() {
+        let mut calc = eval("hex ws8 0x1FF");
+        assert_eq!(calc.format(calc.x()), "0xFF");
+        assert_eq!(calc.x(), Value::Int(-1));
+        calc.eval("1 +").unwrap();
+        assert_eq!(calc.x(), Value::Int(0));
+        calc.eval("0x81 1 shl").unwrap();
+        assert_eq!(calc.format(calc.x()), "0x2");
+        assert_eq!(calc.status(), "HEX W8");
+    }
+
+    #[test]
+    fn switching_modes_converts_values() {
+        let mut calc = eval("2.9 -3 sto1");
+        calc.set_radix(Radix::Hex);
+        assert_eq!(calc.stack()[..2], [Value::Int(-3), Value::Int(2)]);
+        assert_eq!(calc.memory()[1], Value::Int(-3));
+        calc.eval("dec").unwrap();
+        assert_eq!(floats(&calc)[..2], [-3.0, 2.0]);
+    }
+
+    #[test]
+    fn stack_lines_truncate_on_the_left() {
+        let calc = eval("hex 0x123456789ABCDEF0");
+        let lines = calc.stack_lines(12);
+        assert_eq!(lines[3], "X:<89ABCDEF0");
+        assert_eq!(lines[0], format!("T:{:>10}", "0x0"));
+    }
+}
@@ -1,19 +1,30 @@
 use esp_idf_hal::gpio::OutputPin;
 use esp_idf_hal::modem::Modem;
 use esp_idf_hal::peripherals;
+use esp_idf_svc::log::EspLogger;
+use log::LevelFilter;
 
 use crate::hal::{cardputer_peripherals, CardputerPeripherals};
+use crate::terminal::{LogSink, ScreenLogger};
 
 pub fn init() {
     esp_idf_svc::sys::link_patches();
-    let _ = esp_idf_svc::log::EspLogger::initialize_default();
+    let _ = EspLogger::initialize_default();
 }
 
-pub fn take_cardputer(
-) -> (
-    CardputerPeripherals<impl OutputPin, impl OutputPin, impl OutputPin>,
-    Modem,
-) {
+/// Like `init`, but records at or above `level` also go to the returned sink,
+/// to be shown with `FbTerminal::show_log`. Everything still goes to the serial port.
+pub fn init_with_screen_log(level: LevelFilter) -> LogSink {
+    esp_idf_svc::sys::link_patches();
+    let sink = LogSink::new();
+    let logger = ScreenLogger::new(EspLogger::new(), sink.clone(), level);
+    if log::set_logger(Box::leak(Box::new(logger))).is_ok() {
+        log::set_max_level(level.max(LevelFilter::Info));
+    }
+    sink
+}
+
+pub fn take_cardputer() -> (CardputerPeripherals<impl OutputPin, impl OutputPin>, Modem) {
     let peripherals = peripherals::Peripherals::take().unwrap();
     let peripherals::Peripherals {
         pins,
@@ -25,5 +36,10 @@ pub fn take_cardputer(
     } = peripherals;
 
     let cardputer = cardputer_peripherals(pins, spi2, ledc, i2s0);
+    // Without an SD card this keeps the defaults.
+    cardputer.backlight.load_settings();
+    cardputer
+        .backlight
+        .start_idle_timer(cardputer.keyboard.activity());
     (cardputer, modem)
 }
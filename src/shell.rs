@@ -0,0 +1,758 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use crate::os::storage::DiskUsage;
+use crate::terminal::completion::{Completer, Completion};
+
+/// `cat` shows at most this much of a file.
+const MAX_CAT_BYTES: usize = 8 * 1024;
+/// `hexdump` shows at most this much of a file.
+const MAX_HEXDUMP_BYTES: usize = 1024;
+
+const COMMANDS: &[&str] = &[
+    "cat", "cd", "clear", "cp", "df", "help", "hexdump", "ls", "mkdir", "mv", "pwd", "rm", "run",
+];
+
+const HELP: &[&str] = &[
+    "ls [DIR]        list a directory",
+    "cd [DIR]        change directory",
+    "pwd             current directory",
+    "cat FILE        print a file",
+    "hexdump FILE    dump a file in hex",
+    "cp SRC DST      copy a file",
+    "mv SRC DST      move or rename",
+    "rm [-r] PATH    delete",
+    "mkdir DIR       create a directory",
+    "df              SD card usage",
+    "run FILE.bin    flash and start an app",
+    "clear           clear the screen",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Ls(Option<String>),
+    Cd(Option<String>),
+    Pwd,
+    Cat(String),
+    Hexdump(String),
+    Cp(String, String),
+    Mv(String, String),
+    Rm { path: String, recursive: bool },
+    Mkdir(String),
+    Df,
+    Run(String),
+    Clear,
+    Help,
+}
+
+impl Command {
+    /// Parses a command line. Arguments are separated by blanks and may be
+    /// quoted with `"` or `'`; a backslash escapes the next character.
+    /// A blank line gives `Ok(None)`.
+    pub fn parse(line: &str) -> Result<Option<Command>, ShellError> {
+        let args = split_args(line)?;
+        let Some((name, args)) = args.split_first() else {
+            return Ok(None);
+        };
+
+        let command = match (name.as_str(), args) {
+            ("ls", []) => Command::Ls(None),
+            ("ls", [dir]) => Command::Ls(Some(dir.clone())),
+            ("cd", []) => Command::Cd(None),
+            ("cd", [dir]) => Command::Cd(Some(dir.clone())),
+            ("pwd", []) => Command::Pwd,
+            ("cat", [file]) => Command::Cat(file.clone()),
+            ("hexdump", [file]) => Command::Hexdump(file.clone()),
+            ("cp", [src, dst]) => Command::Cp(src.clone(), dst.clone()),
+            ("mv", [src, dst]) => Command::Mv(src.clone(), dst.clone()),
+            ("rm", [path]) => Command::Rm {
+                path: path.clone(),
+                recursive: false,
+            },
+            ("rm", [flag, path]) if flag == "-r" => Command::Rm {
+                path: path.clone(),
+                recursive: true,
+            },
+            ("mkdir", [dir]) => Command::Mkdir(dir.clone()),
+            ("df", []) => Command::Df,
+            ("run", [file]) => Command::Run(file.clone()),
+            ("clear", []) => Command::Clear,
+            ("help", []) => Command::Help,
+            (name, _) => match usage(name) {
+                Some(usage) => return Err(ShellError::Usage(usage)),
+                None => return Err(ShellError::UnknownCommand(name.to_string())),
+            },
+        };
+        Ok(Some(command))
+    }
+}
+
+fn usage(name: &str) -> Option<&'static str> {
+    let line = HELP
+        .iter()
+        .find(|line| line.split(' ').next() == Some(name))?;
+    Some(line.split("  ").next().unwrap_or(line))
+}
+
+/// `line` as a single path argument, read with the same quoting as a command line.
+pub fn parse_path(line: &str) -> Result<String, ShellError> {
+    match split_args(line)?.as_slice() {
+        [path] => Ok(path.clone()),
+        _ => Err(ShellError::Usage("FILE")),
+    }
+}
+
+fn split_args(line: &str) -> Result<Vec<String>, ShellError> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => {
+                let escaped = chars.next().unwrap_or('\\');
+                arg.get_or_insert_with(String::new).push(escaped);
+            }
+            (c, Some(q)) if c == q => quote = None,
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (c, None) if c.is_whitespace() => args.extend(arg.take()),
+            (c, _) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(ShellError::UnterminatedQuote);
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+#[derive(Debug)]
+pub enum ShellError {
+    UnknownCommand(String),
+    Usage(&'static str),
+    UnterminatedQuote,
+    NotADirectory(PathBuf),
+    NotAnApp(PathBuf),
+    Unsupported(&'static str),
+    Io(PathBuf, io::Error),
+}
+
+impl ShellError {
+    pub fn to_lines(&self) -> Vec<String> {
+        match self {
+            ShellError::UnknownCommand(name) => {
+                vec![format!("{}: command not found (try help)", name)]
+            }
+            ShellError::Usage(usage) => vec![format!("usage: {}", usage)],
+            ShellError::UnterminatedQuote => vec!["Unterminated quote".to_string()],
+            ShellError::NotADirectory(path) => {
+                vec![format!("{}: not a directory", path.display())]
+            }
+            ShellError::NotAnApp(path) => vec![format!("{}: not a .bin file", path.display())],
+            ShellError::Unsupported(what) => vec![format!("{} is not available", what)],
+            ShellError::Io(path, err) => vec![format!("{}: {}", path.display(), err)],
+        }
+    }
+}
+
+/// What a command wants done besides printing lines.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Output {
+    Lines(Vec<String>),
+    Clear,
+    /// Flash this app and reboot into it.
+    Run(PathBuf),
+}
+
+/// File commands relative to a current directory.
+///
+/// Only `std::fs` is used, so the same code runs on the SD card and on a
+/// host directory. `df` needs a `disk_usage` source, see `set_disk_usage`.
+pub struct Shell {
+    home: PathBuf,
+    cwd: Rc<RefCell<PathBuf>>,
+    /// Columns available for output, used to size hexdump lines.
+    width: usize,
+    disk_usage: Option<Box<dyn Fn() -> io::Result<DiskUsage>>>,
+}
+
+impl Shell {
+    /// A shell that starts in, and returns with a bare `cd` to, `home`.
+    pub fn new(home: impl Into<PathBuf>) -> Self {
+        let home = home.into();
+        Self {
+            cwd: Rc::new(RefCell::new(home.clone())),
+            home,
+            width: 29,
+            disk_usage: None,
+        }
+    }
+
+    pub fn cwd(&self) -> PathBuf {
+        self.cwd.borrow().clone()
+    }
+
+    pub fn set_width(&mut self, width: usize) {
+        self.width = width;
+    }
+
+    pub fn set_disk_usage(&mut self, disk_usage: impl Fn() -> io::Result<DiskUsage> + 'static) {
+        self.disk_usage = Some(Box::new(disk_usage));
+    }
+
+    /// Completes command names and paths, following the shell's `cd`s.
+    pub fn completer(&self) -> PathCompleter {
+        PathCompleter {
+            cwd: self.cwd.clone(),
+            commands: true,
+        }
+    }
+
+    /// Completes file names only, for a line that holds just a path.
+    pub fn file_completer(&self) -> PathCompleter {
+        PathCompleter {
+            cwd: self.cwd.clone(),
+            commands: false,
+        }
+    }
+
+    /// `path` relative to the current directory, with `.` and `..` resolved.
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        resolve(&self.cwd.borrow(), path)
+    }
+
+    pub fn execute(&mut self, command: Command) -> Result<Output, ShellError> {
+        let lines = match command {
+            Command::Ls(dir) => self.ls(dir.as_deref().unwrap_or("."))?,
+            Command::Cd(dir) => {
+                let dir = match dir {
+                    Some(dir) => self.resolve(&dir),
+                    None => self.home.clone(),
+                };
+                if !dir.is_dir() {
+                    return Err(ShellError::NotADirectory(dir));
+                }
+                *self.cwd.borrow_mut() = dir;
+                Vec::new()
+            }
+            Command::Pwd => vec![self.cwd().display().to_string()],
+            Command::Cat(file) => self.cat(&file)?,
+            Command::Hexdump(file) => self.hexdump(&file)?,
+            Command::Cp(src, dst) => {
+                let (src, dst) = self.source_and_target(&src, &dst);
+                fs::copy(&src, &dst).map_err(|err| ShellError::Io(src, err))?;
+                Vec::new()
+            }
+            Command::Mv(src, dst) => {
+                let (src, dst) = self.source_and_target(&src, &dst);
+                fs::rename(&src, &dst).map_err(|err| ShellError::Io(src, err))?;
+                Vec::new()
+            }
+            Command::Rm { path, recursive } => {
+                let path = self.resolve(&path);
+                let result = if !path.is_dir() {
+                    fs::remove_file(&path)
+                } else if recursive {
+                    fs::remove_dir_all(&path)
+                } else {
+                    fs::remove_dir(&path)
+                };
+                result.map_err(|err| ShellError::Io(path, err))?;
+                Vec::new()
+            }
+            Command::Mkdir(dir) => {
+                let dir = self.resolve(&dir);
+                fs::create_dir_all(&dir).map_err(|err| ShellError::Io(dir, err))?;
+                Vec::new()
+            }
+            Command::Df => self.df()?,
+            Command::Run(file) => {
+                let file = self.resolve(&file);
+                let is_bin = file
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("bin"));
+                if !is_bin || !file.is_file() {
+                    return Err(ShellError::NotAnApp(file));
+                }
+                return Ok(Output::Run(file));
+            }
+            Command::Clear => return Ok(Output::Clear),
+            Command::Help => HELP.iter().map(|line| line.to_string()).collect(),
+        };
+        Ok(Output::Lines(lines))
+    }
+
+    /// `cp` and `mv` put the source inside `dst` when it is a directory.
+    fn source_and_target(&self, src: &str, dst: &str) -> (PathBuf, PathBuf) {
+        let src = self.resolve(src);
+        let mut dst = self.resolve(dst);
+        if dst.is_dir() {
+            if let Some(name) = src.file_name() {
+                dst.push(name);
+            }
+        }
+        (src, dst)
+    }
+
+    fn ls(&self, dir: &str) -> Result<Vec<String>, ShellError> {
+        let dir = self.resolve(dir);
+        if dir.is_file() {
+            let size = fs::metadata(&dir).map(|meta| meta.len()).unwrap_or(0);
+            return Ok(vec![format!("{}  {}", dir.display(), format_size(size))]);
+        }
+
+        let entries = fs::read_dir(&dir).map_err(|err| ShellError::Io(dir.clone(), err))?;
+        let mut entries: Vec<(String, Option<u64>)> = entries
+            .flatten()
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let meta = entry.metadata().ok();
+                let size = meta.filter(|meta| meta.is_file()).map(|meta| meta.len());
+                (name, size)
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            (a.1.is_some(), a.0.to_lowercase()).cmp(&(b.1.is_some(), b.0.to_lowercase()))
+        });
+
+        Ok(entries
+            .into_iter()
+            .map(|(name, size)| match size {
+                Some(size) => format!("{}  {}", name, format_size(size)),
+                None => format!("{}/", name),
+            })
+            .collect())
+    }
+
+    fn read_start(&self, file: &str, limit: usize) -> Result<(Vec<u8>, u64), ShellError> {
+        let path = self.resolve(file);
+        let io_err = |err| ShellError::Io(path.clone(), err);
+        let file = fs::File::open(&path).map_err(io_err)?;
+        let len = file.metadata().map_err(io_err)?.len();
+        let mut data = Vec::new();
+        file.take(limit as u64)
+            .read_to_end(&mut data)
+            .map_err(io_err)?;
+        Ok((data, len))
+    }
+
+    fn cat(&self, file: &str) -> Result<Vec<String>, ShellError> {
+        let (data, len) = self.read_start(file, MAX_CAT_BYTES)?;
+        let mut lines: Vec<String> = String::from_utf8_lossy(&data)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        if len > data.len() as u64 {
+            lines.push(format!("... {} more bytes", len - data.len() as u64));
+        }
+        Ok(lines)
+    }
+
+    fn hexdump(&self, file: &str) -> Result<Vec<String>, ShellError> {
+        let (data, len) = self.read_start(file, MAX_HEXDUMP_BYTES)?;
+        let mut lines = hexdump_lines(&data, self.width);
+        if len > data.len() as u64 {
+            lines.push(format!("... {} more bytes", len - data.len() as u64));
+        }
+        Ok(lines)
+    }
+
+    fn df(&self) -> Result<Vec<String>, ShellError> {
+        let disk_usage = self
+            .disk_usage
+            .as_ref()
+            .ok_or(ShellError::Unsupported("df"))?;
+        let DiskUsage { total, free } =
+            disk_usage().map_err(|err| ShellError::Io(self.home.clone(), err))?;
+        Ok(vec![
+            format!("Size  {}", format_size(total)),
+            format!("Used  {}", format_size(total.saturating_sub(free))),
+            format!("Free  {}", format_size(free)),
+        ])
+    }
+}
+
+/// Offset, bytes and their ASCII, as many bytes per line as fit in `width` columns.
+pub fn hexdump_lines(data: &[u8], width: usize) -> Vec<String> {
+    // "0000 " then three columns for the hex and one for the ASCII of each byte.
+    let per_line = (width.saturating_sub(5) / 4).max(1);
+    data.chunks(per_line)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: String = chunk.iter().map(|b| format!("{:02x} ", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!(
+                "{:04x} {:<w$}{}",
+                i * per_line,
+                hex,
+                ascii,
+                w = per_line * 3
+            )
+        })
+        .collect()
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "K", "M", "G"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[0])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+fn resolve(cwd: &Path, path: &str) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in cwd.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// Tab completion for the shell: command names in the first word, file
+/// names after that. Directories complete with a trailing `/`, and blanks
+/// and quotes in names are escaped with a backslash.
+pub struct PathCompleter {
+    cwd: Rc<RefCell<PathBuf>>,
+    /// Whether the first word is a command name.
+    commands: bool,
+}
+
+impl Completer for PathCompleter {
+    fn complete(&self, line: &str, cursor: usize) -> Completion {
+        let (start, word) = arg_at(line, cursor);
+
+        let first_word = line.chars().take(start).all(char::is_whitespace);
+        if first_word && self.commands {
+            let candidates = COMMANDS
+                .iter()
+                .filter(|command| command.starts_with(&word))
+                .map(|command| command.to_string())
+                .collect();
+            return Completion { start, candidates };
+        }
+
+        let (dir, prefix) = match word.rfind('/') {
+            Some(index) => word.split_at(index + 1),
+            None => ("", word.as_str()),
+        };
+        let Ok(entries) = fs::read_dir(resolve(&self.cwd.borrow(), dir)) else {
+            return Completion::default();
+        };
+        let mut candidates: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !name.starts_with(prefix) {
+                    return None;
+                }
+                let slash = if entry.path().is_dir() { "/" } else { "" };
+                Some(escape_arg(&format!("{}{}{}", dir, name, slash)))
+            })
+            .collect();
+        candidates.sort();
+        Completion { start, candidates }
+    }
+}
+
+/// Where the argument ending at `cursor` starts, and its text with the
+/// quotes and escapes taken out the way `split_args` does.
+fn arg_at(line: &str, cursor: usize) -> (usize, String) {
+    let mut start = 0;
+    let mut arg = String::new();
+    let mut quote = None;
+    let mut chars = line.chars().take(cursor).enumerate();
+    while let Some((i, c)) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => arg.push(chars.next().map_or('\\', |(_, c)| c)),
+            (c, Some(q)) if c == q => quote = None,
+            ('"' | '\'', None) => quote = Some(c),
+            (c, None) if c.is_whitespace() => {
+                start = i + 1;
+                arg.clear();
+            }
+            (c, _) => arg.push(c),
+        }
+    }
+    (start, arg)
+}
+
+/// `arg` with a backslash in front of blanks, quotes and backslashes, so
+/// `split_args` reads it back as one argument.
+fn escape_arg(arg: &str) -> String {
+    let mut escaped = String::with_capacity(arg.len());
+    for c in arg.chars() {
+        if c.is_whitespace() || matches!(c, '"' | '\'' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os::storage::temp_dir;
+
+    /// A directory with a few files to run the commands on.
+    fn files(name: &str) -> PathBuf {
+        let dir = temp_dir(&format!("shell-{}", name));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("notes.txt"), b"hello\nworld\n").unwrap();
+        fs::write(dir.join("my file.txt"), b"spaced").unwrap();
+        fs::write(dir.join("docs/readme.md"), b"# readme").unwrap();
+        dir
+    }
+
+    fn run(shell: &mut Shell, line: &str) -> Result<Output, ShellError> {
+        shell.execute(Command::parse(line)?.unwrap())
+    }
+
+    fn lines(shell: &mut Shell, line: &str) -> Vec<String> {
+        match run(shell, line).unwrap() {
+            Output::Lines(lines) => lines,
+            output => panic!("{}: {:?}", line, output),
+        }
+    }
+
+    fn complete(shell: &Shell, line: &str) -> Completion {
+        shell.completer().complete(line, line.chars().count())
+    }
+
+    #[test]
+    fn split_args_quoting() {
+        let args = |line| split_args(line).unwrap();
+        assert_eq!(args("  cp  a   b "), ["cp", "a", "b"]);
+        assert_eq!(args(r#"cat "my file.txt""#), ["cat", "my file.txt"]);
+        assert_eq!(args("cat 'it''s' x"), ["cat", "its", "x"]);
+        assert_eq!(
+            args(r#"cat "say \"hi\"" 'a"b'"#),
+            ["cat", r#"say "hi""#, r#"a"b"#]
+        );
+        assert_eq!(args(r"cat my\ file.txt"), ["cat", "my file.txt"]);
+        assert_eq!(args(r#"rm "" x"#), ["rm", "", "x"]);
+        assert!(args("").is_empty());
+        assert!(matches!(
+            split_args("cat 'oops"),
+            Err(ShellError::UnterminatedQuote)
+        ));
+    }
+
+    #[test]
+    fn parse_commands() {
+        let parse = |line| Command::parse(line).unwrap();
+        assert_eq!(parse("   "), None);
+        assert_eq!(parse("ls"), Some(Command::Ls(None)));
+        assert_eq!(
+            parse("ls docs"),
+            Some(Command::Ls(Some("docs".to_string())))
+        );
+        assert_eq!(
+            parse("mv 'a b' c"),
+            Some(Command::Mv("a b".to_string(), "c".to_string()))
+        );
+        assert_eq!(
+            parse("rm -r docs"),
+            Some(Command::Rm {
+                path: "docs".to_string(),
+                recursive: true
+            })
+        );
+        assert!(matches!(
+            Command::parse("cp a"),
+            Err(ShellError::Usage("cp SRC DST"))
+        ));
+        assert!(matches!(
+            Command::parse("frob"),
+            Err(ShellError::UnknownCommand(name)) if name == "frob"
+        ));
+    }
+
+    #[test]
+    fn resolve_paths() {
+        let cwd = Path::new("/sdcard/apps");
+        assert_eq!(resolve(cwd, "x.bin"), Path::new("/sdcard/apps/x.bin"));
+        assert_eq!(resolve(cwd, "../docs/./a"), Path::new("/sdcard/docs/a"));
+        assert_eq!(resolve(cwd, "/tmp/../etc"), Path::new("/etc"));
+        assert_eq!(resolve(cwd, "../../../.."), Path::new("/"));
+    }
+
+    #[test]
+    fn cd_pwd_and_ls() {
+        let dir = files("ls");
+        let mut shell = Shell::new(&dir);
+        assert_eq!(
+            lines(&mut shell, "ls"),
+            ["docs/", "my file.txt  6B", "notes.txt  12B"]
+        );
+
+        lines(&mut shell, "cd docs");
+        assert_eq!(shell.cwd(), dir.join("docs"));
+        assert_eq!(lines(&mut shell, "ls"), ["readme.md  8B"]);
+        assert_eq!(lines(&mut shell, "cat ../notes.txt"), ["hello", "world"]);
+
+        lines(&mut shell, "cd");
+        assert_eq!(lines(&mut shell, "pwd"), [dir.display().to_string()]);
+        assert!(matches!(
+            run(&mut shell, "cd notes.txt"),
+            Err(ShellError::NotADirectory(_))
+        ));
+    }
+
+    #[test]
+    fn cp_mv_rm_mkdir() {
+        let dir = files("files");
+        let mut shell = Shell::new(&dir);
+
+        lines(&mut shell, "cp notes.txt copy.txt");
+        assert_eq!(fs::read(dir.join("copy.txt")).unwrap(), b"hello\nworld\n");
+        lines(&mut shell, "cp 'my file.txt' docs");
+        assert_eq!(fs::read(dir.join("docs/my file.txt")).unwrap(), b"spaced");
+
+        lines(&mut shell, "mv copy.txt docs/moved.txt");
+        assert!(!dir.join("copy.txt").exists());
+        assert!(dir.join("docs/moved.txt").is_file());
+
+        lines(&mut shell, "mkdir a/b/c");
+        assert!(dir.join("a/b/c").is_dir());
+
+        lines(&mut shell, "rm notes.txt");
+        assert!(!dir.join("notes.txt").exists());
+        assert!(matches!(
+            run(&mut shell, "rm docs"),
+            Err(ShellError::Io(..))
+        ));
+        assert!(dir.join("docs").is_dir());
+        lines(&mut shell, "rm -r docs");
+        assert!(!dir.join("docs").exists());
+    }
+
+    #[test]
+    fn run_needs_a_bin_file() {
+        let dir = files("run");
+        fs::write(dir.join("app.BIN"), b"app").unwrap();
+        let mut shell = Shell::new(&dir);
+        assert_eq!(
+            run(&mut shell, "run app.BIN").unwrap(),
+            Output::Run(dir.join("app.BIN"))
+        );
+        assert!(matches!(
+            run(&mut shell, "run notes.txt"),
+            Err(ShellError::NotAnApp(_))
+        ));
+        assert!(matches!(
+            run(&mut shell, "run gone.bin"),
+            Err(ShellError::NotAnApp(_))
+        ));
+    }
+
+    #[test]
+    fn hexdump_fits_the_width() {
+        let data: Vec<u8> = (b'A'..b'A' + 10).chain([0, b' ', 0xff]).collect();
+        assert_eq!(
+            hexdump_lines(&data, 29),
+            [
+                "0000 41 42 43 44 45 46 ABCDEF",
+                "0006 47 48 49 4a 00 20 GHIJ. ",
+                "000c ff                .",
+            ]
+        );
+        assert_eq!(hexdump_lines(b"ab", 0), ["0000 61 a", "0001 62 b"]);
+        assert!(hexdump_lines(&[], 29).is_empty());
+    }
+
+    #[test]
+    fn complete_commands_and_paths() {
+        let dir = files("complete");
+        let shell = Shell::new(&dir);
+
+        assert_eq!(
+            complete(&shell, "c").candidates,
+            ["cat", "cd", "clear", "cp"]
+        );
+        let completion = complete(&shell, "cat no");
+        assert_eq!(completion.start, 4);
+        assert_eq!(completion.candidates, ["notes.txt"]);
+        assert_eq!(complete(&shell, "ls d").candidates, ["docs/"]);
+        assert_eq!(
+            complete(&shell, "cat docs/r").candidates,
+            ["docs/readme.md"]
+        );
+        assert!(complete(&shell, "cat nowhere/").candidates.is_empty());
+    }
+
+    #[test]
+    fn completions_escape_blanks() {
+        let dir = files("escape");
+        let shell = Shell::new(&dir);
+
+        assert_eq!(complete(&shell, "cat m").candidates, [r"my\ file.txt"]);
+        let completion = complete(&shell, r"cat my\ f");
+        assert_eq!(completion.start, 4);
+        assert_eq!(completion.candidates, [r"my\ file.txt"]);
+        let completion = complete(&shell, "cat 'my f");
+        assert_eq!(completion.start, 4);
+        assert_eq!(completion.candidates, [r"my\ file.txt"]);
+
+        let line = format!("cat {}", completion.candidates[0]);
+        assert_eq!(
+            Command::parse(&line).unwrap(),
+            Some(Command::Cat("my file.txt".to_string()))
+        );
+    }
+
+    #[test]
+    fn completed_file_names_parse_back() {
+        let dir = files("file-names");
+        let shell = Shell::new(&dir);
+
+        let completion = shell.file_completer().complete("my", 2);
+        assert_eq!(completion.candidates, [r"my\ file.txt"]);
+        let path = parse_path(&completion.candidates[0]).unwrap();
+        assert_eq!(shell.resolve(&path), dir.join("my file.txt"));
+
+        assert_eq!(parse_path(" 'docs/a b' ").unwrap(), "docs/a b");
+        assert!(matches!(parse_path("a b"), Err(ShellError::Usage(_))));
+        assert!(matches!(parse_path(""), Err(ShellError::Usage(_))));
+        assert!(matches!(
+            parse_path("'oops"),
+            Err(ShellError::UnterminatedQuote)
+        ));
+    }
+
+    #[test]
+    fn completion_follows_cd() {
+        let dir = files("follow");
+        let mut shell = Shell::new(&dir);
+        lines(&mut shell, "cd docs");
+        assert_eq!(complete(&shell, "cat ").candidates, ["readme.md"]);
+    }
+}
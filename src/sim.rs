@@ -0,0 +1,361 @@
+use std::convert::Infallible;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use embedded_graphics::pixelcolor::{raw::RawU16, Rgb565, Rgb888};
+use embedded_graphics::prelude::*;
+
+use crate::backlight::BacklightSettings;
+use crate::dirty::Region;
+use crate::display_driver::{FbWriteError, FramebufferTarget};
+use crate::keyboard::{Key, KeyTiming, Keyboard, ScriptedKeyMatrix};
+use crate::os::app::AppContext;
+use crate::os::launcher::{Launcher, LauncherHost};
+use crate::os::status::StatusSnapshot;
+use crate::os::ui::render_status;
+use crate::swapchain::{DoubleBuffer, OwnedDoubleBuffer};
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// One screen of pixels, row by row.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Frame {
+    pixels: Vec<Rgb565>,
+}
+
+impl Frame {
+    /// An all black frame.
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![Rgb565::BLACK; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    /// A frame as the swapchain hands it to the panel, big-endian.
+    pub fn from_framebuffer(buf: &[u16]) -> Self {
+        Self {
+            pixels: buf
+                .iter()
+                .map(|&raw| Rgb565::from(RawU16::new(u16::from_be(raw))))
+                .collect(),
+        }
+    }
+
+    pub fn pixels(&self) -> &[Rgb565] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb565 {
+        self.pixels[y * SCREEN_WIDTH + x]
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            SCREEN_WIDTH as u32,
+            SCREEN_HEIGHT as u32,
+        );
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|&pixel| {
+                let rgb = Rgb888::from(pixel);
+                [rgb.r(), rgb.g(), rgb.b()]
+            })
+            .collect();
+        encoder.write_header()?.write_image_data(&data)?;
+        Ok(())
+    }
+
+    /// Reads a PNG written by `save_png`.
+    pub fn load_png(path: &Path) -> io::Result<Self> {
+        let mut reader = png::Decoder::new(File::open(path)?).read_info()?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data)?;
+        if (info.width as usize, info.height as usize) != (SCREEN_WIDTH, SCREEN_HEIGHT)
+            || info.color_type != png::ColorType::Rgb
+            || info.bit_depth != png::BitDepth::Eight
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "expected a {}x{} RGB image, found {}x{} {:?}",
+                    SCREEN_WIDTH, SCREEN_HEIGHT, info.width, info.height, info.color_type
+                ),
+            ));
+        }
+
+        let pixels = data[..info.buffer_size()]
+            .chunks_exact(3)
+            .map(|rgb| Rgb565::from(Rgb888::new(rgb[0], rgb[1], rgb[2])))
+            .collect();
+        Ok(Self { pixels })
+    }
+
+    /// How many pixels differ from `other`.
+    pub fn diff_count(&self, other: &Frame) -> usize {
+        self.pixels
+            .iter()
+            .zip(&other.pixels)
+            .filter(|(a, b)| a != b)
+            .count()
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OriginDimensions for Frame {
+    fn size(&self) -> Size {
+        Size::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+    }
+}
+
+impl DrawTarget for Frame {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let (x, y) = (point.x as usize, point.y as usize);
+            if point.x >= 0 && point.y >= 0 && x < SCREEN_WIDTH && y < SCREEN_HEIGHT {
+                self.pixels[y * SCREEN_WIDTH + x] = color;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares `frame` with the reference image `dir/<name>.png`.
+///
+/// On a mismatch the frame is written next to the reference as
+/// `<name>.actual.png` and this panics. With `UPDATE_SNAPSHOTS=1` set, the
+/// frame becomes the new reference instead.
+pub fn assert_snapshot(dir: &Path, name: &str, frame: &Frame) {
+    let reference = dir.join(format!("{}.png", name));
+    let actual = dir.join(format!("{}.actual.png", name));
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some_and(|value| value == "1") {
+        fs::create_dir_all(dir).unwrap();
+        frame.save_png(&reference).unwrap();
+        let _ = fs::remove_file(&actual);
+        return;
+    }
+
+    let expected = match Frame::load_png(&reference) {
+        Ok(expected) => expected,
+        Err(err) => panic!(
+            "{}: {}; run with UPDATE_SNAPSHOTS=1 to create it",
+            reference.display(),
+            err
+        ),
+    };
+    let diff = frame.diff_count(&expected);
+    if diff > 0 {
+        frame.save_png(&actual).unwrap();
+        panic!(
+            "{} pixels differ from {}, see {}",
+            diff,
+            reference.display(),
+            actual.display()
+        );
+    }
+    let _ = fs::remove_file(&actual);
+}
+
+#[derive(Default)]
+struct DisplayState {
+    frames: Vec<Frame>,
+    regions: Vec<Region>,
+    output_dir: Option<PathBuf>,
+}
+
+/// A `FramebufferTarget` that keeps every frame that differs from the one
+/// before, and also writes it as a PNG when given a directory.
+/// Clones share the same frames.
+#[derive(Clone, Default)]
+pub struct SimDisplay {
+    state: Arc<Mutex<DisplayState>>,
+}
+
+impl SimDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Frames go to `frame-0001.png`, `frame-0002.png`, ... in `dir`.
+    pub fn with_output_dir(dir: impl Into<PathBuf>) -> Self {
+        let display = Self::new();
+        display.state.lock().unwrap().output_dir = Some(dir.into());
+        display
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.state.lock().unwrap().frames.len()
+    }
+
+    pub fn last_frame(&self) -> Option<Frame> {
+        self.state.lock().unwrap().frames.last().cloned()
+    }
+
+    pub fn frames(&self) -> Vec<Frame> {
+        self.state.lock().unwrap().frames.clone()
+    }
+
+    /// The part of the screen each update rewrote, as the panel would get it.
+    pub fn regions(&self) -> Vec<Region> {
+        self.state.lock().unwrap().regions.clone()
+    }
+}
+
+impl FramebufferTarget for SimDisplay {
+    fn eat_framebuffer(&mut self, buf: &[u16]) -> Result<(), FbWriteError> {
+        let frame = Frame::from_framebuffer(buf);
+        let mut state = self.state.lock().unwrap();
+        if state.frames.last() == Some(&frame) {
+            return Ok(());
+        }
+
+        if let Some(dir) = &state.output_dir {
+            let path = dir.join(format!("frame-{:04}.png", state.frames.len() + 1));
+            frame.save_png(&path).map_err(|_| FbWriteError::Error)?;
+        }
+        state.frames.push(frame);
+        Ok(())
+    }
+
+    fn eat_region(
+        &mut self,
+        buf: &[u16],
+        _width: usize,
+        region: Region,
+    ) -> Result<(), FbWriteError> {
+        self.state.lock().unwrap().regions.push(region);
+        self.eat_framebuffer(buf)
+    }
+}
+
+/// Fixed status texts, so frames come out the same on every run.
+#[derive(Default)]
+struct SimHost {
+    launched: Vec<PathBuf>,
+    backlight: BacklightSettings,
+}
+
+impl LauncherHost for SimHost {
+    fn status(&self) -> StatusSnapshot {
+        StatusSnapshot {
+            clock_text: "12:00:00".to_string(),
+            wifi_text: "WiFi sim".to_string(),
+            battery_text: "Batt 100%".to_string(),
+        }
+    }
+
+    /// Nothing is flashed; the path is only recorded.
+    fn launch(
+        &mut self,
+        buffers: &mut DoubleBuffer<SCREEN_WIDTH, SCREEN_HEIGHT>,
+        path: &Path,
+    ) -> Result<(), Vec<String>> {
+        let name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("app.bin");
+        render_status(buffers, "Launching", &[name], None);
+        self.launched.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// There is no backlight; the setting is kept but not saved.
+    fn change_brightness(&mut self, steps: i32) -> Result<(), Vec<String>> {
+        self.backlight.step_brightness(steps);
+        Ok(())
+    }
+}
+
+/// The OS launcher on the host: a directory stands in for `/sdcard`, keys
+/// come from a script and frames go to a `SimDisplay`.
+pub struct Simulator {
+    buffers: OwnedDoubleBuffer<SCREEN_WIDTH, SCREEN_HEIGHT>,
+    keyboard: Keyboard<ScriptedKeyMatrix>,
+    display: SimDisplay,
+    launcher: Launcher,
+    host: SimHost,
+}
+
+impl Simulator {
+    /// Starts in `sd_root/apps` when it exists, like the device does in `/sdcard/apps`.
+    pub fn new(sd_root: impl Into<PathBuf>, display: SimDisplay) -> Self {
+        let root = sd_root.into();
+        let apps = root.join("apps");
+        let start = if apps.is_dir() { apps } else { root.clone() };
+
+        let mut buffers = OwnedDoubleBuffer::new();
+        buffers.present_inline(display.clone());
+
+        let mut keyboard = Keyboard::with_matrix(ScriptedKeyMatrix::new());
+        keyboard.set_timing(KeyTiming::RAW);
+
+        let mut launcher = Launcher::new(root, start, AppContext::new(true, true));
+        launcher.refresh();
+
+        Self {
+            buffers,
+            keyboard,
+            display,
+            launcher,
+            host: SimHost::default(),
+        }
+    }
+
+    /// Queues a press and release of `key`.
+    pub fn tap(&mut self, key: Key) {
+        self.keyboard.matrix_mut().tap(key);
+    }
+
+    pub fn tap_all(&mut self, keys: &[Key]) {
+        self.keyboard.matrix_mut().tap_all(keys);
+    }
+
+    /// Runs the launcher until every queued key has been read, then draws
+    /// the screen it ended up on.
+    pub fn run(&mut self) {
+        while !self.keyboard.matrix_mut().is_finished() {
+            self.step();
+        }
+        self.step();
+    }
+
+    fn step(&mut self) {
+        self.launcher
+            .step(&mut self.buffers, &mut self.keyboard, &mut self.host);
+    }
+
+    pub fn launcher(&self) -> &Launcher {
+        &self.launcher
+    }
+
+    pub fn display(&self) -> &SimDisplay {
+        &self.display
+    }
+
+    /// The apps that would have been flashed, in order.
+    pub fn launched(&self) -> &[PathBuf] {
+        &self.host.launched
+    }
+
+    /// The backlight settings as the launcher's brightness keys left them.
+    pub fn backlight(&self) -> BacklightSettings {
+        self.host.backlight
+    }
+}
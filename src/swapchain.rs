@@ -4,23 +4,115 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use display_interface_spi::SPIInterface;
 use embedded_gfx::framebuffer::DmaReadyFramebuffer;
-use esp_idf_hal::{
-    gpio::{Output, OutputPin, PinDriver},
-    spi::{SpiDeviceDriver, SpiDriver},
-    task::thread::ThreadSpawnConfiguration,
-};
+#[cfg(target_os = "espidf")]
+use esp_idf_hal::{cpu::Core, task::thread::ThreadSpawnConfiguration};
 use log::info;
 
-use crate::display_driver;
+use crate::dirty::DirtyTracker;
+use crate::display_driver::{FbWriteError, FramebufferTarget};
+
+/// Starts the thread that presents frames. `presenter` returns once the
+/// `DoubleBuffer` that started it is dropped, and that drop waits for it to
+/// return before the buffers may be freed.
+pub trait ThreadSpawner {
+    fn spawn(&self, presenter: Box<dyn FnOnce() + Send + 'static>);
+}
+
+/// A plain `std::thread`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdSpawner;
+
+impl ThreadSpawner for StdSpawner {
+    fn spawn(&self, presenter: Box<dyn FnOnce() + Send + 'static>) {
+        std::thread::Builder::new()
+            .name("fb writer".to_string())
+            .spawn(presenter)
+            .unwrap();
+    }
+}
+
+/// A FreeRTOS thread pinned to a core, so drawing and SPI transfers overlap.
+#[cfg(target_os = "espidf")]
+#[derive(Debug, Clone, Copy)]
+pub struct EspIdfSpawner {
+    pub core: Core,
+    pub stack_size: usize,
+}
+
+#[cfg(target_os = "espidf")]
+impl Default for EspIdfSpawner {
+    fn default() -> Self {
+        Self {
+            core: Core::Core1,
+            stack_size: 10240,
+        }
+    }
+}
+
+#[cfg(target_os = "espidf")]
+impl ThreadSpawner for EspIdfSpawner {
+    fn spawn(&self, presenter: Box<dyn FnOnce() + Send + 'static>) {
+        ThreadSpawnConfiguration {
+            name: Some(b"fb writer\0"),
+            pin_to_core: Some(self.core),
+            stack_size: self.stack_size,
+            ..Default::default()
+        }
+        .set()
+        .unwrap();
+
+        std::thread::spawn(presenter);
+
+        ThreadSpawnConfiguration::default().set().unwrap();
+    }
+}
+
+/// What `DoubleBuffer::start_thread` uses.
+#[cfg(target_os = "espidf")]
+pub type PlatformSpawner = EspIdfSpawner;
+#[cfg(not(target_os = "espidf"))]
+pub type PlatformSpawner = StdSpawner;
+
+/// Sends frames to a target, only the part that changed since the last one.
+struct Presenter<T> {
+    target: T,
+    tracker: DirtyTracker,
+}
+
+impl<T: FramebufferTarget> Presenter<T> {
+    fn new(target: T, width: usize, height: usize) -> Self {
+        Self {
+            target,
+            tracker: DirtyTracker::new(width, height),
+        }
+    }
+
+    fn present(&mut self, buf: &[u16], width: usize) -> Result<(), FbWriteError> {
+        let Some(region) = self.tracker.update(buf) else {
+            return Ok(());
+        };
+        let result = self.target.eat_region(buf, width, region);
+        if result.is_err() {
+            // The panel may show anything now.
+            self.tracker.invalidate();
+        }
+        result
+    }
+}
 
 pub struct DoubleBuffer<const W: usize, const H: usize> {
     sender: Option<std::sync::mpsc::Sender<usize>>,
+    /// Set by `present_inline`: frames go straight to it from `send_framebuffer`.
+    inline: Option<Presenter<Box<dyn FramebufferTarget>>>,
+    /// The last error from presenting a frame, until `take_error`.
+    error: Arc<Mutex<Option<FbWriteError>>>,
     toggle: bool,
     fbuf0: DmaReadyFramebuffer<W, H>,
     fbuf1: DmaReadyFramebuffer<W, H>,
     mutex: Arc<Mutex<bool>>,
+    /// Disconnected when the writer thread is done with the buffers.
+    stopped: Option<std::sync::mpsc::Receiver<()>>,
 }
 
 impl<const W: usize, const H: usize> DoubleBuffer<W, H> {
@@ -30,65 +122,69 @@ impl<const W: usize, const H: usize> DoubleBuffer<W, H> {
 
         Self {
             sender: None,
+            inline: None,
+            error: Arc::new(Mutex::new(None)),
             toggle: false,
             fbuf0,
             fbuf1,
             mutex: Arc::new(Mutex::new(true)),
+            stopped: None,
         }
     }
 
-    pub fn start_thread(
+    /// Presents every frame sent with `send_framebuffer` to `display` from a
+    /// writer thread started by the platform's spawner.
+    pub fn start_thread(&mut self, display: impl FramebufferTarget + Send + 'static) {
+        self.start_thread_with(display, &PlatformSpawner::default());
+    }
+
+    /// Like `start_thread`, with the writer thread started by `spawner`.
+    pub fn start_thread_with(
         &mut self,
-        display: display_driver::ST7789<
-            SPIInterface<
-                SpiDeviceDriver<'static, SpiDriver<'static>>,
-                PinDriver<'static, impl OutputPin + esp_idf_hal::gpio::Pin, Output>,
-            >,
-            esp_idf_hal::gpio::PinDriver<
-                'static,
-                impl OutputPin + esp_idf_hal::gpio::Pin + esp_idf_hal::gpio::Pin,
-                esp_idf_hal::gpio::Output,
-            >,
-            esp_idf_hal::gpio::PinDriver<
-                'static,
-                impl OutputPin + esp_idf_hal::gpio::Pin,
-                esp_idf_hal::gpio::Output,
-            >,
-        >,
+        display: impl FramebufferTarget + Send + 'static,
+        spawner: &impl ThreadSpawner,
     ) {
         info!("Starting fb writer thread");
-        let (send, receive) = std::sync::mpsc::channel();
+        let (send, receive) = std::sync::mpsc::channel::<usize>();
 
         self.sender = Some(send);
+        let (stopping, stopped) = std::sync::mpsc::channel::<()>();
+        self.stopped = Some(stopped);
 
         let mutex2 = self.mutex.clone();
-        let mut display = display;
+        let error = self.error.clone();
+        let mut presenter = Presenter::new(display, W, H);
 
-        ThreadSpawnConfiguration {
-            name: Some(b"fb writer\0"),
-            pin_to_core: Some(esp_idf_svc::hal::cpu::Core::Core1),
-            stack_size: 10240,
-            ..Default::default()
-        }
-        .set()
-        .unwrap();
+        spawner.spawn(Box::new(move || {
+            let _stopping = stopping;
+            while let Ok(ptr) = receive.recv() {
+                unsafe {
+                    let _lock = mutex2.lock().unwrap();
 
-        std::thread::spawn(move || loop {
-            let ptr = receive.recv().unwrap();
-            unsafe {
-                let _lock = mutex2.lock().unwrap();
+                    let ptr = ptr as *mut u16;
+                    let ptr = std::slice::from_raw_parts_mut(ptr, W * H);
 
-                let ptr = ptr as *mut u16;
-                let ptr = std::slice::from_raw_parts_mut(ptr, W * H);
+                    // takes about 12ms for a full frame
+                    // 83 fps limit
+                    if let Err(err) = presenter.present(ptr, W) {
+                        *error.lock().unwrap() = Some(err);
+                    }
+                    ptr.fill(0); // 2.2ms
+                };
+            }
+        }));
+    }
 
-                // takes about 12ms
-                // 83 fps limit
-                display.eat_framebuffer(ptr).unwrap();
-                ptr.fill(0); // 2.2ms
-            };
-        });
+    /// Presents each frame to `target` on the thread calling `send_framebuffer`,
+    /// instead of on a writer thread. Used by the simulator.
+    pub fn present_inline(&mut self, target: impl FramebufferTarget + 'static) {
+        self.inline = Some(Presenter::new(Box::new(target), W, H));
+    }
 
-        ThreadSpawnConfiguration::default().set().unwrap();
+    /// The last error from presenting a frame since the previous call, e.g.
+    /// a failed SPI transfer. The frame it happened on is lost.
+    pub fn take_error(&mut self) -> Option<FbWriteError> {
+        self.error.lock().unwrap().take()
     }
 
     pub fn swap_framebuffer(&mut self) -> &mut DmaReadyFramebuffer<W, H> {
@@ -113,18 +209,45 @@ impl<const W: usize, const H: usize> DoubleBuffer<W, H> {
             &mut self.fbuf1
         };
 
-        if let Some(sender) = &self.sender {
+        if let Some(presenter) = &mut self.inline {
+            let buf =
+                unsafe { std::slice::from_raw_parts_mut(fbuf.framebuffer as *mut u16, W * H) };
+            if let Err(err) = presenter.present(buf, W) {
+                *self.error.lock().unwrap() = Some(err);
+            }
+            // Like the writer thread, hand back a cleared buffer.
+            buf.fill(0);
+        } else if let Some(sender) = &self.sender {
             sender.send(fbuf.framebuffer as usize).unwrap();
         }
     }
 }
 
+impl<const W: usize, const H: usize> Drop for DoubleBuffer<W, H> {
+    fn drop(&mut self) {
+        // Closing the channel stops the writer thread, but only after the
+        // frame it is on, which may still be using a buffer.
+        self.sender = None;
+        if let Some(stopped) = self.stopped.take() {
+            let _ = stopped.recv();
+        }
+    }
+}
+
 pub struct OwnedDoubleBuffer<const W: usize, const H: usize> {
+    /// Declared first so it drops, and waits for the writer thread, before
+    /// the buffers are freed.
     buffers: DoubleBuffer<W, H>,
     _fb0: Vec<u16>,
     _fb1: Vec<u16>,
 }
 
+impl<const W: usize, const H: usize> Default for OwnedDoubleBuffer<W, H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<const W: usize, const H: usize> OwnedDoubleBuffer<W, H> {
     pub fn new() -> Self {
         let mut fb0 = vec![0u16; W * H];
@@ -154,3 +277,166 @@ impl<const W: usize, const H: usize> DerefMut for OwnedDoubleBuffer<W, H> {
         &mut self.buffers
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    use embedded_graphics::pixelcolor::Rgb565;
+    use embedded_graphics::prelude::*;
+
+    use super::*;
+    use crate::dirty::Region;
+
+    const W: usize = 4;
+    const H: usize = 3;
+
+    /// Starts the presenter on a `std::thread` and counts the threads started.
+    #[derive(Default)]
+    struct CountingSpawner(Cell<usize>);
+
+    impl ThreadSpawner for CountingSpawner {
+        fn spawn(&self, presenter: Box<dyn FnOnce() + Send + 'static>) {
+            self.0.set(self.0.get() + 1);
+            std::thread::spawn(presenter);
+        }
+    }
+
+    /// Keeps the regions it is given, or fails while `failing` is set.
+    #[derive(Clone, Default)]
+    struct FakeTarget {
+        regions: Arc<Mutex<Vec<Region>>>,
+        failing: Arc<Mutex<bool>>,
+    }
+
+    impl FramebufferTarget for FakeTarget {
+        fn eat_framebuffer(&mut self, _: &[u16]) -> Result<(), FbWriteError> {
+            unreachable!("the presenter sends regions")
+        }
+
+        fn eat_region(
+            &mut self,
+            buf: &[u16],
+            _width: usize,
+            region: Region,
+        ) -> Result<(), FbWriteError> {
+            assert_eq!(buf.len(), W * H);
+            if *self.failing.lock().unwrap() {
+                return Err(FbWriteError::Error);
+            }
+            self.regions.lock().unwrap().push(region);
+            Ok(())
+        }
+    }
+
+    /// Waits for the writer thread until `done` returns true.
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !done() {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn send_pixel(buffers: &mut DoubleBuffer<W, H>, x: i32, y: i32) {
+        Pixel(Point::new(x, y), Rgb565::WHITE)
+            .draw(buffers.swap_framebuffer())
+            .unwrap();
+        buffers.send_framebuffer();
+    }
+
+    #[test]
+    fn frames_reach_the_target() {
+        let target = FakeTarget::default();
+        let spawner = CountingSpawner::default();
+        let mut buffers = OwnedDoubleBuffer::<W, H>::new();
+        buffers.start_thread_with(target.clone(), &spawner);
+        assert_eq!(spawner.0.get(), 1);
+
+        send_pixel(&mut buffers, 2, 1);
+        wait_for(|| target.regions.lock().unwrap().len() == 1);
+        send_pixel(&mut buffers, 2, 1);
+        send_pixel(&mut buffers, 0, 0);
+        wait_for(|| target.regions.lock().unwrap().len() == 2);
+
+        // The repeated frame had nothing new to send.
+        let regions = target.regions.lock().unwrap().clone();
+        let changed = Region {
+            x: 0,
+            y: 0,
+            width: 3,
+            height: 2,
+        };
+        assert_eq!(regions, [Region::full(W, H), changed]);
+        assert_eq!(buffers.take_error(), None);
+    }
+
+    /// Holds the writer thread in `eat_region` until `release` gets a message.
+    struct BlockingTarget {
+        entered: mpsc::Sender<()>,
+        release: mpsc::Receiver<()>,
+        finished: Arc<AtomicBool>,
+    }
+
+    impl FramebufferTarget for BlockingTarget {
+        fn eat_framebuffer(&mut self, _: &[u16]) -> Result<(), FbWriteError> {
+            unreachable!("the presenter sends regions")
+        }
+
+        fn eat_region(&mut self, _: &[u16], _: usize, _: Region) -> Result<(), FbWriteError> {
+            self.entered.send(()).unwrap();
+            self.release.recv().unwrap();
+            self.finished.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn drop_waits_for_the_writer_thread() {
+        let (entered_send, entered) = mpsc::channel();
+        let (release, release_receive) = mpsc::channel();
+        let finished = Arc::new(AtomicBool::new(false));
+        let target = BlockingTarget {
+            entered: entered_send,
+            release: release_receive,
+            finished: finished.clone(),
+        };
+        let mut buffers = OwnedDoubleBuffer::<W, H>::new();
+        buffers.start_thread_with(target, &CountingSpawner::default());
+
+        send_pixel(&mut buffers, 0, 0);
+        entered.recv().unwrap();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            release.send(()).unwrap();
+        });
+        drop(buffers);
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn target_errors_are_reported() {
+        let target = FakeTarget::default();
+        let mut buffers = OwnedDoubleBuffer::<W, H>::new();
+        buffers.start_thread_with(target.clone(), &CountingSpawner::default());
+
+        *target.failing.lock().unwrap() = true;
+        send_pixel(&mut buffers, 1, 1);
+        let mut error = None;
+        wait_for(|| {
+            error = buffers.take_error();
+            error.is_some()
+        });
+        assert_eq!(error, Some(FbWriteError::Error));
+        assert_eq!(buffers.take_error(), None);
+
+        // The writer thread carries on, and sends the whole next frame.
+        *target.failing.lock().unwrap() = false;
+        send_pixel(&mut buffers, 1, 1);
+        wait_for(|| !target.regions.lock().unwrap().is_empty());
+        assert_eq!(*target.regions.lock().unwrap(), [Region::full(W, H)]);
+    }
+}
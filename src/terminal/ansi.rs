@@ -0,0 +1,1239 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use embedded_graphics::pixelcolor::{Rgb565, Rgb888};
+
+use crate::vt_keys::CursorKeyMode;
+
+const TAB_WIDTH: usize = 8;
+/// Lines kept after they scroll off the top, unless changed with `set_scrollback_limit`.
+pub const DEFAULT_SCROLLBACK: usize = 500;
+/// Further parameters of a control sequence are folded into the last one.
+const MAX_PARAMS: usize = 16;
+
+/// One step of the byte stream, as seen by the screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Print(char),
+    /// A C0 control character such as `\r`, `\n` or backspace.
+    Control(u8),
+    /// `ESC` followed by a single final byte, e.g. `ESC 7`.
+    Escape(u8),
+    /// A control sequence `ESC [ params final`. Empty parameters are 0.
+    Csi {
+        private: bool,
+        params: Vec<u16>,
+        action: u8,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    /// `ESC (` and friends: the next byte picks a character set, which we ignore.
+    Charset,
+    Csi,
+    Osc,
+    OscEscape,
+}
+
+/// Splits a VT100/xterm byte stream into printable characters and commands.
+/// Text is decoded as UTF-8; invalid bytes, and sequences cut short, print as U+FFFD.
+#[derive(Debug)]
+pub struct Parser {
+    state: State,
+    private: bool,
+    params: Vec<u16>,
+    utf8: [u8; 4],
+    utf8_len: usize,
+    utf8_needed: usize,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            private: false,
+            params: Vec::new(),
+            utf8: [0; 4],
+            utf8_len: 0,
+            utf8_needed: 0,
+        }
+    }
+
+    /// Feeds one byte, passing any character or sequence it completes to
+    /// `perform`. A byte that cuts a UTF-8 sequence short yields U+FFFD first.
+    pub fn advance(&mut self, byte: u8, mut perform: impl FnMut(Action)) {
+        if self.utf8_needed > 0 && byte & 0xc0 != 0x80 {
+            self.utf8_needed = 0;
+            perform(Action::Print(char::REPLACEMENT_CHARACTER));
+        }
+        let action = match self.state {
+            State::Ground => self.ground(byte),
+            State::Escape => self.escape(byte),
+            State::Charset => {
+                self.state = State::Ground;
+                None
+            }
+            State::Csi => self.csi(byte),
+            State::Osc => {
+                match byte {
+                    0x07 => self.state = State::Ground,
+                    0x1b => self.state = State::OscEscape,
+                    _ => {}
+                }
+                None
+            }
+            State::OscEscape => {
+                // ST is `ESC \`; anything else aborts the string as well.
+                self.state = State::Ground;
+                None
+            }
+        };
+        if let Some(action) = action {
+            perform(action);
+        }
+    }
+
+    fn ground(&mut self, byte: u8) -> Option<Action> {
+        // `advance` has already ended the sequence if `byte` doesn't continue it.
+        if self.utf8_needed > 0 {
+            self.utf8[self.utf8_len] = byte;
+            self.utf8_len += 1;
+            if self.utf8_len < self.utf8_needed {
+                return None;
+            }
+            self.utf8_needed = 0;
+            let c = std::str::from_utf8(&self.utf8[..self.utf8_len])
+                .ok()
+                .and_then(|s| s.chars().next())
+                .unwrap_or(char::REPLACEMENT_CHARACTER);
+            return Some(Action::Print(c));
+        }
+
+        match byte {
+            0x1b => {
+                self.state = State::Escape;
+                None
+            }
+            0x00..=0x1f => Some(Action::Control(byte)),
+            0x20..=0x7e => Some(Action::Print(byte as char)),
+            0x7f => None,
+            0xc2..=0xdf => self.start_utf8(byte, 2),
+            0xe0..=0xef => self.start_utf8(byte, 3),
+            0xf0..=0xf4 => self.start_utf8(byte, 4),
+            _ => Some(Action::Print(char::REPLACEMENT_CHARACTER)),
+        }
+    }
+
+    fn start_utf8(&mut self, byte: u8, len: usize) -> Option<Action> {
+        self.utf8[0] = byte;
+        self.utf8_len = 1;
+        self.utf8_needed = len;
+        None
+    }
+
+    fn escape(&mut self, byte: u8) -> Option<Action> {
+        self.state = State::Ground;
+        match byte {
+            b'[' => {
+                self.private = false;
+                self.params.clear();
+                self.state = State::Csi;
+                None
+            }
+            b']' | b'P' | b'_' | b'^' => {
+                // OSC, DCS, APC and PM strings are skipped up to their terminator.
+                self.state = State::Osc;
+                None
+            }
+            b'(' | b')' | b'*' | b'+' => {
+                self.state = State::Charset;
+                None
+            }
+            0x1b => {
+                self.state = State::Escape;
+                None
+            }
+            0x30..=0x7e => Some(Action::Escape(byte)),
+            _ => None,
+        }
+    }
+
+    fn csi(&mut self, byte: u8) -> Option<Action> {
+        match byte {
+            b'0'..=b'9' => {
+                if self.params.is_empty() {
+                    self.params.push(0);
+                }
+                let last = self.params.last_mut().unwrap();
+                *last = last.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                None
+            }
+            b';' | b':' => {
+                if self.params.is_empty() {
+                    self.params.push(0);
+                }
+                if self.params.len() < MAX_PARAMS {
+                    self.params.push(0);
+                }
+                None
+            }
+            b'?' | b'<' | b'=' | b'>' => {
+                self.private = true;
+                None
+            }
+            0x40..=0x7e => {
+                self.state = State::Ground;
+                Some(Action::Csi {
+                    private: self.private,
+                    params: std::mem::take(&mut self.params),
+                    action: byte,
+                })
+            }
+            0x1b => {
+                self.state = State::Escape;
+                None
+            }
+            0x18 | 0x1a => {
+                self.state = State::Ground;
+                None
+            }
+            // Controls inside a sequence are executed right away, as on a VT100.
+            0x00..=0x1f => Some(Action::Control(byte)),
+            // Intermediate bytes; none of the sequences we handle use them.
+            _ => None,
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rendition of a cell. Colors are `None` for the terminal's default.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct CellStyle {
+    pub fg: Option<Rgb565>,
+    pub bg: Option<Rgb565>,
+    pub bold: bool,
+    pub inverse: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    pub style: CellStyle,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            c: ' ',
+            style: CellStyle::default(),
+        }
+    }
+}
+
+/// A line in the scrollback. A row of `Cell`s takes 16 bytes a column, too
+/// much to keep hundreds of without PSRAM, so the line is kept as text and
+/// style runs and only turned back into cells while on display.
+#[derive(Debug)]
+struct StoredLine {
+    /// Without trailing blank cells.
+    text: Box<str>,
+    /// How many characters of `text` each style covers, in order.
+    runs: Box<[(u16, CellStyle)]>,
+}
+
+impl StoredLine {
+    fn new(cells: &[Cell]) -> Self {
+        let len = cells
+            .iter()
+            .rposition(|cell| *cell != Cell::default())
+            .map_or(0, |last| last + 1);
+        let mut runs: Vec<(u16, CellStyle)> = Vec::new();
+        for cell in &cells[..len] {
+            match runs.last_mut() {
+                Some((count, style)) if *style == cell.style => *count += 1,
+                _ => runs.push((1, cell.style)),
+            }
+        }
+        Self {
+            text: cells[..len].iter().map(|cell| cell.c).collect(),
+            runs: runs.into_boxed_slice(),
+        }
+    }
+
+    /// The line as `cols` cells, cut off or padded with blanks.
+    fn cells(&self, cols: usize) -> Vec<Cell> {
+        let styles = self
+            .runs
+            .iter()
+            .flat_map(|&(count, style)| std::iter::repeat(style).take(count as usize));
+        let mut cells: Vec<Cell> = self
+            .text
+            .chars()
+            .zip(styles)
+            .map(|(c, style)| Cell { c, style })
+            .take(cols)
+            .collect();
+        cells.resize(cols, Cell::default());
+        cells
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct SavedCursor {
+    row: usize,
+    col: usize,
+    style: CellStyle,
+    pending_wrap: bool,
+}
+
+/// A grid of character cells driven by a VT100/xterm byte stream.
+///
+/// Knows nothing about fonts or displays; `FbTerminal` draws it.
+#[derive(Debug)]
+pub struct Screen {
+    cols: usize,
+    rows: usize,
+    grid: Vec<Vec<Cell>>,
+    row: usize,
+    col: usize,
+    /// The last column was written; the next character wraps first.
+    pending_wrap: bool,
+    style: CellStyle,
+    scroll_top: usize,
+    /// Inclusive.
+    scroll_bottom: usize,
+    saved: Option<SavedCursor>,
+    autowrap: bool,
+    /// LNM: line feed also returns the carriage.
+    newline_mode: bool,
+    cursor_visible: bool,
+    cursor_keys: CursorKeyMode,
+    parser: Parser,
+    /// Lines that scrolled off the top, oldest first.
+    scrollback: VecDeque<StoredLine>,
+    scrollback_limit: usize,
+    /// How far the view is scrolled back from the live screen.
+    view_offset: usize,
+}
+
+impl Screen {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        assert!(cols > 0 && rows > 0);
+        Self {
+            cols,
+            rows,
+            grid: vec![vec![Cell::default(); cols]; rows],
+            row: 0,
+            col: 0,
+            pending_wrap: false,
+            style: CellStyle::default(),
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            saved: None,
+            autowrap: true,
+            newline_mode: false,
+            cursor_visible: true,
+            cursor_keys: CursorKeyMode::Normal,
+            parser: Parser::new(),
+            scrollback: VecDeque::new(),
+            scrollback_limit: DEFAULT_SCROLLBACK,
+            view_offset: 0,
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// `(row, column)`, both starting at 0.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// The cursor-key mode the host asked for with `ESC [ ? 1 h/l`.
+    pub fn cursor_keys(&self) -> CursorKeyMode {
+        self.cursor_keys
+    }
+
+    pub fn row(&self, row: usize) -> &[Cell] {
+        &self.grid[row]
+    }
+
+    /// The characters of `row` without trailing blanks.
+    pub fn row_text(&self, row: usize) -> String {
+        let text: String = self.grid[row].iter().map(|cell| cell.c).collect();
+        text.trim_end().to_string()
+    }
+
+    /// Back to the power-on state (`ESC c`). This also clears the scrollback.
+    pub fn reset(&mut self) {
+        let limit = self.scrollback_limit;
+        *self = Self::new(self.cols, self.rows);
+        self.scrollback_limit = limit;
+    }
+
+    /// Changes the size of the screen. When it gets shorter, lines above the
+    /// cursor go to the scrollback; when it gets taller, they come back from it.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        assert!(cols > 0 && rows > 0);
+        if cols == self.cols && rows == self.rows {
+            return;
+        }
+
+        self.view_offset = 0;
+        for line in &mut self.grid {
+            line.resize(cols, Cell::default());
+        }
+        while self.grid.len() > rows {
+            if self.row > 0 {
+                let line = self.grid.remove(0);
+                self.row -= 1;
+                self.push_scrollback(line);
+            } else {
+                self.grid.pop();
+            }
+        }
+        while self.grid.len() < rows {
+            match self.scrollback.pop_back() {
+                Some(line) => {
+                    self.grid.insert(0, line.cells(cols));
+                    self.row += 1;
+                }
+                None => self.grid.push(vec![Cell::default(); cols]),
+            }
+        }
+
+        self.cols = cols;
+        self.rows = rows;
+        self.col = self.col.min(cols - 1);
+        self.pending_wrap = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.scrollback.len()
+    }
+
+    pub fn scrollback_limit(&self) -> usize {
+        self.scrollback_limit
+    }
+
+    /// Sets how many lines are kept after scrolling off the top; 0 disables scrollback.
+    pub fn set_scrollback_limit(&mut self, lines: usize) {
+        self.scrollback_limit = lines;
+        while self.scrollback.len() > lines {
+            self.scrollback.pop_front();
+        }
+        self.view_offset = self.view_offset.min(self.scrollback.len());
+    }
+
+    /// How many lines the view is scrolled back; 0 shows the live screen.
+    pub fn view_offset(&self) -> usize {
+        self.view_offset
+    }
+
+    pub fn scroll_view_up(&mut self, lines: usize) {
+        self.view_offset = (self.view_offset + lines).min(self.scrollback.len());
+    }
+
+    pub fn scroll_view_down(&mut self, lines: usize) {
+        self.view_offset = self.view_offset.saturating_sub(lines);
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.view_offset = 0;
+    }
+
+    /// Row `row` of what is on display, taking the scrollback position into account.
+    pub fn view_row(&self, row: usize) -> Cow<'_, [Cell]> {
+        let line = self.scrollback.len() - self.view_offset + row;
+        match self.scrollback.get(line) {
+            Some(stored) => Cow::Owned(stored.cells(self.cols)),
+            None => Cow::Borrowed(&self.grid[line - self.scrollback.len()]),
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        let mut parser = std::mem::take(&mut self.parser);
+        for &byte in bytes {
+            parser.advance(byte, |action| self.apply(action));
+        }
+        self.parser = parser;
+    }
+
+    pub fn write_str(&mut self, text: &str) {
+        self.write(text.as_bytes());
+    }
+
+    pub fn apply(&mut self, action: Action) {
+        match action {
+            Action::Print(c) => self.print(c),
+            Action::Control(byte) => self.control(byte),
+            Action::Escape(byte) => self.escape(byte),
+            Action::Csi {
+                private,
+                params,
+                action,
+            } => self.csi(private, &params, action),
+        }
+    }
+
+    fn print(&mut self, c: char) {
+        if self.pending_wrap {
+            self.col = 0;
+            self.linefeed();
+        }
+        self.grid[self.row][self.col] = Cell {
+            c,
+            style: self.style,
+        };
+        if self.col + 1 < self.cols {
+            self.col += 1;
+        } else {
+            self.pending_wrap = self.autowrap;
+        }
+    }
+
+    fn control(&mut self, byte: u8) {
+        match byte {
+            0x08 => self.move_to(self.row, self.col.saturating_sub(1)),
+            b'\t' => {
+                let next = (self.col / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.move_to(self.row, next);
+            }
+            b'\n' | 0x0b | 0x0c => {
+                if self.newline_mode {
+                    self.col = 0;
+                }
+                self.linefeed();
+            }
+            b'\r' => self.move_to(self.row, 0),
+            _ => {}
+        }
+    }
+
+    fn escape(&mut self, byte: u8) {
+        match byte {
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            b'D' => self.linefeed(),
+            b'E' => {
+                self.col = 0;
+                self.linefeed();
+            }
+            b'M' => self.reverse_index(),
+            b'c' => self.reset(),
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, private: bool, params: &[u16], action: u8) {
+        let count = count_param(params, 0);
+        if private {
+            match action {
+                b'h' => self.set_private_modes(params, true),
+                b'l' => self.set_private_modes(params, false),
+                _ => {}
+            }
+            return;
+        }
+
+        match action {
+            b'A' => {
+                let top = if self.row >= self.scroll_top {
+                    self.scroll_top
+                } else {
+                    0
+                };
+                self.move_to(self.row.saturating_sub(count).max(top), self.col);
+            }
+            b'B' => {
+                let bottom = if self.row <= self.scroll_bottom {
+                    self.scroll_bottom
+                } else {
+                    self.rows - 1
+                };
+                self.move_to((self.row + count).min(bottom), self.col);
+            }
+            b'C' => self.move_to(self.row, self.col + count),
+            b'D' => self.move_to(self.row, self.col.saturating_sub(count)),
+            b'E' => self.move_to(self.row + count, 0),
+            b'F' => self.move_to(self.row.saturating_sub(count), 0),
+            b'G' | b'`' => self.move_to(self.row, count - 1),
+            b'd' => self.move_to(count - 1, self.col),
+            b'H' | b'f' => self.move_to(count - 1, count_param(params, 1) - 1),
+            b'J' => self.erase_display(param(params, 0)),
+            b'K' => self.erase_line(param(params, 0)),
+            b'S' => self.scroll_up(count),
+            b'T' => self.scroll_down(count),
+            b'L' => self.insert_lines(count),
+            b'M' => self.delete_lines(count),
+            b'@' => self.insert_chars(count),
+            b'P' => self.delete_chars(count),
+            b'X' => {
+                let end = (self.col + count).min(self.cols);
+                self.erase(self.row, self.col, end);
+            }
+            b'm' => self.select_graphic_rendition(params),
+            b'r' => {
+                let top = count_param(params, 0) - 1;
+                let bottom = match param(params, 1) {
+                    0 => self.rows,
+                    bottom => (bottom as usize).min(self.rows),
+                } - 1;
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_to(0, 0);
+                }
+            }
+            b's' => self.save_cursor(),
+            b'u' => self.restore_cursor(),
+            b'h' | b'l' if params.contains(&20) => self.newline_mode = action == b'h',
+            _ => {}
+        }
+    }
+
+    fn set_private_modes(&mut self, params: &[u16], on: bool) {
+        for &mode in params {
+            match mode {
+                1 => {
+                    self.cursor_keys = if on {
+                        CursorKeyMode::Application
+                    } else {
+                        CursorKeyMode::Normal
+                    }
+                }
+                7 => self.autowrap = on,
+                25 => self.cursor_visible = on,
+                _ => {}
+            }
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            self.style = CellStyle::default();
+            return;
+        }
+
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => self.style = CellStyle::default(),
+                1 => self.style.bold = true,
+                22 => self.style.bold = false,
+                7 => self.style.inverse = true,
+                27 => self.style.inverse = false,
+                n @ 30..=37 => self.style.fg = Some(ansi_color((n - 30) as u8)),
+                n @ 90..=97 => self.style.fg = Some(ansi_color((n - 90 + 8) as u8)),
+                39 => self.style.fg = None,
+                n @ 40..=47 => self.style.bg = Some(ansi_color((n - 40) as u8)),
+                n @ 100..=107 => self.style.bg = Some(ansi_color((n - 100 + 8) as u8)),
+                49 => self.style.bg = None,
+                n @ (38 | 48) => {
+                    let (color, used) = extended_color(&params[i + 1..]);
+                    i += used;
+                    if let Some(color) = color {
+                        if n == 38 {
+                            self.style.fg = Some(color);
+                        } else {
+                            self.style.bg = Some(color);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    fn move_to(&mut self, row: usize, col: usize) {
+        self.row = row.min(self.rows - 1);
+        self.col = col.min(self.cols - 1);
+        self.pending_wrap = false;
+    }
+
+    fn linefeed(&mut self) {
+        self.pending_wrap = false;
+        if self.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.row + 1 < self.rows {
+            self.row += 1;
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        self.pending_wrap = false;
+        if self.row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.row > 0 {
+            self.row -= 1;
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved = Some(SavedCursor {
+            row: self.row,
+            col: self.col,
+            style: self.style,
+            pending_wrap: self.pending_wrap,
+        });
+    }
+
+    fn restore_cursor(&mut self) {
+        if let Some(saved) = self.saved {
+            self.move_to(saved.row, saved.col);
+            self.style = saved.style;
+            self.pending_wrap = saved.pending_wrap;
+        }
+    }
+
+    /// An empty cell in the current background color.
+    fn blank(&self) -> Cell {
+        Cell {
+            c: ' ',
+            style: CellStyle {
+                bg: self.style.bg,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn blank_row(&self) -> Vec<Cell> {
+        vec![self.blank(); self.cols]
+    }
+
+    /// Scrolls the scroll region up. Lines leaving the top of the screen go to the scrollback.
+    fn scroll_up(&mut self, count: usize) {
+        let height = self.scroll_bottom - self.scroll_top + 1;
+        for _ in 0..count.min(height) {
+            let line = self.grid.remove(self.scroll_top);
+            self.grid.insert(self.scroll_bottom, self.blank_row());
+            if self.scroll_top == 0 {
+                self.push_scrollback(line);
+            }
+        }
+    }
+
+    fn push_scrollback(&mut self, line: Vec<Cell>) {
+        if self.scrollback_limit == 0 {
+            return;
+        }
+        if self.scrollback.len() == self.scrollback_limit {
+            self.scrollback.pop_front();
+        } else if self.view_offset > 0 {
+            // Keep a scrolled-back view on the same lines while output continues.
+            self.view_offset += 1;
+        }
+        self.scrollback.push_back(StoredLine::new(&line));
+    }
+
+    fn scroll_down(&mut self, count: usize) {
+        let height = self.scroll_bottom - self.scroll_top + 1;
+        for _ in 0..count.min(height) {
+            self.grid.remove(self.scroll_bottom);
+            self.grid.insert(self.scroll_top, self.blank_row());
+        }
+    }
+
+    fn insert_lines(&mut self, count: usize) {
+        if self.row < self.scroll_top || self.row > self.scroll_bottom {
+            return;
+        }
+        for _ in 0..count.min(self.scroll_bottom - self.row + 1) {
+            self.grid.remove(self.scroll_bottom);
+            self.grid.insert(self.row, self.blank_row());
+        }
+        self.move_to(self.row, 0);
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        if self.row < self.scroll_top || self.row > self.scroll_bottom {
+            return;
+        }
+        for _ in 0..count.min(self.scroll_bottom - self.row + 1) {
+            self.grid.remove(self.row);
+            self.grid.insert(self.scroll_bottom, self.blank_row());
+        }
+        self.move_to(self.row, 0);
+    }
+
+    fn insert_chars(&mut self, count: usize) {
+        let blank = self.blank();
+        let line = &mut self.grid[self.row];
+        for _ in 0..count.min(self.cols - self.col) {
+            line.pop();
+            line.insert(self.col, blank);
+        }
+        self.pending_wrap = false;
+    }
+
+    fn delete_chars(&mut self, count: usize) {
+        let blank = self.blank();
+        let line = &mut self.grid[self.row];
+        for _ in 0..count.min(self.cols - self.col) {
+            line.remove(self.col);
+            line.push(blank);
+        }
+        self.pending_wrap = false;
+    }
+
+    /// Blanks the columns `start..end` of `row`.
+    fn erase(&mut self, row: usize, start: usize, end: usize) {
+        let blank = self.blank();
+        for cell in &mut self.grid[row][start..end] {
+            *cell = blank;
+        }
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        match mode {
+            0 => self.erase(self.row, self.col, self.cols),
+            1 => self.erase(self.row, 0, self.col + 1),
+            2 => self.erase(self.row, 0, self.cols),
+            _ => {}
+        }
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        let rows = match mode {
+            0 => {
+                self.erase(self.row, self.col, self.cols);
+                self.row + 1..self.rows
+            }
+            1 => {
+                self.erase(self.row, 0, self.col + 1);
+                0..self.row
+            }
+            2 => 0..self.rows,
+            // Only the saved lines, the screen stays as it is.
+            3 => {
+                self.scrollback.clear();
+                self.view_offset = 0;
+                0..0
+            }
+            _ => return,
+        };
+        for row in rows {
+            self.erase(row, 0, self.cols);
+        }
+    }
+}
+
+/// The raw value of parameter `index`, 0 if missing.
+fn param(params: &[u16], index: usize) -> u16 {
+    params.get(index).copied().unwrap_or(0)
+}
+
+/// A count or position parameter: missing or 0 means 1.
+fn count_param(params: &[u16], index: usize) -> usize {
+    param(params, index).max(1) as usize
+}
+
+/// Parses the color after SGR 38/48: `5;n` or `2;r;g;b`.
+/// Returns the color and how many parameters it used.
+fn extended_color(params: &[u16]) -> (Option<Rgb565>, usize) {
+    match params {
+        [5, index, ..] => (Some(ansi_color(*index as u8)), 2),
+        [2, r, g, b, ..] => (
+            Some(Rgb565::from(Rgb888::new(*r as u8, *g as u8, *b as u8))),
+            4,
+        ),
+        _ => (None, params.len()),
+    }
+}
+
+/// The xterm 256-color palette: 16 system colors, a 6x6x6 cube and 24 greys.
+pub fn ansi_color(index: u8) -> Rgb565 {
+    const SYSTEM: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+
+    let (r, g, b) = match index {
+        0..=15 => SYSTEM[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let v = 8 + (index - 232) * 10;
+            (v, v, v)
+        }
+    };
+    Rgb565::from(Rgb888::new(r, g, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{size_of, size_of_val};
+
+    use super::*;
+
+    fn actions(bytes: &[u8]) -> Vec<Action> {
+        let mut parser = Parser::new();
+        let mut actions = Vec::new();
+        for &byte in bytes {
+            parser.advance(byte, |action| actions.push(action));
+        }
+        actions
+    }
+
+    fn csi(private: bool, params: &[u16], action: u8) -> Action {
+        Action::Csi {
+            private,
+            params: params.to_vec(),
+            action,
+        }
+    }
+
+    fn rows(screen: &Screen) -> Vec<String> {
+        (0..screen.rows()).map(|row| screen.row_text(row)).collect()
+    }
+
+    #[test]
+    fn parser_splits_text_and_sequences() {
+        assert_eq!(
+            actions(b"a\r\n\x1b7\x1b[12;34H\x1b[?25l\x1b[m"),
+            [
+                Action::Print('a'),
+                Action::Control(b'\r'),
+                Action::Control(b'\n'),
+                Action::Escape(b'7'),
+                csi(false, &[12, 34], b'H'),
+                csi(true, &[25], b'l'),
+                csi(false, &[], b'm'),
+            ]
+        );
+        assert_eq!(actions(b"\x1b[;5H"), [csi(false, &[0, 5], b'H')]);
+        assert_eq!(actions(b"\x1b[38:5:9m"), [csi(false, &[38, 5, 9], b'm')]);
+    }
+
+    #[test]
+    fn parser_decodes_utf8() {
+        assert_eq!(
+            actions("é€😀".as_bytes()),
+            [Action::Print('é'), Action::Print('€'), Action::Print('😀')]
+        );
+        assert_eq!(
+            actions(b"\xc3x\xff"),
+            [
+                Action::Print(char::REPLACEMENT_CHARACTER),
+                Action::Print('x'),
+                Action::Print(char::REPLACEMENT_CHARACTER)
+            ]
+        );
+    }
+
+    #[test]
+    fn parser_replaces_sequences_cut_short() {
+        assert_eq!(
+            actions(b"\xe2\x82\r"),
+            [
+                Action::Print(char::REPLACEMENT_CHARACTER),
+                Action::Control(b'\r')
+            ]
+        );
+        assert_eq!(
+            actions(b"\xf0\x9f\x1b[m"),
+            [
+                Action::Print(char::REPLACEMENT_CHARACTER),
+                csi(false, &[], b'm')
+            ]
+        );
+        // A new lead byte ends the old sequence and starts its own.
+        assert_eq!(
+            actions(b"\xc3\xc3\xa9"),
+            [
+                Action::Print(char::REPLACEMENT_CHARACTER),
+                Action::Print('\u{e9}')
+            ]
+        );
+    }
+
+    #[test]
+    fn parser_skips_strings_and_charsets() {
+        assert_eq!(actions(b"\x1b]0;title\x07a"), [Action::Print('a')]);
+        assert_eq!(actions(b"\x1b]0;title\x1b\\b"), [Action::Print('b')]);
+        assert_eq!(actions(b"\x1b(Bc"), [Action::Print('c')]);
+        assert_eq!(actions(b"\x1b[1\x18d"), [Action::Print('d')]);
+        // Controls inside a sequence still run.
+        assert_eq!(
+            actions(b"\x1b[1\n2A"),
+            [Action::Control(b'\n'), csi(false, &[12], b'A')]
+        );
+    }
+
+    #[test]
+    fn cursor_movement() {
+        let mut screen = Screen::new(10, 5);
+        screen.write_str("\x1b[3;4H");
+        assert_eq!(screen.cursor(), (2, 3));
+        screen.write_str("\x1b[A\x1b[2C");
+        assert_eq!(screen.cursor(), (1, 5));
+        screen.write_str("\x1b[99B\x1b[99D");
+        assert_eq!(screen.cursor(), (4, 0));
+        screen.write_str("\x1b[7G\x1b[2d");
+        assert_eq!(screen.cursor(), (1, 6));
+        screen.write_str("\x1b7\x1b[H\x1b8");
+        assert_eq!(screen.cursor(), (1, 6));
+        screen.write_str("\x1b[H\tx\x08\x08");
+        assert_eq!(screen.cursor(), (0, 7));
+    }
+
+    #[test]
+    fn wrap_and_autowrap() {
+        let mut screen = Screen::new(4, 3);
+        screen.write_str("abcd");
+        assert_eq!(screen.cursor(), (0, 3));
+        screen.write_str("e");
+        assert_eq!(rows(&screen), ["abcd", "e", ""]);
+
+        screen.write_str("\x1b[?7l\x1b[3;1Hwxyz!");
+        assert_eq!(screen.row_text(2), "wxy!");
+    }
+
+    #[test]
+    fn erase_and_edit() {
+        let mut screen = Screen::new(6, 3);
+        screen.write_str("abcdef\r\nghijkl\r\nmnopqr");
+        screen.write_str("\x1b[2;3H\x1b[K");
+        assert_eq!(rows(&screen), ["abcdef", "gh", "mnopqr"]);
+        screen.write_str("\x1b[1;3H\x1b[1K");
+        assert_eq!(screen.row_text(0), "   def");
+        screen.write_str("\x1b[3;2H\x1b[2P");
+        assert_eq!(screen.row_text(2), "mpqr");
+        screen.write_str("\x1b[2@");
+        assert_eq!(screen.row_text(2), "m  pqr");
+        screen.write_str("\x1b[3X");
+        assert_eq!(screen.row_text(2), "m   qr");
+        screen.write_str("\x1b[2;1H\x1b[J");
+        assert_eq!(rows(&screen), ["   def", "", ""]);
+        screen.write_str("\x1b[2J");
+        assert_eq!(rows(&screen), ["", "", ""]);
+    }
+
+    #[test]
+    fn sgr() {
+        let mut screen = Screen::new(10, 2);
+        screen.write_str("\x1b[1;31;44ma\x1b[22;39mb\x1b[7mc\x1b[0md");
+        let styles: Vec<CellStyle> = screen.row(0)[..4].iter().map(|c| c.style).collect();
+        assert_eq!(
+            styles[0],
+            CellStyle {
+                fg: Some(ansi_color(1)),
+                bg: Some(ansi_color(4)),
+                bold: true,
+                inverse: false,
+            }
+        );
+        assert_eq!(
+            styles[1],
+            CellStyle {
+                bg: Some(ansi_color(4)),
+                ..Default::default()
+            }
+        );
+        assert!(styles[2].inverse);
+        assert_eq!(styles[3], CellStyle::default());
+
+        screen.write_str("\x1b[92;103me\x1b[38;5;196;48;2;0;0;255mf\x1b[mg");
+        let e = screen.row(0)[4].style;
+        assert_eq!((e.fg, e.bg), (Some(ansi_color(10)), Some(ansi_color(11))));
+        let f = screen.row(0)[5].style;
+        assert_eq!(f.fg, Some(ansi_color(196)));
+        assert_eq!(f.bg, Some(Rgb565::from(Rgb888::new(0, 0, 255))));
+        assert_eq!(screen.row(0)[6].style, CellStyle::default());
+
+        // Erasing fills with the current background.
+        screen.write_str("\x1b[41m\x1b[2K");
+        assert_eq!(screen.row(0)[0].style.bg, Some(ansi_color(1)));
+    }
+
+    #[test]
+    fn palette() {
+        assert_eq!(ansi_color(15), Rgb565::from(Rgb888::new(255, 255, 255)));
+        assert_eq!(ansi_color(16), Rgb565::from(Rgb888::new(0, 0, 0)));
+        assert_eq!(ansi_color(231), Rgb565::from(Rgb888::new(255, 255, 255)));
+        assert_eq!(ansi_color(232), Rgb565::from(Rgb888::new(8, 8, 8)));
+    }
+
+    #[test]
+    fn scroll_region() {
+        let mut screen = Screen::new(5, 4);
+        screen.write_str("a\r\nb\r\nc\r\nd");
+        screen.write_str("\x1b[2;3r");
+        assert_eq!(screen.cursor(), (0, 0));
+        screen.write_str("\x1b[3;1H\n");
+        assert_eq!(rows(&screen), ["a", "c", "", "d"]);
+        assert_eq!(screen.scrollback_len(), 0);
+
+        screen.write_str("\x1b[2;1H\x1bM");
+        assert_eq!(rows(&screen), ["a", "", "c", "d"]);
+        screen.write_str("\x1b[3;1Hx\x1b[L");
+        assert_eq!(rows(&screen), ["a", "", "", "d"]);
+        screen.write_str("\x1b[2;1Hy\x1b[M");
+        assert_eq!(rows(&screen), ["a", "", "", "d"]);
+
+        screen.write_str("\x1b[r\x1b[4;1H\nx");
+        assert_eq!(rows(&screen), ["", "", "d", "x"]);
+        assert_eq!(screen.scrollback_len(), 1);
+    }
+
+    #[test]
+    fn modes_and_reset() {
+        let mut screen = Screen::new(5, 3);
+        screen.write_str("\x1b[?1h\x1b[?25l");
+        assert_eq!(screen.cursor_keys(), CursorKeyMode::Application);
+        assert!(!screen.cursor_visible());
+
+        screen.write_str("\x1b[20hab\ncd");
+        assert_eq!(rows(&screen), ["ab", "cd", ""]);
+
+        screen.write_str("\x1bc");
+        assert_eq!(screen.cursor_keys(), CursorKeyMode::Normal);
+        assert!(screen.cursor_visible());
+        assert_eq!(rows(&screen), ["", "", ""]);
+    }
+
+    /// A screen with `lines` numbered lines written to it, one per row.
+    fn numbered(cols: usize, rows: usize, lines: usize) -> Screen {
+        let mut screen = Screen::new(cols, rows);
+        for i in 0..lines {
+            screen.write_str(&format!("{}\r\n", i));
+        }
+        screen
+    }
+
+    fn view_text(screen: &Screen, row: usize) -> String {
+        let text: String = screen.view_row(row).iter().map(|cell| cell.c).collect();
+        text.trim_end().to_string()
+    }
+
+    #[test]
+    fn lines_scroll_into_the_scrollback() {
+        let screen = numbered(5, 3, 10);
+        assert_eq!(screen.scrollback_len(), 8);
+        assert_eq!(rows(&screen), ["8", "9", ""]);
+        assert_eq!(screen.scrollback_limit(), DEFAULT_SCROLLBACK);
+    }
+
+    #[test]
+    fn view_scrolls_back_and_stays_put() {
+        let mut screen = numbered(5, 3, 10);
+        screen.scroll_view_up(2);
+        assert_eq!(screen.view_offset(), 2);
+        assert_eq!(view_text(&screen, 0), "6");
+        assert_eq!(view_text(&screen, 2), "8");
+
+        // New output does not move a view that is scrolled back.
+        screen.write_str("x\r\n");
+        assert_eq!(screen.view_offset(), 3);
+        assert_eq!(view_text(&screen, 0), "6");
+
+        screen.scroll_view_up(100);
+        assert_eq!(screen.view_offset(), screen.scrollback_len());
+        assert_eq!(view_text(&screen, 0), "0");
+        screen.scroll_view_down(1);
+        assert_eq!(view_text(&screen, 0), "1");
+        screen.scroll_to_bottom();
+        assert_eq!(view_text(&screen, 0), "9");
+    }
+
+    #[test]
+    fn scrollback_lines_are_compact() {
+        let mut screen = Screen::new(39, 2);
+        let line = format!("\x1b[31m{}\x1b[m{}", "x".repeat(10), "y".repeat(29));
+        screen.write_str(&line);
+        let cells = screen.row(0).to_vec();
+        screen.write_str("\r\n\r\n");
+
+        let stored = &screen.scrollback[0];
+        let bytes = size_of::<StoredLine>() + stored.text.len() + size_of_val(&*stored.runs);
+        assert!(bytes < 39 * 2 + size_of::<StoredLine>(), "{} bytes", bytes);
+        assert!(bytes * 6 < 39 * size_of::<Cell>(), "{} bytes", bytes);
+
+        screen.scroll_view_up(1);
+        assert_eq!(*screen.view_row(0), cells[..]);
+    }
+
+    #[test]
+    fn scrollback_limit() {
+        let mut screen = numbered(5, 3, 10);
+        screen.scroll_view_up(8);
+        screen.set_scrollback_limit(4);
+        assert_eq!(screen.scrollback_len(), 4);
+        assert_eq!(screen.view_offset(), 4);
+        assert_eq!(view_text(&screen, 0), "4");
+
+        screen.write_str("a\r\nb\r\n");
+        assert_eq!(screen.scrollback_len(), 4);
+        screen.scroll_view_up(4);
+        assert_eq!(view_text(&screen, 0), "6");
+
+        screen.set_scrollback_limit(0);
+        screen.write_str("c\r\n");
+        assert_eq!(screen.scrollback_len(), 0);
+        assert_eq!(screen.view_offset(), 0);
+    }
+
+    #[test]
+    fn erase_saved_lines() {
+        let mut screen = numbered(5, 3, 10);
+        let visible = rows(&screen);
+        assert_ne!(visible, ["", "", ""]);
+        screen.scroll_view_up(3);
+        screen.write_str("\x1b[3J");
+        assert_eq!(screen.scrollback_len(), 0);
+        assert_eq!(screen.view_offset(), 0);
+        assert_eq!(rows(&screen), visible);
+    }
+
+    #[test]
+    fn resize_moves_lines_through_the_scrollback() {
+        let mut screen = numbered(5, 4, 3);
+        assert_eq!(screen.cursor(), (3, 0));
+        screen.resize(5, 2);
+        assert_eq!(rows(&screen), ["2", ""]);
+        assert_eq!(screen.scrollback_len(), 2);
+
+        screen.resize(8, 4);
+        assert_eq!(rows(&screen), ["0", "1", "2", ""]);
+        assert_eq!(screen.scrollback_len(), 0);
+        assert_eq!(screen.cursor(), (3, 0));
+        assert_eq!(screen.row(0).len(), 8);
+    }
+}
@@ -0,0 +1,604 @@
+use super::completion::{common_prefix, Completer, Completion};
+use super::history::History;
+use crate::keyboard::Key;
+use crate::typing::KeyboardEvent;
+
+/// A Ctrl+R reverse incremental search in progress.
+struct Search {
+    query: String,
+    /// The history entry on the line.
+    found: Option<usize>,
+    /// The last key found nothing (more).
+    failed: bool,
+}
+
+/// The line being typed at the prompt, with a cursor and a history.
+///
+/// Positions are counted in characters, not bytes.
+pub struct CommandLine {
+    line: String,
+    cursor: usize,
+    history: History,
+    /// The history entry being browsed with the arrows.
+    history_pos: Option<usize>,
+    /// What was on the line before browsing or searching the history.
+    draft: String,
+    search: Option<Search>,
+}
+
+impl CommandLine {
+    pub fn new() -> Self {
+        Self::with_history(History::default())
+    }
+
+    pub fn with_history(history: History) -> Self {
+        Self {
+            line: String::new(),
+            cursor: 0,
+            history,
+            history_pos: None,
+            draft: String::new(),
+            search: None,
+        }
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Replaces the history, e.g. with `History::for_app` to keep it on the SD card.
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+        self.history_pos = None;
+    }
+
+    /// Inserts `c` at the cursor.
+    pub fn push(&mut self, c: char) {
+        let at = self.byte_index(self.cursor);
+        self.line.insert(at, c);
+        self.cursor += 1;
+    }
+
+    /// Deletes the character before the cursor.
+    pub fn pop(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.line.remove(self.byte_index(self.cursor));
+        }
+    }
+
+    /// Deletes the character under the cursor.
+    pub fn delete(&mut self) {
+        if self.cursor < self.len() {
+            self.line.remove(self.byte_index(self.cursor));
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.len());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.len();
+    }
+
+    /// Deletes the word before the cursor, along with the blanks after it.
+    pub fn delete_word(&mut self) {
+        let chars: Vec<char> = self.line.chars().collect();
+        let mut start = self.cursor;
+        while start > 0 && chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        self.delete_range(start, self.cursor);
+    }
+
+    /// Deletes everything before the cursor.
+    pub fn kill_line(&mut self) {
+        self.delete_range(0, self.cursor);
+    }
+
+    /// Deletes everything from the cursor on.
+    pub fn kill_to_end(&mut self) {
+        self.delete_range(self.cursor, self.len());
+    }
+
+    pub(super) fn enter(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        self.history.push(&line);
+        self.cursor = 0;
+        self.history_pos = None;
+        self.search = None;
+    }
+
+    pub fn get(&self) -> &str {
+        &self.line
+    }
+
+    /// Replaces the line and puts the cursor at its end.
+    pub fn set(&mut self, line: &str) {
+        self.history_pos = None;
+        self.replace(line);
+    }
+
+    fn replace(&mut self, line: &str) {
+        self.line = line.to_string();
+        self.cursor = self.len();
+    }
+
+    pub fn clear(&mut self) {
+        self.set("");
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Shows the next older history entry.
+    pub fn arrow_up(&mut self) {
+        let pos = match self.history_pos {
+            Some(0) => return,
+            Some(pos) => pos - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.line.clone();
+                self.history.len() - 1
+            }
+        };
+        self.show_history(pos);
+    }
+
+    /// Shows the next newer history entry, and finally the line typed before browsing.
+    pub fn arrow_down(&mut self) {
+        match self.history_pos {
+            Some(pos) if pos + 1 < self.history.len() => self.show_history(pos + 1),
+            Some(_) => {
+                self.history_pos = None;
+                let draft = std::mem::take(&mut self.draft);
+                self.replace(&draft);
+            }
+            None => {}
+        }
+    }
+
+    fn show_history(&mut self, pos: usize) {
+        let entry = self.history.get(pos).unwrap_or_default().to_string();
+        self.history_pos = Some(pos);
+        self.replace(&entry);
+    }
+
+    /// Starts a reverse search, or looks further back if one is running.
+    pub fn search_history(&mut self) {
+        match &self.search {
+            Some(search) => {
+                let before = search.found.unwrap_or(self.history.len());
+                self.update_search(before);
+            }
+            None => {
+                self.draft = self.line.clone();
+                self.search = Some(Search {
+                    query: String::new(),
+                    found: None,
+                    failed: false,
+                });
+            }
+        }
+    }
+
+    /// The current search term, while searching.
+    pub fn search_query(&self) -> Option<&str> {
+        self.search.as_ref().map(|search| search.query.as_str())
+    }
+
+    /// Looks for the search term in entries older than `before` and puts
+    /// the match on the line. Without a match the line stays as it is.
+    fn update_search(&mut self, before: usize) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        if search.query.is_empty() {
+            search.found = None;
+            search.failed = false;
+            let draft = self.draft.clone();
+            self.replace(&draft);
+            return;
+        }
+
+        match self.history.search(&search.query, before) {
+            Some(index) => {
+                let entry = self.history.get(index).unwrap_or_default();
+                let at = entry.find(&search.query).unwrap_or(0);
+                search.found = Some(index);
+                search.failed = false;
+                self.line = entry.to_string();
+                self.cursor = self.line[..at].chars().count();
+            }
+            None => search.failed = true,
+        }
+    }
+
+    fn handle_search_event(&mut self, event: KeyboardEvent) -> Option<KeyboardEvent> {
+        let Some(search) = &mut self.search else {
+            return Some(event);
+        };
+        match event {
+            KeyboardEvent::Ascii(c) => {
+                search.query.push(c);
+                // Stay on the current match while it still contains the longer term.
+                let before = search.found.map_or(self.history.len(), |index| index + 1);
+                self.update_search(before);
+            }
+            KeyboardEvent::Backspace => {
+                search.query.pop();
+                self.update_search(self.history.len());
+            }
+            KeyboardEvent::Chord {
+                ctrl: true,
+                key: Key::R,
+                ..
+            } => self.search_history(),
+            KeyboardEvent::Esc
+            | KeyboardEvent::CtrlC
+            | KeyboardEvent::Chord {
+                ctrl: true,
+                key: Key::G,
+                ..
+            } => {
+                self.search = None;
+                let draft = std::mem::take(&mut self.draft);
+                self.replace(&draft);
+            }
+            _ => {
+                // Any other key takes the match and then does what it normally does.
+                self.search = None;
+                return self.handle_event(event);
+            }
+        }
+        None
+    }
+
+    /// Completes the word at the cursor. A single candidate, or the prefix all
+    /// candidates share, is inserted. Otherwise the candidates are returned so
+    /// they can be listed.
+    pub fn complete(&mut self, completer: &dyn Completer) -> Vec<String> {
+        let Completion { start, candidates } = completer.complete(&self.line, self.cursor);
+        let start = start.min(self.cursor);
+        match candidates.len() {
+            0 => Vec::new(),
+            1 => {
+                self.replace_range(start, self.cursor, &candidates[0]);
+                Vec::new()
+            }
+            _ => {
+                let prefix = common_prefix(&candidates);
+                if prefix.chars().count() > self.cursor - start {
+                    self.replace_range(start, self.cursor, &prefix);
+                    Vec::new()
+                } else {
+                    candidates
+                }
+            }
+        }
+    }
+
+    /// What to show in front of the line: `prompt`, or the search term while searching.
+    pub fn prompt(&self, prompt: &str) -> String {
+        match &self.search {
+            Some(search) if search.failed => format!("search:{}?> ", search.query),
+            Some(search) => format!("search:{}> ", search.query),
+            None => prompt.to_string(),
+        }
+    }
+
+    /// Applies an editing key. Everything else, including `Enter`, is returned.
+    ///
+    /// Up and Down browse the history and Ctrl+R searches it. Ctrl+W deletes
+    /// a word, Ctrl+U deletes to the start of the line and Ctrl+K to its end;
+    /// Ctrl+A and Ctrl+E are Home and End.
+    pub fn handle_event(&mut self, event: KeyboardEvent) -> Option<KeyboardEvent> {
+        if self.search.is_some() {
+            return self.handle_search_event(event);
+        }
+
+        match event {
+            KeyboardEvent::Ascii(c) => self.push(c),
+            KeyboardEvent::Backspace => self.pop(),
+            KeyboardEvent::Delete => self.delete(),
+            KeyboardEvent::ArrowLeft => self.move_left(),
+            KeyboardEvent::ArrowRight => self.move_right(),
+            KeyboardEvent::ArrowUp => self.arrow_up(),
+            KeyboardEvent::ArrowDown => self.arrow_down(),
+            KeyboardEvent::Home => self.home(),
+            KeyboardEvent::End => self.end(),
+            KeyboardEvent::Chord {
+                ctrl: true,
+                alt: false,
+                opt: false,
+                key,
+            } => match key {
+                Key::W => self.delete_word(),
+                Key::U => self.kill_line(),
+                Key::K => self.kill_to_end(),
+                Key::A => self.home(),
+                Key::E => self.end(),
+                Key::R => self.search_history(),
+                _ => return Some(event),
+            },
+            _ => return Some(event),
+        }
+        None
+    }
+
+    /// The part of the line that fits in `width` columns, and the cursor's
+    /// column within it. Scrolls horizontally to keep the cursor visible.
+    pub fn view(&self, width: usize) -> (String, usize) {
+        let width = width.max(1);
+        let start = self.cursor.saturating_sub(width - 1);
+        let text = self.line.chars().skip(start).take(width).collect();
+        (text, self.cursor - start)
+    }
+
+    fn len(&self) -> usize {
+        self.line.chars().count()
+    }
+
+    fn byte_index(&self, position: usize) -> usize {
+        self.line
+            .char_indices()
+            .nth(position)
+            .map_or(self.line.len(), |(index, _)| index)
+    }
+
+    fn replace_range(&mut self, start: usize, end: usize, text: &str) {
+        self.delete_range(start, end);
+        self.line.insert_str(self.byte_index(start), text);
+        self.cursor = start + text.chars().count();
+    }
+
+    fn delete_range(&mut self, start: usize, end: usize) {
+        let (start_byte, end_byte) = (self.byte_index(start), self.byte_index(end));
+        self.line.replace_range(start_byte..end_byte, "");
+        self.cursor = start;
+    }
+}
+
+impl Default for CommandLine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::completion::word_start;
+
+    fn ctrl(key: Key) -> KeyboardEvent {
+        KeyboardEvent::Chord {
+            ctrl: true,
+            alt: false,
+            opt: false,
+            key,
+        }
+    }
+
+    fn type_text(line: &mut CommandLine, text: &str) {
+        for c in text.chars() {
+            assert_eq!(line.handle_event(KeyboardEvent::Ascii(c)), None);
+        }
+    }
+
+    #[test]
+    fn insert_and_delete_at_the_cursor() {
+        let mut line = CommandLine::new();
+        type_text(&mut line, "hllo wörld");
+        for _ in 0..9 {
+            line.handle_event(KeyboardEvent::ArrowLeft);
+        }
+        type_text(&mut line, "e");
+        assert_eq!(line.get(), "hello wörld");
+        assert_eq!(line.cursor(), 2);
+
+        line.handle_event(KeyboardEvent::End);
+        line.handle_event(KeyboardEvent::Backspace);
+        assert_eq!(line.get(), "hello wörl");
+        line.handle_event(KeyboardEvent::Home);
+        line.handle_event(KeyboardEvent::Delete);
+        assert_eq!(line.get(), "ello wörl");
+        line.handle_event(KeyboardEvent::Backspace);
+        assert_eq!((line.get(), line.cursor()), ("ello wörl", 0));
+
+        line.handle_event(ctrl(Key::E));
+        line.handle_event(KeyboardEvent::ArrowRight);
+        line.handle_event(KeyboardEvent::Delete);
+        assert_eq!((line.get(), line.cursor()), ("ello wörl", 9));
+        line.handle_event(ctrl(Key::A));
+        assert_eq!(line.cursor(), 0);
+    }
+
+    #[test]
+    fn word_and_line_deletion() {
+        let mut line = CommandLine::new();
+        line.set("foo bar  ");
+        line.handle_event(ctrl(Key::W));
+        assert_eq!(line.get(), "foo ");
+        line.handle_event(ctrl(Key::W));
+        line.handle_event(ctrl(Key::W));
+        assert_eq!((line.get(), line.cursor()), ("", 0));
+
+        line.set("one two three");
+        for _ in 0..6 {
+            line.move_left();
+        }
+        line.handle_event(ctrl(Key::K));
+        assert_eq!(line.get(), "one two");
+        line.move_left();
+        line.handle_event(ctrl(Key::U));
+        assert_eq!((line.get(), line.cursor()), ("o", 0));
+    }
+
+    #[test]
+    fn other_keys_are_returned() {
+        let mut line = CommandLine::new();
+        for event in [KeyboardEvent::Enter, KeyboardEvent::Tab, ctrl(Key::X)] {
+            assert_eq!(line.handle_event(event), Some(event));
+        }
+    }
+
+    #[test]
+    fn view_follows_the_cursor() {
+        let mut line = CommandLine::new();
+        line.set("0123456789");
+        assert_eq!(line.view(4), ("789".to_string(), 3));
+        line.home();
+        assert_eq!(line.view(4), ("0123".to_string(), 0));
+        for _ in 0..5 {
+            line.move_right();
+        }
+        assert_eq!(line.view(4), ("2345".to_string(), 3));
+    }
+
+    fn with_history(lines: &[&str]) -> CommandLine {
+        let mut line = CommandLine::new();
+        for entry in lines {
+            line.set(entry);
+            line.enter();
+        }
+        line
+    }
+
+    #[test]
+    fn arrows_browse_the_history() {
+        let mut line = with_history(&["one", "two", "three"]);
+        type_text(&mut line, "dra");
+        line.handle_event(KeyboardEvent::ArrowUp);
+        assert_eq!(line.get(), "three");
+        line.handle_event(KeyboardEvent::ArrowUp);
+        line.handle_event(KeyboardEvent::ArrowUp);
+        line.handle_event(KeyboardEvent::ArrowUp);
+        assert_eq!((line.get(), line.cursor()), ("one", 3));
+        line.handle_event(KeyboardEvent::ArrowDown);
+        assert_eq!(line.get(), "two");
+        line.handle_event(KeyboardEvent::ArrowDown);
+        line.handle_event(KeyboardEvent::ArrowDown);
+        assert_eq!(line.get(), "dra");
+        line.handle_event(KeyboardEvent::ArrowDown);
+        assert_eq!(line.get(), "dra");
+    }
+
+    #[test]
+    fn enter_adds_to_the_history() {
+        let mut line = with_history(&["one", "one", ""]);
+        assert_eq!(line.history().iter().collect::<Vec<_>>(), ["one"]);
+        line.set("two");
+        line.enter();
+        assert_eq!(line.get(), "");
+        assert_eq!(line.history().len(), 2);
+    }
+
+    #[test]
+    fn ctrl_r_searches_backwards() {
+        let mut line = with_history(&["ls apps", "cd apps", "cat notes", "ls docs"]);
+        type_text(&mut line, "draft");
+        line.handle_event(ctrl(Key::R));
+        assert_eq!(line.search_query(), Some(""));
+        assert_eq!(line.prompt("> "), "search:> ");
+
+        type_text(&mut line, "ap");
+        assert_eq!((line.get(), line.cursor()), ("cd apps", 3));
+        type_text(&mut line, "ps");
+        assert_eq!(line.get(), "cd apps");
+        line.handle_event(ctrl(Key::R));
+        assert_eq!((line.get(), line.cursor()), ("ls apps", 3));
+
+        // Nothing older: the match stays and the prompt says so.
+        line.handle_event(ctrl(Key::R));
+        assert_eq!(line.get(), "ls apps");
+        assert_eq!(line.prompt("> "), "search:apps?> ");
+
+        line.handle_event(KeyboardEvent::Backspace);
+        assert_eq!(line.get(), "cd apps");
+        assert_eq!(line.prompt("> "), "search:app> ");
+    }
+
+    #[test]
+    fn search_ends_with_esc_or_another_key() {
+        let mut line = with_history(&["ls apps", "cat notes"]);
+        type_text(&mut line, "draft");
+        line.handle_event(ctrl(Key::R));
+        type_text(&mut line, "cat");
+        line.handle_event(KeyboardEvent::Esc);
+        assert_eq!(line.search_query(), None);
+        assert_eq!(line.get(), "draft");
+
+        line.handle_event(ctrl(Key::R));
+        type_text(&mut line, "ls");
+        assert_eq!(
+            line.handle_event(KeyboardEvent::Enter),
+            Some(KeyboardEvent::Enter)
+        );
+        assert_eq!(line.search_query(), None);
+        assert_eq!(line.get(), "ls apps");
+
+        line.handle_event(ctrl(Key::R));
+        type_text(&mut line, "cat");
+        line.handle_event(KeyboardEvent::End);
+        type_text(&mut line, "!");
+        assert_eq!(line.get(), "cat notes!");
+    }
+
+    fn words(words: &'static [&'static str]) -> impl Fn(&str, usize) -> Completion {
+        move |line: &str, cursor: usize| {
+            let start = word_start(line, cursor, char::is_alphanumeric);
+            let prefix: String = line.chars().skip(start).take(cursor - start).collect();
+            let candidates = words
+                .iter()
+                .filter(|word| word.starts_with(&prefix))
+                .map(|word| word.to_string())
+                .collect();
+            Completion { start, candidates }
+        }
+    }
+
+    #[test]
+    fn single_candidate_replaces_the_word() {
+        let mut line = CommandLine::new();
+        type_text(&mut line, "2 fü to m");
+        for _ in 0..5 {
+            line.handle_event(KeyboardEvent::ArrowLeft);
+        }
+        let completer = words(&["füße", "meter"]);
+        assert!(line.complete(&completer).is_empty());
+        assert_eq!(line.get(), "2 füße to m");
+        assert_eq!(line.cursor(), 6);
+    }
+
+    #[test]
+    fn several_candidates_extend_to_their_common_prefix() {
+        let mut line = CommandLine::new();
+        type_text(&mut line, "3 m");
+        let completer = words(&["meter", "metre", "mile"]);
+        assert_eq!(line.complete(&completer), ["meter", "metre", "mile"]);
+        assert_eq!(line.get(), "3 m");
+
+        type_text(&mut line, "e");
+        assert!(line.complete(&completer).is_empty());
+        assert_eq!(line.get(), "3 met");
+        assert_eq!(line.cursor(), 5);
+        assert_eq!(line.complete(&completer), ["meter", "metre"]);
+
+        type_text(&mut line, "x");
+        assert!(line.complete(&completer).is_empty());
+        assert_eq!(line.get(), "3 metx");
+    }
+}
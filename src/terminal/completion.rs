@@ -0,0 +1,131 @@
+/// What a `Completer` found for the word at the cursor.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Completion {
+    /// Where the word being completed starts, in characters.
+    /// The candidates replace the line from here up to the cursor.
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+/// Supplies Tab completions for a terminal app's command line.
+pub trait Completer {
+    /// `cursor` is a character position in `line`.
+    fn complete(&self, line: &str, cursor: usize) -> Completion;
+}
+
+impl<F: Fn(&str, usize) -> Completion> Completer for F {
+    fn complete(&self, line: &str, cursor: usize) -> Completion {
+        self(line, cursor)
+    }
+}
+
+/// Completes words from a fixed list, e.g. the unit names known to rink.
+pub struct WordListCompleter {
+    words: Vec<String>,
+}
+
+impl WordListCompleter {
+    pub fn new(words: impl IntoIterator<Item = String>) -> Self {
+        let mut words: Vec<String> = words.into_iter().collect();
+        words.sort();
+        words.dedup();
+        Self { words }
+    }
+}
+
+impl Completer for WordListCompleter {
+    fn complete(&self, line: &str, cursor: usize) -> Completion {
+        let start = word_start(line, cursor, |c| c.is_alphanumeric() || c == '_');
+        let prefix: String = line.chars().skip(start).take(cursor - start).collect();
+        if prefix.is_empty() {
+            return Completion::default();
+        }
+
+        let first = self
+            .words
+            .partition_point(|word| word.as_str() < prefix.as_str());
+        let candidates = self.words[first..]
+            .iter()
+            .take_while(|word| word.starts_with(&prefix))
+            .cloned()
+            .collect();
+        Completion { start, candidates }
+    }
+}
+
+/// The character position where the word ending at `cursor` starts.
+pub fn word_start(line: &str, cursor: usize, is_word_char: impl Fn(char) -> bool) -> usize {
+    let chars: Vec<char> = line.chars().take(cursor).collect();
+    chars
+        .iter()
+        .rposition(|&c| !is_word_char(c))
+        .map_or(0, |index| index + 1)
+}
+
+/// The longest string all `candidates` start with.
+pub fn common_prefix(candidates: &[String]) -> String {
+    let Some(first) = candidates.first() else {
+        return String::new();
+    };
+    let mut len = first.len();
+    for candidate in &candidates[1..] {
+        len = first
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((index, c), _)| index + c.len_utf8())
+            .min(len);
+    }
+    first[..len].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn common_prefix_cuts_on_char_boundaries() {
+        assert_eq!(common_prefix(&[]), "");
+        assert_eq!(common_prefix(&strings(&["meter"])), "meter");
+        assert_eq!(common_prefix(&strings(&["meter", "metre", "mile"])), "m");
+        // 'ä' and 'ö' share their first UTF-8 byte.
+        assert_eq!(common_prefix(&strings(&["gräte", "gröte"])), "gr");
+        assert_eq!(common_prefix(&strings(&["größer", "größte"])), "größ");
+        assert_eq!(common_prefix(&strings(&["ä", "b"])), "");
+    }
+
+    #[test]
+    fn word_start_counts_characters() {
+        let is_word_char = |c: char| c.is_alphanumeric();
+        assert_eq!(word_start("2 ångström", 10, is_word_char), 2);
+        assert_eq!(word_start("2 ångström", 5, is_word_char), 2);
+        assert_eq!(word_start("2 ", 2, is_word_char), 2);
+        assert_eq!(word_start("km", 2, is_word_char), 0);
+    }
+
+    #[test]
+    fn word_list_filters_by_prefix() {
+        let completer = WordListCompleter::new(strings(&["mile", "meter", "metre", "m", "meter"]));
+        assert_eq!(
+            completer.complete("3 me", 4),
+            Completion {
+                start: 2,
+                candidates: strings(&["meter", "metre"]),
+            }
+        );
+        assert_eq!(
+            completer.complete("3 m to ft", 3).candidates,
+            ["m", "meter", "metre", "mile"]
+        );
+        assert_eq!(
+            completer.complete("met_x", 5).candidates,
+            Vec::<String>::new()
+        );
+        assert_eq!(completer.complete("3 ", 2), Completion::default());
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::error;
+
+use crate::os::storage::{save_atomic, SD_HISTORY_PATH};
+
+pub const DEFAULT_HISTORY_LEN: usize = 100;
+
+/// Previously entered command lines, oldest first.
+///
+/// With a file attached, the history is read from it on creation and
+/// rewritten after every new entry, one line per entry.
+#[derive(Debug)]
+pub struct History {
+    entries: VecDeque<String>,
+    limit: usize,
+    file: Option<PathBuf>,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            limit,
+            file: None,
+        }
+    }
+
+    /// A history kept in `path`. A missing file starts an empty history.
+    pub fn with_file(path: impl Into<PathBuf>, limit: usize) -> Self {
+        let path = path.into();
+        let mut history = Self::new(limit);
+        if let Ok(text) = fs::read_to_string(&path) {
+            for line in text.lines().filter(|line| !line.is_empty()) {
+                history.add(line);
+            }
+        }
+        history.file = Some(path);
+        history
+    }
+
+    /// The history of `app` on the SD card, in `/sdcard/history/<app>`.
+    pub fn for_app(app: &str) -> Self {
+        Self::with_file(Path::new(SD_HISTORY_PATH).join(app), DEFAULT_HISTORY_LEN)
+    }
+
+    /// Appends `line`, unless it is empty or repeats the newest entry.
+    pub fn push(&mut self, line: &str) {
+        if self.add(line) {
+            if let Err(err) = self.save() {
+                error!("Failed to save history: {:?}", err);
+            }
+        }
+    }
+
+    fn add(&mut self, line: &str) -> bool {
+        if line.trim().is_empty() || self.entries.back().map(String::as_str) == Some(line) {
+            return false;
+        }
+        self.entries.push_back(line.to_string());
+        while self.entries.len() > self.limit {
+            self.entries.pop_front();
+        }
+        true
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for entry in &self.entries {
+            text.push_str(entry);
+            text.push('\n');
+        }
+        save_atomic(path, text.as_bytes())
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+
+    /// The newest entry before `before` that contains `query`.
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries
+            .iter()
+            .take(before)
+            .rposition(|entry| entry.contains(query))
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os::storage::temp_dir;
+
+    fn entries(history: &History) -> Vec<&str> {
+        history.iter().collect()
+    }
+
+    #[test]
+    fn skips_blanks_and_repeats_and_keeps_the_newest() {
+        let mut history = History::new(3);
+        for line in ["one", "two", "two", " ", "three", "four", "two"] {
+            history.push(line);
+        }
+        assert_eq!(entries(&history), ["three", "four", "two"]);
+    }
+
+    #[test]
+    fn search_looks_back_from_before() {
+        let mut history = History::default();
+        for line in ["ls apps", "cd apps", "ls docs", "pwd"] {
+            history.push(line);
+        }
+        assert_eq!(history.search("ls", history.len()), Some(2));
+        assert_eq!(history.search("ls", 2), Some(0));
+        assert_eq!(history.search("ls", 0), None);
+        assert_eq!(history.search("rm", history.len()), None);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let path = temp_dir("history").join("app");
+
+        let mut history = History::with_file(&path, 2);
+        assert!(history.is_empty());
+        for line in ["one", "two", "three"] {
+            history.push(line);
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two\nthree\n");
+        // Saved through a temporary file, which is gone again.
+        let files: Vec<_> = fs::read_dir(path.parent().unwrap()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+
+        let history = History::with_file(&path, 5);
+        assert_eq!(entries(&history), ["two", "three"]);
+        let history = History::with_file(&path, 1);
+        assert_eq!(entries(&history), ["three"]);
+    }
+}
@@ -0,0 +1,832 @@
+pub mod ansi;
+mod command_line;
+pub mod completion;
+mod history;
+mod pager;
+mod screen_log;
+mod style;
+mod wrap;
+
+use std::fmt;
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+use embedded_graphics::Drawable;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::Point,
+    geometry::Size,
+    mono_font::MonoTextStyle,
+    pixelcolor::Rgb565,
+    primitives::{Line, Primitive, PrimitiveStyle, Rectangle},
+    text::Text,
+};
+
+use log::error;
+
+use crate::display_driver::FramebufferTarget;
+use crate::swapchain::{DoubleBuffer, OwnedDoubleBuffer};
+use crate::typing::KeyboardEvent;
+use ansi::Screen;
+pub use command_line::CommandLine;
+use completion::Completer;
+pub use history::{History, DEFAULT_HISTORY_LEN};
+use pager::Pager;
+pub use screen_log::{LogSink, ScreenLogger};
+use style::MARGIN_LEFT;
+pub use style::{TerminalSettings, TerminalStyle, Theme};
+pub use wrap::{display_width, wrap_text};
+
+const PROMPT: &str = "> ";
+/// Tab lists at most this many candidates.
+const MAX_LISTED_COMPLETIONS: usize = 40;
+
+struct TerminalRows {
+    screen: Screen,
+}
+
+impl TerminalRows {
+    fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            screen: Screen::new(cols, rows),
+        }
+    }
+
+    fn print(&self, fbuf: &mut impl DrawTarget<Color = Rgb565>, style: &TerminalStyle) {
+        let char_width = style.char_width();
+        let line_height = style.line_height();
+        for i in 0..self.screen.rows() {
+            let cells = self.screen.view_row(i);
+            let top = (i * line_height) as i32;
+
+            // Draw runs of cells that share a style with a single Text.
+            let mut start = 0;
+            while start < cells.len() {
+                let cell_style = cells[start].style;
+                let end = cells[start..]
+                    .iter()
+                    .position(|cell| cell.style != cell_style)
+                    .map_or(cells.len(), |len| start + len);
+                let text: String = cells[start..end].iter().map(|cell| cell.c).collect();
+
+                let fg = cell_style.fg.unwrap_or(style.foreground);
+                let (fg, bg) = if cell_style.inverse {
+                    (cell_style.bg.unwrap_or(style.background), Some(fg))
+                } else {
+                    (fg, cell_style.bg)
+                };
+
+                let x = (MARGIN_LEFT + start * char_width) as i32;
+                if let Some(bg) = bg {
+                    let _ = Rectangle::new(
+                        Point::new(x, top),
+                        Size::new(((end - start) * char_width) as u32, line_height as u32),
+                    )
+                    .into_styled(PrimitiveStyle::with_fill(bg))
+                    .draw(fbuf);
+                }
+                if !text.trim_end().is_empty() {
+                    let font = if cell_style.bold {
+                        style.bold_font
+                    } else {
+                        style.font
+                    };
+                    let _ = Text::new(
+                        &text,
+                        Point::new(x, top + style.baseline() as i32),
+                        MonoTextStyle::new(font, fg),
+                    )
+                    .draw(fbuf);
+                }
+                start = end;
+            }
+        }
+
+        if self.screen.view_offset() > 0 {
+            self.print_scrollbar(fbuf, style);
+        }
+    }
+
+    /// A thumb on the right edge showing which part of the scrollback is on display.
+    fn print_scrollbar(&self, fbuf: &mut impl DrawTarget<Color = Rgb565>, style: &TerminalStyle) {
+        let rows = self.screen.rows();
+        let total = self.screen.scrollback_len() + rows;
+        let first = self.screen.scrollback_len() - self.screen.view_offset();
+        let track = rows * style.line_height();
+        let height = (track * rows / total).max(4);
+        let top = track * first / total;
+
+        let _ = Rectangle::new(
+            Point::new(fbuf.bounding_box().size.width as i32 - 2, top as i32),
+            Size::new(2, height as u32),
+        )
+        .into_styled(PrimitiveStyle::with_fill(style.foreground))
+        .draw(fbuf);
+    }
+}
+
+/// A terminal drawn through a `DoubleBuffer`.
+///
+/// Frames are handed to the presenter thread instead of being written to
+/// the display in place, and only when something changed since the last one.
+pub struct FbTerminal<'a, const W: usize, const H: usize> {
+    buffers: OwnedDoubleBuffer<W, H>,
+    rows: TerminalRows,
+    style: TerminalStyle,
+    command_line: CommandLine,
+    completer: Option<Box<dyn Completer + 'a>>,
+    /// Whether the bottom of the screen is taken by the command line.
+    command_line_visible: bool,
+    log: Option<LogSink>,
+    /// The rest of an output printed with `println_paged`.
+    pager: Option<Pager>,
+    /// draw to framebuffer after push_line
+    auto_draw: bool,
+    /// `println` holds back its newline until more output arrives,
+    /// so the latest line stays on the bottom row.
+    newline_pending: bool,
+    /// Something changed since the last frame was sent.
+    dirty: bool,
+}
+
+impl<'a, const W: usize, const H: usize> FbTerminal<'a, W, H> {
+    /// `buffers` should already have its presenter thread running.
+    pub fn new(buffers: OwnedDoubleBuffer<W, H>) -> FbTerminal<'a, W, H> {
+        let style = TerminalStyle::default();
+        let rows = TerminalRows::new(style.columns(W), style.rows(H));
+
+        FbTerminal {
+            buffers,
+            rows,
+            style,
+            command_line: CommandLine::new(),
+            completer: None,
+            command_line_visible: true,
+            log: None,
+            pager: None,
+            auto_draw: false,
+            newline_pending: false,
+            dirty: true,
+        }
+    }
+
+    pub fn auto_draw(&mut self, auto: bool) {
+        self.auto_draw = auto;
+    }
+
+    pub fn style(&self) -> &TerminalStyle {
+        &self.style
+    }
+
+    /// Switches fonts and colors. A font of another size changes the number
+    /// of rows and columns; the text on screen is kept.
+    pub fn set_style(&mut self, style: TerminalStyle) {
+        self.style = style;
+        self.resize_screen();
+    }
+
+    /// Hides the command line, so that the screen takes up the whole display.
+    /// For full screen apps that read keys themselves, like the editor.
+    pub fn set_command_line_visible(&mut self, visible: bool) {
+        self.command_line_visible = visible;
+        self.resize_screen();
+    }
+
+    fn resize_screen(&mut self) {
+        let rows = if self.command_line_visible {
+            self.style.rows(H)
+        } else {
+            (H / self.style.line_height()).max(1)
+        };
+        self.rows.screen.resize(self.style.columns(W), rows);
+        self.dirty = true;
+    }
+
+    /// Makes Tab complete the command line with `completer`.
+    pub fn set_completer(&mut self, completer: impl Completer + 'a) {
+        self.completer = Some(Box::new(completer));
+    }
+
+    /// Prints the lines logged to `sink` whenever the terminal draws.
+    pub fn show_log(&mut self, sink: LogSink) {
+        self.log = Some(sink);
+    }
+
+    /// The emulated screen, e.g. to look up the cursor-key mode the host asked for.
+    pub fn screen(&self) -> &Screen {
+        &self.rows.screen
+    }
+
+    pub fn screen_mut(&mut self) -> &mut Screen {
+        self.dirty = true;
+        &mut self.rows.screen
+    }
+
+    /// The framebuffers, to show something else for a while, e.g. the progress
+    /// screen of `chainload::flash_and_reboot`. The next `draw` brings the terminal back.
+    pub fn buffers_mut(&mut self) -> &mut DoubleBuffer<W, H> {
+        self.dirty = true;
+        &mut self.buffers
+    }
+
+    pub fn command_line(&self) -> &CommandLine {
+        &self.command_line
+    }
+
+    pub fn command_line_mut(&mut self) -> &mut CommandLine {
+        self.dirty = true;
+        &mut self.command_line
+    }
+
+    /// Scrolls through the scrollback: PageUp/PageDown move by a page, and once
+    /// scrolled back the arrow keys move by a line. Any other event jumps back
+    /// to the bottom and goes to the command line editor, or to the completer
+    /// for Tab. Events none of them use, such as `Enter`, are returned for
+    /// the app to handle.
+    pub fn handle_event(&mut self, event: KeyboardEvent) -> Option<KeyboardEvent> {
+        if self.pager.is_some() {
+            self.handle_pager_event(event);
+            return None;
+        }
+
+        let screen = &mut self.rows.screen;
+        let page = screen.rows().saturating_sub(1).max(1);
+        let view_offset = screen.view_offset();
+        let unhandled = match event {
+            KeyboardEvent::PageUp => {
+                screen.scroll_view_up(page);
+                None
+            }
+            KeyboardEvent::PageDown => {
+                screen.scroll_view_down(page);
+                None
+            }
+            KeyboardEvent::ArrowUp if screen.view_offset() > 0 => {
+                screen.scroll_view_up(1);
+                None
+            }
+            KeyboardEvent::ArrowDown if screen.view_offset() > 0 => {
+                screen.scroll_view_down(1);
+                None
+            }
+            KeyboardEvent::Tab if self.completer.is_some() => {
+                screen.scroll_to_bottom();
+                self.complete();
+                None
+            }
+            _ => {
+                screen.scroll_to_bottom();
+                self.command_line.handle_event(event)
+            }
+        };
+
+        if unhandled.is_none() || self.rows.screen.view_offset() != view_offset {
+            self.dirty = true;
+            if self.auto_draw {
+                self.draw();
+            }
+        }
+        unhandled
+    }
+
+    /// While an output is being paged, Space, Enter and PageDown show the next
+    /// page and ArrowDown the next line. `q`, Esc and Ctrl+C skip the rest.
+    /// Other keys are ignored until the output is done.
+    fn handle_pager_event(&mut self, event: KeyboardEvent) {
+        let page_len = self.page_len();
+        let Some(pager) = &mut self.pager else {
+            return;
+        };
+        let lines = match event {
+            KeyboardEvent::Ascii(' ') | KeyboardEvent::Enter | KeyboardEvent::PageDown => {
+                pager.next_lines(page_len)
+            }
+            KeyboardEvent::ArrowDown => pager.next_lines(1),
+            KeyboardEvent::Ascii('q') | KeyboardEvent::Esc | KeyboardEvent::CtrlC => {
+                self.pager = None;
+                Vec::new()
+            }
+            _ => return,
+        };
+        if self.pager.as_ref().is_some_and(Pager::is_done) {
+            self.pager = None;
+        }
+
+        self.rows.screen.scroll_to_bottom();
+        self.print_lines(lines);
+        self.dirty = true;
+        if self.auto_draw {
+            self.draw();
+        }
+    }
+
+    /// Lines per page for `println_paged`, keeping one line of the previous page.
+    fn page_len(&self) -> usize {
+        self.rows.screen.rows().saturating_sub(1).max(1)
+    }
+
+    fn complete(&mut self) {
+        let Some(completer) = &self.completer else {
+            return;
+        };
+        let candidates = self.command_line.complete(completer.as_ref());
+        if candidates.len() < 2 {
+            return;
+        }
+
+        let shown = candidates.len().min(MAX_LISTED_COMPLETIONS);
+        let mut list = candidates[..shown].join("  ");
+        if candidates.len() > shown {
+            list.push_str(&format!("  (+{} more)", candidates.len() - shown));
+        }
+        self.println(&list);
+    }
+
+    /// Writes text that may contain VT100/xterm escape sequences.
+    pub fn write(&mut self, text: &str) {
+        self.write_bytes(text.as_bytes());
+    }
+
+    /// Same as `write` for a raw byte stream, e.g. from a serial port.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.feed(bytes);
+
+        if self.auto_draw {
+            self.draw();
+        }
+    }
+
+    /// Prints `res` as one or more lines, word-wrapped to the screen width.
+    pub fn println(&mut self, res: &str) {
+        self.print_wrapped(res);
+
+        if self.auto_draw {
+            self.draw();
+        }
+    }
+
+    /// Like `println`, but an output taller than the screen stops after the
+    /// first page. The command line then shows how many lines are left, and
+    /// the keys page through them until the end, or until `q`.
+    pub fn println_paged(&mut self, res: &str) {
+        let lines = wrap_text(res, self.rows.screen.cols());
+        if lines.len() <= self.rows.screen.rows() {
+            self.print_lines(lines);
+        } else {
+            let mut pager = Pager::new(lines);
+            let first = pager.next_lines(self.page_len());
+            self.print_lines(first);
+            self.pager = Some(pager);
+        }
+
+        if self.auto_draw {
+            self.draw();
+        }
+    }
+
+    fn print_wrapped(&mut self, res: &str) {
+        self.print_lines(wrap_text(res, self.rows.screen.cols()));
+    }
+
+    fn print_lines(&mut self, lines: Vec<String>) {
+        for line in lines {
+            self.feed(line.as_bytes());
+            self.newline_pending = true;
+        }
+    }
+
+    /// Like `write_bytes`, but a `\n` starts a new line the way `println` does.
+    fn write_lines(&mut self, bytes: &[u8]) {
+        let mut lines = bytes.split(|&b| b == b'\n').peekable();
+        while let Some(line) = lines.next() {
+            if !line.is_empty() {
+                self.feed(line);
+            }
+            if lines.peek().is_some() {
+                if self.newline_pending {
+                    self.feed(b"");
+                }
+                self.newline_pending = true;
+            }
+        }
+
+        if self.auto_draw {
+            self.draw();
+        }
+    }
+
+    fn feed(&mut self, bytes: &[u8]) {
+        if self.newline_pending {
+            self.newline_pending = false;
+            self.rows.screen.write(b"\r\n");
+        }
+        self.rows.screen.write(bytes);
+        self.dirty = true;
+    }
+
+    pub fn enter(&mut self) {
+        self.println(&format!("{}{}", PROMPT, self.command_line.get()));
+        self.command_line.enter();
+    }
+
+    /// Sends a new frame to the display if anything changed since the last one.
+    /// An unchanged frame returns at once, so a loop calling this should sleep
+    /// to give the idle task a turn.
+    pub fn draw(&mut self) {
+        if let Some(sink) = &self.log {
+            for line in sink.take() {
+                self.print_wrapped(&line);
+            }
+        }
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let style = self.style;
+        let char_width = style.char_width();
+        let fbuf = self.buffers.swap_framebuffer();
+        fbuf.clear(style.background).unwrap();
+
+        if self.command_line_visible {
+            let separator_y = (H - style.prompt_height()) as i32;
+            Line::new(
+                Point::new(0, separator_y),
+                Point::new(W as i32, separator_y),
+            )
+            .into_styled(PrimitiveStyle::with_stroke(style.separator, 1))
+            .draw(fbuf)
+            .unwrap();
+
+            let prompt = match &self.pager {
+                Some(pager) => format!("--More-- ({} lines) ", pager.remaining()),
+                None => self.command_line.prompt(PROMPT),
+            };
+            let prompt_len = prompt.chars().count();
+            let prompt_cols = style.columns(W).saturating_sub(prompt_len);
+            let (visible, cursor) = match self.pager {
+                Some(_) => (String::new(), 0),
+                None => self.command_line.view(prompt_cols),
+            };
+            let baseline = (H - style.line_height() - 2 + style.baseline()) as i32;
+            let end = Text::new(
+                &prompt,
+                Point::new(MARGIN_LEFT as i32, baseline),
+                MonoTextStyle::new(style.font, style.prompt),
+            )
+            .draw(fbuf)
+            .unwrap();
+            Text::new(
+                &visible,
+                end,
+                MonoTextStyle::new(style.font, style.foreground),
+            )
+            .draw(fbuf)
+            .unwrap();
+
+            Rectangle::new(
+                Point::new(
+                    (MARGIN_LEFT + (prompt_len + cursor) * char_width) as i32,
+                    H as i32 - 3,
+                ),
+                Size::new(char_width as u32, 2),
+            )
+            .into_styled(PrimitiveStyle::with_fill(style.foreground))
+            .draw(fbuf)
+            .unwrap();
+        }
+
+        self.rows.print(fbuf, &style);
+
+        self.buffers.send_framebuffer();
+
+        // The frame did not make it to the display, so send the next one anyway.
+        if let Some(err) = self.buffers.take_error() {
+            error!("Failed to present frame: {:?}", err);
+            self.dirty = true;
+        }
+    }
+
+    /// Sends a new frame even if nothing changed.
+    pub fn redraw(&mut self) {
+        self.dirty = true;
+        self.draw();
+    }
+}
+
+impl<const W: usize, const H: usize> fmt::Write for FbTerminal<'_, W, H> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_lines(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl<const W: usize, const H: usize> io::Write for FbTerminal<'_, W, H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_lines(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.draw();
+        Ok(())
+    }
+}
+
+/// An `FbTerminal` with its own framebuffers, presenting to `display`
+/// from a separate thread. It starts with the theme chosen in the settings
+/// on the SD card.
+pub struct OwnedTerminal<'a, const W: usize, const H: usize> {
+    terminal: FbTerminal<'a, W, H>,
+}
+
+impl<'a, const W: usize, const H: usize> OwnedTerminal<'a, W, H> {
+    pub fn new(display: impl FramebufferTarget + Send + 'static) -> Self {
+        let mut buffers = OwnedDoubleBuffer::new();
+        buffers.start_thread(display);
+        let mut terminal = FbTerminal::new(buffers);
+        terminal.set_style(TerminalStyle::from_sd_card());
+        Self { terminal }
+    }
+}
+
+impl<'a, const W: usize, const H: usize> Deref for OwnedTerminal<'a, W, H> {
+    type Target = FbTerminal<'a, W, H>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.terminal
+    }
+}
+
+impl<'a, const W: usize, const H: usize> DerefMut for OwnedTerminal<'a, W, H> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.terminal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::display_driver::FbWriteError;
+    use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    fn terminal(lines: usize) -> FbTerminal<'static, SCREEN_WIDTH, SCREEN_HEIGHT> {
+        let mut terminal = FbTerminal::new(OwnedDoubleBuffer::new());
+        for i in 0..lines {
+            terminal.println(&format!("line {}", i));
+        }
+        terminal
+    }
+
+    #[test]
+    fn page_and_arrow_keys_scroll_back() {
+        let mut terminal = terminal(30);
+        let page = terminal.screen().rows() - 1;
+
+        // The arrows belong to the command line until the view is scrolled back.
+        assert_eq!(terminal.handle_event(KeyboardEvent::ArrowUp), None);
+        assert_eq!(terminal.screen().view_offset(), 0);
+
+        assert_eq!(terminal.handle_event(KeyboardEvent::PageUp), None);
+        assert_eq!(terminal.screen().view_offset(), page);
+        terminal.handle_event(KeyboardEvent::ArrowUp);
+        assert_eq!(terminal.screen().view_offset(), page + 1);
+        terminal.handle_event(KeyboardEvent::ArrowDown);
+        terminal.handle_event(KeyboardEvent::PageDown);
+        assert_eq!(terminal.screen().view_offset(), 0);
+
+        terminal.handle_event(KeyboardEvent::PageUp);
+        assert_eq!(terminal.handle_event(KeyboardEvent::Ascii('a')), None);
+        assert_eq!(terminal.screen().view_offset(), 0);
+        assert_eq!(terminal.command_line().get(), "a");
+    }
+
+    #[test]
+    fn enter_goes_back_to_the_bottom() {
+        let mut terminal = terminal(30);
+        terminal.handle_event(KeyboardEvent::PageUp);
+        assert_eq!(
+            terminal.handle_event(KeyboardEvent::Enter),
+            Some(KeyboardEvent::Enter)
+        );
+        assert_eq!(terminal.screen().view_offset(), 0);
+    }
+
+    fn shows(terminal: &FbTerminal<'static, SCREEN_WIDTH, SCREEN_HEIGHT>, text: &str) -> bool {
+        let screen = terminal.screen();
+        (0..screen.rows()).any(|row| screen.row_text(row).trim_end() == text)
+    }
+
+    fn long_output(lines: usize) -> String {
+        (0..lines)
+            .map(|i| format!("result {}", i))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn short_output_is_not_paged() {
+        let mut terminal = terminal(0);
+        terminal.println_paged(&long_output(3));
+        assert!(shows(&terminal, "result 2"));
+        assert_eq!(terminal.handle_event(KeyboardEvent::Ascii('a')), None);
+        assert_eq!(terminal.command_line().get(), "a");
+    }
+
+    #[test]
+    fn long_output_stops_after_a_page() {
+        let mut terminal = terminal(0);
+        let page = terminal.screen().rows() - 1;
+        terminal.println_paged(&long_output(page * 2 + 2));
+        assert!(shows(&terminal, &format!("result {}", page - 1)));
+        assert!(!shows(&terminal, &format!("result {}", page)));
+
+        // Other keys are ignored while paging.
+        assert_eq!(terminal.handle_event(KeyboardEvent::Ascii('a')), None);
+        assert_eq!(terminal.command_line().get(), "");
+
+        terminal.handle_event(KeyboardEvent::ArrowDown);
+        assert!(shows(&terminal, &format!("result {}", page)));
+        assert!(!shows(&terminal, &format!("result {}", page + 1)));
+
+        terminal.handle_event(KeyboardEvent::Ascii(' '));
+        assert!(shows(&terminal, &format!("result {}", page * 2)));
+        terminal.handle_event(KeyboardEvent::Enter);
+        assert!(shows(&terminal, &format!("result {}", page * 2 + 1)));
+
+        // Done, so keys reach the command line again.
+        terminal.handle_event(KeyboardEvent::Ascii('a'));
+        assert_eq!(terminal.command_line().get(), "a");
+    }
+
+    #[test]
+    fn q_skips_the_rest() {
+        let mut terminal = terminal(0);
+        let page = terminal.screen().rows() - 1;
+        terminal.println_paged(&long_output(page * 3));
+        terminal.handle_event(KeyboardEvent::Ascii('q'));
+        assert!(!shows(&terminal, &format!("result {}", page)));
+
+        terminal.handle_event(KeyboardEvent::Ascii(' '));
+        assert!(!shows(&terminal, &format!("result {}", page)));
+        assert_eq!(terminal.command_line().get(), " ");
+    }
+
+    /// The screen's rows without trailing blanks, up to the last one with text.
+    fn text(terminal: &FbTerminal<'static, SCREEN_WIDTH, SCREEN_HEIGHT>) -> Vec<String> {
+        let screen = terminal.screen();
+        let mut rows: Vec<String> = (0..screen.rows())
+            .map(|row| screen.row_text(row).trim_end().to_string())
+            .collect();
+        while rows.last().is_some_and(String::is_empty) {
+            rows.pop();
+        }
+        rows
+    }
+
+    #[test]
+    fn writes_continue_the_current_line() {
+        use std::fmt::Write as _;
+
+        let mut terminal = terminal(0);
+        write!(terminal, "hel").unwrap();
+        write!(terminal, "lo\nwor").unwrap();
+        writeln!(terminal, "ld").unwrap();
+        assert_eq!(text(&terminal), ["hello", "world"]);
+
+        // The last newline is held back until more output arrives.
+        assert_eq!(terminal.screen().cursor(), (1, 5));
+        writeln!(terminal, "!").unwrap();
+        assert_eq!(text(&terminal), ["hello", "world", "!"]);
+    }
+
+    #[test]
+    fn crlf_is_one_line_break() {
+        use std::fmt::Write as _;
+
+        let mut terminal = terminal(0);
+        write!(terminal, "one\r\ntwo\nthree\n\nfive\r\n").unwrap();
+        terminal.println("six");
+        assert_eq!(text(&terminal), ["one", "two", "three", "", "five", "six"]);
+    }
+
+    #[test]
+    fn io_write_takes_any_bytes() {
+        use std::io::Write as _;
+
+        let mut terminal = terminal(0);
+        assert_eq!(
+            io::Write::write(&mut terminal, b"bad \xff end\n").unwrap(),
+            10
+        );
+        // A character split across two writes still comes out whole.
+        terminal.write_all(b"gr\xc3").unwrap();
+        terminal.write_all(b"\xbcn\n").unwrap();
+        terminal.flush().unwrap();
+        assert_eq!(text(&terminal), ["bad \u{fffd} end", "grün"]);
+    }
+
+    #[test]
+    fn set_style_resizes_the_screen() {
+        let mut terminal = terminal(0);
+        let size = |terminal: &FbTerminal<'static, SCREEN_WIDTH, SCREEN_HEIGHT>| {
+            (terminal.screen().cols(), terminal.screen().rows())
+        };
+        assert_eq!(size(&terminal), (29, 9));
+        terminal.println("kept");
+
+        let line = "x".repeat(35);
+        terminal.set_style(TerminalStyle::compact());
+        assert_eq!(size(&terminal), (39, 12));
+        terminal.println(&line);
+        assert_eq!(text(&terminal), ["kept", line.as_str()]);
+
+        // Output after the change wraps at the new width.
+        terminal.set_style(TerminalStyle::classic());
+        assert_eq!(size(&terminal), (29, 9));
+        terminal.println(&line);
+        let rows = text(&terminal);
+        assert_eq!(rows[rows.len() - 2..], ["x".repeat(29), "x".repeat(6)]);
+    }
+
+    #[test]
+    fn draw_prints_the_log() {
+        let mut terminal = terminal(0);
+        let sink = LogSink::new();
+        terminal.show_log(sink.clone());
+        terminal.println("before");
+        sink.push("\x1b[33mW\x1b[0m careful".to_string());
+        assert_eq!(text(&terminal), ["before"]);
+
+        terminal.draw();
+        assert_eq!(text(&terminal), ["before", "W careful"]);
+        assert!(sink.take().is_empty());
+    }
+
+    /// Counts the frames that reach it, and fails them while `failing` is set.
+    #[derive(Clone, Default)]
+    struct CountingTarget {
+        frames: Arc<Mutex<usize>>,
+        failing: Arc<Mutex<bool>>,
+    }
+
+    impl CountingTarget {
+        fn frames(&self) -> usize {
+            *self.frames.lock().unwrap()
+        }
+    }
+
+    impl FramebufferTarget for CountingTarget {
+        fn eat_framebuffer(&mut self, _: &[u16]) -> Result<(), FbWriteError> {
+            *self.frames.lock().unwrap() += 1;
+            if *self.failing.lock().unwrap() {
+                return Err(FbWriteError::Error);
+            }
+            Ok(())
+        }
+    }
+
+    fn counted_terminal() -> (
+        FbTerminal<'static, SCREEN_WIDTH, SCREEN_HEIGHT>,
+        CountingTarget,
+    ) {
+        let target = CountingTarget::default();
+        let mut buffers = OwnedDoubleBuffer::new();
+        buffers.present_inline(target.clone());
+        (FbTerminal::new(buffers), target)
+    }
+
+    #[test]
+    fn draw_skips_unchanged_frames() {
+        let (mut terminal, target) = counted_terminal();
+        terminal.draw();
+        assert_eq!(target.frames(), 1);
+        terminal.draw();
+        terminal.draw();
+        assert_eq!(target.frames(), 1);
+
+        terminal.println("hello");
+        terminal.draw();
+        assert_eq!(target.frames(), 2);
+        terminal.handle_event(KeyboardEvent::Ascii('x'));
+        terminal.draw();
+        terminal.draw();
+        assert_eq!(target.frames(), 3);
+    }
+
+    #[test]
+    fn draw_sends_again_after_a_failed_frame() {
+        let (mut terminal, target) = counted_terminal();
+        *target.failing.lock().unwrap() = true;
+        terminal.draw();
+        assert_eq!(target.frames(), 1);
+
+        *target.failing.lock().unwrap() = false;
+        terminal.draw();
+        assert_eq!(target.frames(), 2);
+        terminal.draw();
+        assert_eq!(target.frames(), 2);
+    }
+}
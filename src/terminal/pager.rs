@@ -0,0 +1,47 @@
+use std::collections::VecDeque;
+
+/// Lines of a long output still waiting to be shown, a page at a time.
+pub struct Pager {
+    lines: VecDeque<String>,
+}
+
+impl Pager {
+    pub fn new(lines: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            lines: lines.into_iter().collect(),
+        }
+    }
+
+    /// Takes up to `len` of the remaining lines.
+    pub fn next_lines(&mut self, len: usize) -> Vec<String> {
+        let len = len.min(self.lines.len());
+        self.lines.drain(..len).collect()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_until_done() {
+        let mut pager = Pager::new((0..5).map(|i| i.to_string()));
+        assert_eq!(pager.remaining(), 5);
+
+        assert_eq!(pager.next_lines(2), ["0", "1"]);
+        assert_eq!(pager.remaining(), 3);
+        assert!(!pager.is_done());
+
+        assert_eq!(pager.next_lines(10), ["2", "3", "4"]);
+        assert!(pager.is_done());
+        assert!(pager.next_lines(1).is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Lines kept while no terminal takes them; older ones are dropped.
+const MAX_PENDING_LINES: usize = 100;
+
+/// Log lines on their way to a terminal.
+///
+/// The logger may run on any thread, so it only queues formatted lines here.
+/// The terminal prints them the next time it draws, see `FbTerminal::show_log`.
+/// Clones share the same queue.
+#[derive(Clone, Default)]
+pub struct LogSink {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl LogSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        lines.push_back(line);
+        while lines.len() > MAX_PENDING_LINES {
+            lines.pop_front();
+        }
+    }
+
+    /// Takes all queued lines, oldest first.
+    pub fn take(&self) -> Vec<String> {
+        self.lines.lock().unwrap().drain(..).collect()
+    }
+}
+
+/// A `log::Log` that passes records on to `inner` and also queues those
+/// at or above `level` in a `LogSink`, colored by level.
+pub struct ScreenLogger<L> {
+    inner: L,
+    sink: LogSink,
+    level: LevelFilter,
+}
+
+impl<L: Log> ScreenLogger<L> {
+    pub fn new(inner: L, sink: LogSink, level: LevelFilter) -> Self {
+        Self { inner, sink, level }
+    }
+}
+
+impl<L: Log> Log for ScreenLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+        if record.level() <= self.level {
+            self.sink.push(format_record(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// `E message`, with the level letter in the level's color.
+fn format_record(record: &Record) -> String {
+    let (letter, color) = match record.level() {
+        Level::Error => ('E', 31),
+        Level::Warn => ('W', 33),
+        Level::Info => ('I', 32),
+        Level::Debug => ('D', 36),
+        Level::Trace => ('T', 90),
+    };
+    format!("\x1b[{}m{}\x1b[0m {}", color, letter, record.args())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Counts the records it is given, like a serial logger at `Info`.
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    impl Log for Counter {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= Level::Info
+        }
+
+        fn log(&self, _: &Record) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn log(logger: &impl Log, level: Level, message: &str) {
+        logger.log(
+            &Record::builder()
+                .level(level)
+                .args(format_args!("{}", message))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn queues_records_at_or_above_the_level() {
+        let sink = LogSink::new();
+        let logger = ScreenLogger::new(Counter::default(), sink.clone(), LevelFilter::Warn);
+        log(&logger, Level::Error, "failed");
+        log(&logger, Level::Warn, "careful");
+        log(&logger, Level::Info, "hello");
+        log(&logger, Level::Debug, "details");
+
+        assert_eq!(
+            sink.take(),
+            ["\x1b[31mE\x1b[0m failed", "\x1b[33mW\x1b[0m careful"]
+        );
+        assert!(sink.take().is_empty());
+        // The inner logger still gets what it is enabled for.
+        assert_eq!(logger.inner.0.load(Ordering::Relaxed), 3);
+        assert!(logger.enabled(&Metadata::builder().level(Level::Info).build()));
+        assert!(!logger.enabled(&Metadata::builder().level(Level::Debug).build()));
+    }
+
+    #[test]
+    fn sink_keeps_the_newest_lines() {
+        let sink = LogSink::new();
+        for i in 0..MAX_PENDING_LINES + 5 {
+            sink.clone().push(i.to_string());
+        }
+        let lines = sink.take();
+        assert_eq!(lines.len(), MAX_PENDING_LINES);
+        assert_eq!(lines[0], "5");
+    }
+}
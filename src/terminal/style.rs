@@ -0,0 +1,197 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_6X10, FONT_8X13, FONT_8X13_BOLD},
+        MonoFont,
+    },
+    pixelcolor::{Rgb565, Rgb888, RgbColor},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::os::storage;
+
+/// Space left of the first column, in pixels.
+pub const MARGIN_LEFT: usize = 3;
+
+/// The terminal theme in `SD_SETTINGS_PATH`.
+pub const SETTINGS_FILE: &str = "terminal.cfg";
+
+/// The built-in styles, by name.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Theme {
+    #[default]
+    Classic,
+    Compact,
+    HighContrast,
+}
+
+/// The user's terminal settings, kept as JSON on the SD card.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TerminalSettings {
+    /// `"classic"`, `"compact"` or `"highContrast"`.
+    pub theme: Theme,
+}
+
+impl TerminalSettings {
+    /// `SD_SETTINGS_PATH/SETTINGS_FILE`.
+    pub fn sd_card_path() -> PathBuf {
+        storage::settings_path(SETTINGS_FILE)
+    }
+
+    /// The settings in `path`, or the defaults if it is missing or broken.
+    pub fn load(path: &Path) -> Self {
+        storage::load_settings(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        storage::save_settings(path, self)
+    }
+}
+
+/// Fonts and colors of an `FbTerminal`. The number of rows and columns
+/// follows from the font and the screen size.
+#[derive(Clone, Copy)]
+pub struct TerminalStyle {
+    pub font: &'static MonoFont<'static>,
+    /// Used for bold text (SGR 1). May be the same as `font`.
+    pub bold_font: &'static MonoFont<'static>,
+    pub foreground: Rgb565,
+    pub background: Rgb565,
+    pub prompt: Rgb565,
+    /// The line between the output and the command line.
+    pub separator: Rgb565,
+}
+
+impl TerminalStyle {
+    /// 8x13 light grey on black, 9 rows of 29 columns on the Cardputer.
+    pub fn classic() -> Self {
+        Self {
+            font: &FONT_8X13,
+            bold_font: &FONT_8X13_BOLD,
+            foreground: Rgb565::new(252, 252, 252),
+            background: Rgb565::new(0, 1, 0),
+            prompt: Rgb565::new(252, 252, 252),
+            separator: Rgb888::new(77 >> 3, 85 >> 2, 94 >> 3).into(),
+        }
+    }
+
+    /// 6x10, 12 rows of 39 columns on the Cardputer.
+    pub fn compact() -> Self {
+        Self {
+            font: &FONT_6X10,
+            bold_font: &FONT_6X10,
+            ..Self::classic()
+        }
+    }
+
+    /// Bold white on black with a yellow prompt, 9 rows of 29 columns on the
+    /// Cardputer. Chosen with `"theme": "highContrast"` in `SETTINGS_FILE`.
+    pub fn high_contrast() -> Self {
+        Self {
+            font: &FONT_8X13_BOLD,
+            bold_font: &FONT_8X13_BOLD,
+            foreground: Rgb565::WHITE,
+            background: Rgb565::BLACK,
+            prompt: Rgb565::YELLOW,
+            separator: Rgb565::WHITE,
+        }
+    }
+
+    pub fn from_theme(theme: Theme) -> Self {
+        match theme {
+            Theme::Classic => Self::classic(),
+            Theme::Compact => Self::compact(),
+            Theme::HighContrast => Self::high_contrast(),
+        }
+    }
+
+    /// The style of the theme in the settings on the SD card.
+    pub fn from_sd_card() -> Self {
+        Self::from_theme(TerminalSettings::load(&TerminalSettings::sd_card_path()).theme)
+    }
+
+    pub fn char_width(&self) -> usize {
+        (self.font.character_size.width + self.font.character_spacing) as usize
+    }
+
+    pub fn line_height(&self) -> usize {
+        self.font.character_size.height as usize
+    }
+
+    /// Distance from the top of a line to the text baseline.
+    pub fn baseline(&self) -> usize {
+        self.font.baseline as usize
+    }
+
+    /// Height of the command line area at the bottom, including the separator.
+    pub fn prompt_height(&self) -> usize {
+        self.line_height() + 5
+    }
+
+    /// How many columns fit in `width` pixels.
+    pub fn columns(&self, width: usize) -> usize {
+        (width.saturating_sub(MARGIN_LEFT) / self.char_width()).max(1)
+    }
+
+    /// How many output rows fit above the command line in `height` pixels.
+    pub fn rows(&self, height: usize) -> usize {
+        (height.saturating_sub(self.prompt_height()) / self.line_height()).max(1)
+    }
+}
+
+impl Default for TerminalStyle {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::os::storage::temp_dir;
+    use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    fn size(style: TerminalStyle) -> (usize, usize) {
+        (style.columns(SCREEN_WIDTH), style.rows(SCREEN_HEIGHT))
+    }
+
+    #[test]
+    fn rows_and_columns_on_the_cardputer() {
+        assert_eq!(size(TerminalStyle::classic()), (29, 9));
+        assert_eq!(size(TerminalStyle::compact()), (39, 12));
+        assert_eq!(size(TerminalStyle::high_contrast()), (29, 9));
+    }
+
+    #[test]
+    fn tiny_screens_keep_one_cell() {
+        assert_eq!(TerminalStyle::classic().columns(4), 1);
+        assert_eq!(TerminalStyle::classic().rows(10), 1);
+    }
+
+    #[test]
+    fn settings_pick_the_theme() {
+        let path = temp_dir("terminal-style").join(SETTINGS_FILE);
+        assert_eq!(TerminalSettings::load(&path).theme, Theme::Classic);
+
+        fs::write(&path, r#"{ "theme": "highContrast" }"#).unwrap();
+        let theme = TerminalSettings::load(&path).theme;
+        assert_eq!(theme, Theme::HighContrast);
+        assert_eq!(TerminalStyle::from_theme(theme).foreground, Rgb565::WHITE);
+
+        let settings = TerminalSettings {
+            theme: Theme::Compact,
+        };
+        settings.save(&path).unwrap();
+        assert_eq!(TerminalSettings::load(&path), settings);
+        assert_eq!(size(TerminalStyle::from_theme(settings.theme)), (39, 12));
+
+        fs::write(&path, r#"{ "theme": "neon" }"#).unwrap();
+        assert_eq!(TerminalSettings::load(&path).theme, Theme::Classic);
+    }
+}
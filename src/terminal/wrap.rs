@@ -0,0 +1,184 @@
+const TAB_WIDTH: usize = 8;
+
+/// Splits `text` into lines of at most `width` columns.
+///
+/// Every character takes one column, as in the terminal's monospace fonts.
+/// Lines break after spaces when possible, and in the middle of a word only
+/// when the word alone is wider than a line. `\n` starts a new line, tabs
+/// are expanded to spaces, and escape sequences are kept but take no room.
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        wrap_paragraph(paragraph, width, &mut lines);
+    }
+    lines
+}
+
+/// The number of columns `text` takes on screen.
+pub fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => skip_escape(&mut chars, &mut String::new()),
+            '\t' => width = (width / TAB_WIDTH + 1) * TAB_WIDTH,
+            c if c.is_control() => {}
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+fn wrap_paragraph(text: &str, width: usize, lines: &mut Vec<String>) {
+    let mut line = String::new();
+    let mut col = 0;
+    // Byte index and column just after the last space on `line`.
+    let mut last_break: Option<(usize, usize)> = None;
+    // Nothing visible since the last wrap; blanks here are dropped.
+    let mut at_wrap = false;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let (c, repeat) = match c {
+            '\x1b' => {
+                line.push(c);
+                skip_escape(&mut chars, &mut line);
+                continue;
+            }
+            '\t' => (' ', TAB_WIDTH - col % TAB_WIDTH),
+            c if c.is_control() => continue,
+            c => (c, 1),
+        };
+
+        for _ in 0..repeat {
+            if col == width {
+                if c == ' ' {
+                    // A space at the break is swallowed by it.
+                    lines.push(trim_end_spaces(&line).to_string());
+                    line.clear();
+                    col = 0;
+                    last_break = None;
+                    at_wrap = true;
+                    continue;
+                }
+                match last_break {
+                    Some((index, break_col)) => {
+                        let rest = line.split_off(index);
+                        lines.push(trim_end_spaces(&line).to_string());
+                        line = rest;
+                        col -= break_col;
+                    }
+                    None => {
+                        lines.push(std::mem::take(&mut line));
+                        col = 0;
+                    }
+                }
+                last_break = None;
+                at_wrap = col == 0;
+            }
+
+            if c == ' ' && at_wrap {
+                continue;
+            }
+            at_wrap = false;
+            line.push(c);
+            col += 1;
+            if c == ' ' {
+                last_break = Some((line.len(), col));
+            }
+        }
+    }
+    lines.push(line);
+}
+
+/// Copies the rest of an escape sequence from `chars` to `out`.
+fn skip_escape(chars: &mut std::iter::Peekable<std::str::Chars>, out: &mut String) {
+    match chars.next() {
+        Some('[') => {
+            out.push('[');
+            for c in chars.by_ref() {
+                out.push(c);
+                if ('\x40'..='\x7e').contains(&c) {
+                    break;
+                }
+            }
+        }
+        Some(']') => {
+            out.push(']');
+            while let Some(c) = chars.next() {
+                out.push(c);
+                if c == '\x07' {
+                    break;
+                }
+                if c == '\x1b' && chars.peek() == Some(&'\\') {
+                    out.push(chars.next().unwrap());
+                    break;
+                }
+            }
+        }
+        Some(c) => out.push(c),
+        None => {}
+    }
+}
+
+fn trim_end_spaces(line: &str) -> &str {
+    line.trim_end_matches(' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breaks_after_spaces() {
+        assert_eq!(
+            wrap_text("the quick brown fox", 10),
+            ["the quick", "brown fox"]
+        );
+        assert_eq!(wrap_text("the quick brown", 9), ["the quick", "brown"]);
+        assert_eq!(wrap_text("short", 10), ["short"]);
+        assert_eq!(wrap_text("", 10), [""]);
+    }
+
+    #[test]
+    fn long_words_break_anywhere() {
+        assert_eq!(wrap_text("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(wrap_text("ab abcdefgh", 4), ["ab", "abcd", "efgh"]);
+        assert_eq!(wrap_text("abc", 0), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn blanks_at_a_break_are_dropped() {
+        assert_eq!(wrap_text("abcd    efgh", 4), ["abcd", "efgh"]);
+        assert_eq!(wrap_text("ab   cd", 3), ["ab", "cd"]);
+        assert_eq!(wrap_text("  indented", 20), ["  indented"]);
+    }
+
+    #[test]
+    fn newlines_and_tabs() {
+        assert_eq!(wrap_text("a\n\nb\n", 5), ["a", "", "b", ""]);
+        assert_eq!(wrap_text("a\tb", 20), ["a       b"]);
+        assert_eq!(wrap_text("a\tb", 6), ["a", "b"]);
+        assert_eq!(wrap_text("a\rb", 5), ["ab"]);
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        assert_eq!(wrap_text("héllo wörld", 5), ["héllo", "wörld"]);
+        assert_eq!(wrap_text("ääääää", 4), ["ääää", "ää"]);
+        assert_eq!(display_width("héllo"), 5);
+    }
+
+    #[test]
+    fn escape_sequences_take_no_room() {
+        let text = "\x1b[31mred\x1b[0m and \x1b]0;title\x07blue";
+        assert_eq!(display_width(text), 12);
+        assert_eq!(
+            wrap_text(text, 8),
+            ["\x1b[31mred\x1b[0m and", "\x1b]0;title\x07blue"]
+        );
+        assert_eq!(display_width("\x1b]0;t\x1b\\ab"), 2);
+        assert_eq!(display_width("a\tb"), 9);
+    }
+}
@@ -1,153 +1,633 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
 use crate::keyboard::{Key, KeyEvent};
+use crate::layout::{self, KeyboardLayout, Layer};
+use crate::os::storage::{self, SD_LAYOUTS_PATH};
+
+/// The keyboard settings in `SD_SETTINGS_PATH`.
+pub const SETTINGS_FILE: &str = "keyboard.cfg";
+
+/// What Fn + key produces. Keys not listed here fall through to the layout's Fn layer.
+const FN_KEYS: [(Key, KeyboardEvent); 19] = [
+    (Key::Tilde, KeyboardEvent::Esc),
+    (Key::Backspace, KeyboardEvent::Delete),
+    (Key::BackSlash, KeyboardEvent::Insert),
+    (Key::Semicolon, KeyboardEvent::ArrowUp),
+    (Key::Period, KeyboardEvent::ArrowDown),
+    (Key::Comma, KeyboardEvent::ArrowLeft),
+    (Key::Slash, KeyboardEvent::ArrowRight),
+    (Key::_1, KeyboardEvent::F(1)),
+    (Key::_2, KeyboardEvent::F(2)),
+    (Key::_3, KeyboardEvent::F(3)),
+    (Key::_4, KeyboardEvent::F(4)),
+    (Key::_5, KeyboardEvent::F(5)),
+    (Key::_6, KeyboardEvent::F(6)),
+    (Key::_7, KeyboardEvent::F(7)),
+    (Key::_8, KeyboardEvent::F(8)),
+    (Key::_9, KeyboardEvent::F(9)),
+    (Key::_0, KeyboardEvent::F(10)),
+    (Key::Underscore, KeyboardEvent::F(11)),
+    (Key::Equal, KeyboardEvent::F(12)),
+];
+
+/// What Fn + Shift + key produces: the arrow keys move by page or to the line ends.
+const FN_SHIFT_KEYS: [(Key, KeyboardEvent); 4] = [
+    (Key::Semicolon, KeyboardEvent::PageUp),
+    (Key::Period, KeyboardEvent::PageDown),
+    (Key::Comma, KeyboardEvent::Home),
+    (Key::Slash, KeyboardEvent::End),
+];
+
+/// Looks up the navigation or function key for Fn (+ Shift) + `key`.
+pub fn fn_layer_event(key: Key, shift: bool) -> Option<KeyboardEvent> {
+    let shifted = if shift {
+        FN_SHIFT_KEYS.iter().find(|(k, _)| *k == key)
+    } else {
+        None
+    };
+    shifted
+        .or_else(|| FN_KEYS.iter().find(|(k, _)| *k == key))
+        .map(|(_, event)| *event)
+}
+
+/// How Shift, Ctrl, Alt, Opt and Fn behave.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ModifierMode {
+    /// A tap arms the modifier for the next key. Fn stays on until tapped again.
+    #[default]
+    Sticky,
+    /// A modifier applies only while it is held down.
+    Held,
+}
+
+/// The user's keyboard settings, kept as JSON on the SD card.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct KeyboardSettings {
+    /// A built-in layout or one from `SD_LAYOUTS_PATH`, by name.
+    pub layout: String,
+    /// `"sticky"` or `"held"`.
+    pub modifier_mode: ModifierMode,
+}
+
+impl Default for KeyboardSettings {
+    fn default() -> Self {
+        Self {
+            layout: KeyboardLayout::us().name().to_string(),
+            modifier_mode: ModifierMode::default(),
+        }
+    }
+}
+
+impl KeyboardSettings {
+    /// `SD_SETTINGS_PATH/SETTINGS_FILE`.
+    pub fn sd_card_path() -> PathBuf {
+        storage::settings_path(SETTINGS_FILE)
+    }
+
+    /// The settings in `path`, or the defaults if it is missing or broken.
+    pub fn load(path: &Path) -> Self {
+        storage::load_settings(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        storage::save_settings(path, self)
+    }
+}
 
 pub struct Typing {
     pub mod_shift: bool,
     pub mod_ctrl: bool,
     pub mod_fn: bool,
+    pub mod_opt: bool,
+    pub mod_alt: bool,
+    mode: ModifierMode,
+    layout: KeyboardLayout,
 }
 
 impl Typing {
     pub fn new() -> Self {
+        Self::with_layout(KeyboardLayout::us())
+    }
+
+    pub fn with_layout(layout: KeyboardLayout) -> Self {
         Self {
             mod_shift: false,
             mod_ctrl: false,
             mod_fn: false,
+            mod_opt: false,
+            mod_alt: false,
+            mode: ModifierMode::default(),
+            layout,
         }
     }
 
+    /// Set up from the settings on the SD card, with the layouts in
+    /// `SD_LAYOUTS_PATH` to choose from. Apps call this once the card is mounted.
+    pub fn from_sd_card() -> Self {
+        let mut typing = Self::new();
+        let settings = KeyboardSettings::load(&KeyboardSettings::sd_card_path());
+        typing.apply_settings(&settings, &layout::load_layouts(SD_LAYOUTS_PATH));
+        typing
+    }
+
+    /// Switches to the modifier mode and the layout in `settings`, looking
+    /// the layout up in `layouts`. An unknown name is logged and keeps the
+    /// current layout.
+    pub fn apply_settings(&mut self, settings: &KeyboardSettings, layouts: &[KeyboardLayout]) {
+        self.set_modifier_mode(settings.modifier_mode);
+        match layout::find(layouts, &settings.layout) {
+            Some(layout) => self.set_layout(layout.clone()),
+            None => warn!("Unknown keyboard layout {}", settings.layout),
+        }
+    }
+
+    pub fn layout(&self) -> &KeyboardLayout {
+        &self.layout
+    }
+
+    /// Switches the layout used for all following key presses.
+    pub fn set_layout(&mut self, layout: KeyboardLayout) {
+        self.layout = layout;
+    }
+
+    pub fn modifier_mode(&self) -> ModifierMode {
+        self.mode
+    }
+
+    /// Changes the modifier mode and releases all modifiers.
+    pub fn set_modifier_mode(&mut self, mode: ModifierMode) {
+        self.mode = mode;
+        self.mod_shift = false;
+        self.mod_ctrl = false;
+        self.mod_fn = false;
+        self.mod_opt = false;
+        self.mod_alt = false;
+    }
+
     pub fn eat_keyboard_events(&mut self, event: (KeyEvent, Key)) -> Option<KeyboardEvent> {
-        if let (KeyEvent::Pressed, key) = event {
-            match key {
-                Key::Shift => {
-                    self.mod_shift = !self.mod_shift;
-                    return None;
-                }
-                Key::Ctrl => {
-                    self.mod_ctrl = !self.mod_ctrl;
-                    return None;
-                }
-                Key::Fn => {
-                    self.mod_fn = !self.mod_fn;
-                    return None;
-                }
-                Key::Backspace => return Some(KeyboardEvent::Backspace),
-                Key::Enter => return Some(KeyboardEvent::Enter),
-                Key::Tab => return Some(KeyboardEvent::Tab),
-                _ => {}
-            }
+        let (event, key) = event;
 
-            if self.mod_fn {
-                match key {
-                    Key::Tilde => {
-                        self.mod_fn = false;
-                        return Some(KeyboardEvent::Esc);
-                    }
-                    Key::Backspace => {
+        if key.is_modifier() {
+            self.update_modifier(event, key);
+            return None;
+        }
+
+        if !matches!(event, KeyEvent::Pressed | KeyEvent::Repeat) {
+            return None;
+        }
+
+        if self.mod_fn {
+            if let Some(fn_event) = fn_layer_event(key, self.mod_shift) {
+                if self.mode == ModifierMode::Sticky {
+                    self.mod_shift = false;
+                    // Fn stays armed while navigating, but not after Esc, Delete or F1-F12.
+                    if !fn_event.is_navigation() {
                         self.mod_fn = false;
-                        return Some(KeyboardEvent::Canc);
                     }
-                    Key::Semicolon => return Some(KeyboardEvent::ArrowUp),
-                    Key::Period => return Some(KeyboardEvent::ArrowDown),
-                    Key::Comma => return Some(KeyboardEvent::ArrowLeft),
-                    Key::Slash => return Some(KeyboardEvent::ArrowRight),
-                    _ => {}
                 }
+                return Some(fn_event);
             }
+        }
 
-            if self.mod_ctrl && key == Key::C {
-                self.mod_ctrl = false;
-                return Some(KeyboardEvent::CtrlC);
+        match key {
+            Key::Backspace | Key::Enter | Key::Tab if self.mod_ctrl || self.mod_alt => {
+                return Some(self.chord(key));
             }
+            Key::Backspace | Key::Enter | Key::Tab => {
+                self.consume_modifiers();
+                return Some(match key {
+                    Key::Backspace => KeyboardEvent::Backspace,
+                    Key::Enter => KeyboardEvent::Enter,
+                    _ => KeyboardEvent::Tab,
+                });
+            }
+            _ => {}
+        }
 
-            let keys = [
-                Key::Tilde,
-                Key::_1,
-                Key::_2,
-                Key::_3,
-                Key::_4,
-                Key::_5,
-                Key::_6,
-                Key::_7,
-                Key::_8,
-                Key::_9,
-                Key::_0,
-                Key::Underscore,
-                Key::Equal,
-                Key::Q,
-                Key::W,
-                Key::E,
-                Key::R,
-                Key::T,
-                Key::Y,
-                Key::U,
-                Key::I,
-                Key::O,
-                Key::P,
-                Key::LeftSquareBracket,
-                Key::RightSquareBracket,
-                Key::BackSlash,
-                Key::A,
-                Key::S,
-                Key::D,
-                Key::F,
-                Key::G,
-                Key::H,
-                Key::J,
-                Key::K,
-                Key::L,
-                Key::Semicolon,
-                Key::Quote,
-                Key::Z,
-                Key::X,
-                Key::C,
-                Key::V,
-                Key::B,
-                Key::N,
-                Key::M,
-                Key::Comma,
-                Key::Period,
-                Key::Slash,
-                Key::Space,
-            ];
-
-            let ascii_lowercase = [
-                '`', '1', '2', '3', '4', '5', '6', '7', '8', '9', '0', '-', '=', 'q', 'w', 'e',
-                'r', 't', 'y', 'u', 'i', 'o', 'p', '[', ']', '\\', 'a', 's', 'd', 'f', 'g', 'h',
-                'j', 'k', 'l', ';', '\'', 'z', 'x', 'c', 'v', 'b', 'n', 'm', ',', '.', '/', ' ',
-            ];
-
-            let ascii_uppercase = [
-                '~', '!', '@', '#', '$', '%', '^', '&', '*', '(', ')', '_', '+', 'Q', 'W', 'E',
-                'R', 'T', 'Y', 'U', 'I', 'O', 'P', '{', '}', '|', 'A', 'S', 'D', 'F', 'G', 'H',
-                'J', 'K', 'L', ':', '"', 'Z', 'X', 'C', 'V', 'B', 'N', 'M', '<', '>', '?', ' ',
-            ];
-
-            assert!(keys.len() == ascii_lowercase.len() && keys.len() == ascii_uppercase.len());
-
-            if let Some(ind) = keys.iter().position(|&k| k == key) {
-                if self.mod_shift {
-                    self.mod_shift = false;
-                    return Some(KeyboardEvent::Ascii(ascii_uppercase[ind]));
-                } else {
-                    return Some(KeyboardEvent::Ascii(ascii_lowercase[ind]));
+        if self.mod_ctrl && !self.mod_alt && !self.mod_opt && key == Key::C {
+            self.consume_modifiers();
+            return Some(KeyboardEvent::CtrlC);
+        }
+
+        if self.mod_ctrl || self.mod_alt {
+            return Some(self.chord(key));
+        }
+
+        let chars = self.layout.chars(key);
+        if self.mod_opt {
+            return Some(match chars.get(Layer::Opt) {
+                Some(c) => {
+                    self.consume_modifiers();
+                    KeyboardEvent::Ascii(c)
                 }
-            }
+                None => self.chord(key),
+            });
+        }
+
+        let layer_char = if self.mod_fn {
+            chars.get(Layer::Fn)
+        } else {
+            None
+        };
+        let shifted = if self.mod_shift {
+            chars.get(Layer::Shift)
+        } else {
+            chars.get(Layer::Base)
+        };
+
+        if let Some(c) = layer_char.or(shifted) {
+            self.consume_modifiers();
+            return Some(KeyboardEvent::Ascii(c));
         }
         None
     }
+
+    fn update_modifier(&mut self, event: KeyEvent, key: Key) {
+        let flag = match key {
+            Key::Shift => &mut self.mod_shift,
+            Key::Ctrl => &mut self.mod_ctrl,
+            Key::Fn => &mut self.mod_fn,
+            Key::Opt => &mut self.mod_opt,
+            Key::Alt => &mut self.mod_alt,
+            _ => return,
+        };
+        match (self.mode, event) {
+            (ModifierMode::Sticky, KeyEvent::Pressed) => *flag = !*flag,
+            (ModifierMode::Held, KeyEvent::Pressed) => *flag = true,
+            (ModifierMode::Held, KeyEvent::Released) => *flag = false,
+            _ => {}
+        }
+    }
+
+    fn chord(&mut self, key: Key) -> KeyboardEvent {
+        let chord = KeyboardEvent::Chord {
+            ctrl: self.mod_ctrl,
+            alt: self.mod_alt,
+            opt: self.mod_opt,
+            key,
+        };
+        self.consume_modifiers();
+        chord
+    }
+
+    /// In sticky mode, disarms the one-shot modifiers after they were used.
+    fn consume_modifiers(&mut self) {
+        if self.mode == ModifierMode::Sticky {
+            self.mod_shift = false;
+            self.mod_ctrl = false;
+            self.mod_opt = false;
+            self.mod_alt = false;
+        }
+    }
+}
+
+impl Default for Typing {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyboardEvent {
+    /// A typed character. Despite the name this can be any `char` the layout produces.
     Ascii(char),
     Backspace,
     Enter,
     Tab,
     Esc,
-    Canc,
+    Delete,
+    Insert,
     ArrowUp,
     ArrowDown,
     ArrowLeft,
     ArrowRight,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// Function key F1-F12.
+    F(u8),
     CtrlC,
+    /// A key pressed together with Ctrl, Alt, or Opt when the layout has no
+    /// Opt character for it. `key` is the physical key, whatever the layout.
+    /// Ctrl+C alone is still reported as `CtrlC`.
+    Chord {
+        ctrl: bool,
+        alt: bool,
+        opt: bool,
+        key: Key,
+    },
+}
+
+impl KeyboardEvent {
+    /// Arrows, Home/End and PageUp/PageDown.
+    pub fn is_navigation(&self) -> bool {
+        matches!(
+            self,
+            KeyboardEvent::ArrowUp
+                | KeyboardEvent::ArrowDown
+                | KeyboardEvent::ArrowLeft
+                | KeyboardEvent::ArrowRight
+                | KeyboardEvent::Home
+                | KeyboardEvent::End
+                | KeyboardEvent::PageUp
+                | KeyboardEvent::PageDown
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::keyboard::{decode_changes, encode_keys, Keyboard, ScriptedKeyMatrix};
+    use crate::os::storage::temp_dir;
+
+    const MODES: [ModifierMode; 2] = [ModifierMode::Sticky, ModifierMode::Held];
+
+    fn typing(mode: ModifierMode) -> Typing {
+        let mut typing = Typing::new();
+        typing.set_modifier_mode(mode);
+        typing
+    }
+
+    /// Presses `modifiers` and then `key`, and returns what `key` typed.
+    fn chord(typing: &mut Typing, modifiers: &[Key], key: Key) -> Option<KeyboardEvent> {
+        for &modifier in modifiers {
+            assert_eq!(
+                typing.eat_keyboard_events((KeyEvent::Pressed, modifier)),
+                None
+            );
+        }
+        typing.eat_keyboard_events((KeyEvent::Pressed, key))
+    }
+
+    #[test]
+    fn fn_keys() {
+        for mode in MODES {
+            for (key, event) in FN_KEYS {
+                let mut typing = typing(mode);
+                assert_eq!(
+                    chord(&mut typing, &[Key::Fn], key),
+                    Some(event),
+                    "{:?} Fn+{:?}",
+                    mode,
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fn_shift_keys() {
+        for mode in MODES {
+            for (key, event) in FN_SHIFT_KEYS {
+                let mut typing = typing(mode);
+                let typed = chord(&mut typing, &[Key::Fn, Key::Shift], key);
+                assert_eq!(typed, Some(event), "{:?} Fn+Shift+{:?}", mode, key);
+            }
+        }
+    }
+
+    #[test]
+    fn fn_shift_falls_back_to_fn_keys() {
+        for (key, event) in FN_KEYS {
+            if FN_SHIFT_KEYS.iter().any(|(k, _)| *k == key) {
+                continue;
+            }
+            let mut typing = typing(ModifierMode::Sticky);
+            assert_eq!(chord(&mut typing, &[Key::Fn, Key::Shift], key), Some(event));
+        }
+    }
+
+    #[test]
+    fn sticky_fn_stays_on_for_navigation_only() {
+        for (key, event) in FN_KEYS {
+            let mut typing = typing(ModifierMode::Sticky);
+            chord(&mut typing, &[Key::Fn], key);
+            assert_eq!(typing.mod_fn, event.is_navigation(), "Fn+{:?}", key);
+            assert!(!typing.mod_shift);
+        }
+    }
+
+    #[test]
+    fn settings_pick_the_layout() {
+        let layouts = layout::builtin_layouts();
+        let mut typing = Typing::new();
+        let settings = KeyboardSettings {
+            layout: "DE".to_string(),
+            ..Default::default()
+        };
+        typing.apply_settings(&settings, &layouts);
+        assert_eq!(typing.layout().name(), "de");
+        assert_eq!(
+            typing.eat_keyboard_events((KeyEvent::Pressed, Key::Y)),
+            Some(KeyboardEvent::Ascii('z'))
+        );
+
+        let settings = KeyboardSettings {
+            layout: "colemak".to_string(),
+            modifier_mode: ModifierMode::Held,
+        };
+        typing.apply_settings(&settings, &layouts);
+        assert_eq!(typing.layout().name(), "de");
+        assert_eq!(typing.modifier_mode(), ModifierMode::Held);
+    }
+
+    #[test]
+    fn settings_file() {
+        let path = temp_dir("typing").join("settings").join(SETTINGS_FILE);
+        assert_eq!(KeyboardSettings::load(&path), KeyboardSettings::default());
+
+        let settings = KeyboardSettings {
+            layout: "fr".to_string(),
+            modifier_mode: ModifierMode::Held,
+        };
+        settings.save(&path).unwrap();
+        assert_eq!(KeyboardSettings::load(&path), settings);
+
+        fs::write(&path, "{").unwrap();
+        assert_eq!(KeyboardSettings::load(&path), KeyboardSettings::default());
+        fs::write(&path, r#"{ "modifierMode": "held" }"#).unwrap();
+        let loaded = KeyboardSettings::load(&path);
+        assert_eq!(loaded.layout, "us");
+        assert_eq!(loaded.modifier_mode, ModifierMode::Held);
+    }
+
+    /// The events from scanning each of `scans` in turn, like `Keyboard` does.
+    fn type_scans(typing: &mut Typing, scans: &[&[Key]]) -> Vec<KeyboardEvent> {
+        let mut previous = encode_keys(&[]);
+        let mut typed = Vec::new();
+        for keys in scans {
+            let current = encode_keys(keys);
+            for change in decode_changes(&previous, &current) {
+                typed.extend(typing.eat_keyboard_events(change));
+            }
+            previous = current;
+        }
+        typed
+    }
+
+    fn ctrl_chord(key: Key) -> KeyboardEvent {
+        KeyboardEvent::Chord {
+            ctrl: true,
+            alt: false,
+            opt: false,
+            key,
+        }
+    }
+
+    #[test]
+    fn sticky_modifiers_apply_to_the_next_key() {
+        let mut typing = typing(ModifierMode::Sticky);
+        let typed = type_scans(
+            &mut typing,
+            &[&[Key::Ctrl], &[], &[Key::X], &[], &[Key::X], &[]],
+        );
+        assert_eq!(typed, [ctrl_chord(Key::X), KeyboardEvent::Ascii('x')]);
+
+        let typed = type_scans(
+            &mut typing,
+            &[&[Key::Shift], &[], &[Key::Shift], &[], &[Key::A]],
+        );
+        assert_eq!(typed, [KeyboardEvent::Ascii('a')]);
+    }
+
+    #[test]
+    fn sticky_shift_is_used_up_by_editing_keys() {
+        let keys = [
+            (Key::Backspace, KeyboardEvent::Backspace),
+            (Key::Enter, KeyboardEvent::Enter),
+            (Key::Tab, KeyboardEvent::Tab),
+        ];
+        for (key, event) in keys {
+            let mut typing = typing(ModifierMode::Sticky);
+            assert_eq!(chord(&mut typing, &[Key::Shift], key), Some(event));
+            assert_eq!(
+                chord(&mut typing, &[], Key::A),
+                Some(KeyboardEvent::Ascii('a')),
+                "Shift+{:?}",
+                key
+            );
+        }
+
+        // A held Shift stays down.
+        let mut typing = typing(ModifierMode::Held);
+        chord(&mut typing, &[Key::Shift], Key::Enter);
+        assert_eq!(
+            chord(&mut typing, &[], Key::A),
+            Some(KeyboardEvent::Ascii('A'))
+        );
+    }
+
+    #[test]
+    fn held_modifiers_apply_while_down() {
+        let mut typing = typing(ModifierMode::Held);
+        let typed = type_scans(
+            &mut typing,
+            &[
+                &[Key::Ctrl],
+                &[Key::Ctrl, Key::X],
+                &[Key::Ctrl],
+                &[Key::Ctrl, Key::X],
+                &[],
+                &[Key::X],
+            ],
+        );
+        assert_eq!(
+            typed,
+            [
+                ctrl_chord(Key::X),
+                ctrl_chord(Key::X),
+                KeyboardEvent::Ascii('x')
+            ]
+        );
+    }
+
+    #[test]
+    fn held_chord_in_one_scan() {
+        let mut typing = typing(ModifierMode::Held);
+        let typed = type_scans(&mut typing, &[&[Key::Shift, Key::A], &[], &[Key::A]]);
+        assert_eq!(
+            typed,
+            [KeyboardEvent::Ascii('A'), KeyboardEvent::Ascii('a')]
+        );
+
+        // The next key goes down as the chord comes up.
+        let typed = type_scans(&mut typing, &[&[Key::Ctrl, Key::C], &[Key::B]]);
+        assert_eq!(typed, [KeyboardEvent::CtrlC, KeyboardEvent::Ascii('b')]);
+    }
+
+    /// What `typing` makes of the scans in `matrix`, one scan every 10 ms
+    /// with the default debounce and repeat timing.
+    fn type_on(typing: &mut Typing, matrix: ScriptedKeyMatrix) -> Vec<KeyboardEvent> {
+        let clock = ManualClock::new();
+        let mut keyboard = Keyboard::with_matrix(matrix);
+        keyboard.set_clock(clock.clone());
+        let mut typed = Vec::new();
+        while !keyboard.matrix_mut().is_finished() {
+            for event in keyboard.drain_events() {
+                typed.extend(typing.eat_keyboard_events((event.event, event.key)));
+            }
+            clock.advance(Duration::from_millis(10));
+        }
+        typed
+    }
+
+    #[test]
+    fn keyboard_shift_letter() {
+        let mut sticky = typing(ModifierMode::Sticky);
+        let mut matrix = ScriptedKeyMatrix::new();
+        matrix.tap_all(&[Key::Shift, Key::A, Key::A]);
+        assert_eq!(
+            type_on(&mut sticky, matrix),
+            [KeyboardEvent::Ascii('A'), KeyboardEvent::Ascii('a')]
+        );
+
+        let mut held = typing(ModifierMode::Held);
+        let mut matrix = ScriptedKeyMatrix::from_keys(&[
+            &[Key::Shift],
+            &[Key::Shift, Key::A],
+            &[Key::Shift],
+            &[],
+        ]);
+        matrix.tap(Key::A);
+        assert_eq!(
+            type_on(&mut held, matrix),
+            [KeyboardEvent::Ascii('A'), KeyboardEvent::Ascii('a')]
+        );
+    }
+
+    #[test]
+    fn keyboard_fn_arrows() {
+        let mut typing = typing(ModifierMode::Sticky);
+        let mut matrix = ScriptedKeyMatrix::new();
+        matrix.tap_all(&[
+            Key::Fn,
+            Key::Semicolon,
+            Key::Slash,
+            Key::Shift,
+            Key::Period,
+            Key::Fn,
+            Key::Semicolon,
+        ]);
+        assert_eq!(
+            type_on(&mut typing, matrix),
+            [
+                KeyboardEvent::ArrowUp,
+                KeyboardEvent::ArrowRight,
+                KeyboardEvent::PageDown,
+                KeyboardEvent::Ascii(';'),
+            ]
+        );
+    }
 }
@@ -0,0 +1,272 @@
+use crate::keyboard::Key;
+use crate::layout::{KeyboardLayout, Layer};
+use crate::typing::KeyboardEvent;
+
+const ESC: u8 = 0x1b;
+const DEL: u8 = 0x7f;
+
+/// DECCKM: how the host expects arrows and Home/End to be sent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CursorKeyMode {
+    /// `ESC [ A`, the default after reset.
+    #[default]
+    Normal,
+    /// `ESC O A`, requested by full-screen programs with `ESC [ ? 1 h`.
+    Application,
+}
+
+/// Encodes `KeyboardEvent`s as the bytes a VT100/xterm host expects from a keyboard.
+///
+/// Ctrl and Alt chords are sent for the character the key has in `layout`,
+/// so set it to the layout `Typing` uses.
+#[derive(Debug, Clone, Default)]
+pub struct VtEncoder {
+    pub cursor_keys: CursorKeyMode,
+    layout: KeyboardLayout,
+}
+
+impl VtEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_layout(layout: KeyboardLayout) -> Self {
+        Self {
+            cursor_keys: CursorKeyMode::default(),
+            layout,
+        }
+    }
+
+    pub fn set_layout(&mut self, layout: KeyboardLayout) {
+        self.layout = layout;
+    }
+
+    pub fn encode(&self, event: KeyboardEvent) -> Vec<u8> {
+        match event {
+            KeyboardEvent::Ascii(c) => utf8(c),
+            KeyboardEvent::Backspace => vec![DEL],
+            KeyboardEvent::Enter => vec![b'\r'],
+            KeyboardEvent::Tab => vec![b'\t'],
+            KeyboardEvent::Esc => vec![ESC],
+            KeyboardEvent::CtrlC => vec![0x03],
+            KeyboardEvent::ArrowUp => self.cursor_key(b'A'),
+            KeyboardEvent::ArrowDown => self.cursor_key(b'B'),
+            KeyboardEvent::ArrowRight => self.cursor_key(b'C'),
+            KeyboardEvent::ArrowLeft => self.cursor_key(b'D'),
+            KeyboardEvent::Home => self.cursor_key(b'H'),
+            KeyboardEvent::End => self.cursor_key(b'F'),
+            KeyboardEvent::Insert => tilde_key(2),
+            KeyboardEvent::Delete => tilde_key(3),
+            KeyboardEvent::PageUp => tilde_key(5),
+            KeyboardEvent::PageDown => tilde_key(6),
+            KeyboardEvent::F(n) => function_key(n),
+            KeyboardEvent::Chord {
+                ctrl,
+                alt,
+                opt,
+                key,
+            } => self.chord(ctrl, alt || opt, key),
+        }
+    }
+
+    /// Ctrl maps to the C0 control code, Alt/Opt (meta) prefixes ESC.
+    fn chord(&self, ctrl: bool, meta: bool, key: Key) -> Vec<u8> {
+        let mut bytes = match key {
+            Key::Backspace if ctrl => vec![0x08],
+            Key::Backspace => vec![DEL],
+            Key::Enter => vec![b'\r'],
+            Key::Tab => vec![b'\t'],
+            _ => match self.layout.char_for(key, Layer::Base) {
+                Some(c) if ctrl => control_code(c).map_or_else(|| utf8(c), |code| vec![code]),
+                Some(c) => utf8(c),
+                None => return Vec::new(),
+            },
+        };
+
+        if meta {
+            bytes.insert(0, ESC);
+        }
+        bytes
+    }
+
+    fn cursor_key(&self, code: u8) -> Vec<u8> {
+        match self.cursor_keys {
+            CursorKeyMode::Normal => vec![ESC, b'[', code],
+            CursorKeyMode::Application => vec![ESC, b'O', code],
+        }
+    }
+}
+
+/// `ESC [ n ~`
+fn tilde_key(n: u8) -> Vec<u8> {
+    let mut bytes = vec![ESC, b'['];
+    bytes.extend_from_slice(n.to_string().as_bytes());
+    bytes.push(b'~');
+    bytes
+}
+
+fn function_key(n: u8) -> Vec<u8> {
+    match n {
+        1 => vec![ESC, b'O', b'P'],
+        2 => vec![ESC, b'O', b'Q'],
+        3 => vec![ESC, b'O', b'R'],
+        4 => vec![ESC, b'O', b'S'],
+        5 => tilde_key(15),
+        6 => tilde_key(17),
+        7 => tilde_key(18),
+        8 => tilde_key(19),
+        9 => tilde_key(20),
+        10 => tilde_key(21),
+        11 => tilde_key(23),
+        12 => tilde_key(24),
+        _ => Vec::new(),
+    }
+}
+
+/// The byte xterm sends for Ctrl + `c`; `None` for characters outside ASCII.
+fn control_code(c: char) -> Option<u8> {
+    let code = match c {
+        'a'..='z' => c as u8 - b'a' + 1,
+        ' ' | '2' | '@' => 0x00,
+        '3' | '[' => ESC,
+        '4' | '\\' => 0x1c,
+        '5' | ']' => 0x1d,
+        '6' | '^' => 0x1e,
+        '7' | '-' | '_' | '/' => 0x1f,
+        '8' => DEL,
+        c if c.is_ascii() => c as u8,
+        _ => return None,
+    };
+    Some(code)
+}
+
+fn utf8(c: char) -> Vec<u8> {
+    let mut buf = [0; 4];
+    c.encode_utf8(&mut buf).as_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(ctrl: bool, alt: bool, key: Key) -> KeyboardEvent {
+        KeyboardEvent::Chord {
+            ctrl,
+            alt,
+            opt: false,
+            key,
+        }
+    }
+
+    fn ctrl(key: Key) -> KeyboardEvent {
+        chord(true, false, key)
+    }
+
+    fn alt(key: Key) -> KeyboardEvent {
+        chord(false, true, key)
+    }
+
+    #[test]
+    fn plain_keys() {
+        let vt = VtEncoder::new();
+        assert_eq!(vt.encode(KeyboardEvent::Ascii('a')), b"a");
+        assert_eq!(vt.encode(KeyboardEvent::Ascii('é')), "é".as_bytes());
+        assert_eq!(vt.encode(KeyboardEvent::Backspace), [DEL]);
+        assert_eq!(vt.encode(KeyboardEvent::Enter), b"\r");
+        assert_eq!(vt.encode(KeyboardEvent::Tab), b"\t");
+        assert_eq!(vt.encode(KeyboardEvent::Esc), [ESC]);
+        assert_eq!(vt.encode(KeyboardEvent::CtrlC), [0x03]);
+    }
+
+    #[test]
+    fn ctrl_chords() {
+        let vt = VtEncoder::new();
+        assert_eq!(vt.encode(ctrl(Key::A)), [0x01]);
+        assert_eq!(vt.encode(ctrl(Key::Z)), [0x1a]);
+        assert_eq!(vt.encode(ctrl(Key::Space)), [0x00]);
+        assert_eq!(vt.encode(ctrl(Key::_2)), [0x00]);
+        assert_eq!(vt.encode(ctrl(Key::LeftSquareBracket)), [ESC]);
+        assert_eq!(vt.encode(ctrl(Key::BackSlash)), [0x1c]);
+        assert_eq!(vt.encode(ctrl(Key::Slash)), [0x1f]);
+        assert_eq!(vt.encode(ctrl(Key::_8)), [DEL]);
+        assert_eq!(vt.encode(ctrl(Key::Backspace)), [0x08]);
+        assert_eq!(vt.encode(ctrl(Key::Enter)), b"\r");
+        assert_eq!(vt.encode(ctrl(Key::Shift)), b"");
+    }
+
+    #[test]
+    fn alt_chords() {
+        let vt = VtEncoder::new();
+        assert_eq!(vt.encode(alt(Key::X)), [ESC, b'x']);
+        assert_eq!(vt.encode(alt(Key::Backspace)), [ESC, DEL]);
+        assert_eq!(vt.encode(chord(true, true, Key::C)), [ESC, 0x03]);
+        let opt = KeyboardEvent::Chord {
+            ctrl: false,
+            alt: false,
+            opt: true,
+            key: Key::F,
+        };
+        assert_eq!(vt.encode(opt), [ESC, b'f']);
+    }
+
+    #[test]
+    fn chords_follow_the_layout() {
+        let vt = VtEncoder::with_layout(KeyboardLayout::qwertz());
+        assert_eq!(vt.encode(ctrl(Key::Y)), [0x1a]);
+        assert_eq!(vt.encode(ctrl(Key::Z)), [0x19]);
+        assert_eq!(vt.encode(alt(Key::Semicolon)), "\x1bö".as_bytes());
+        assert_eq!(vt.encode(ctrl(Key::Semicolon)), "ö".as_bytes());
+
+        let mut vt = VtEncoder::new();
+        vt.set_layout(KeyboardLayout::azerty());
+        assert_eq!(vt.encode(ctrl(Key::Q)), [0x01]);
+        assert_eq!(vt.encode(alt(Key::A)), [ESC, b'q']);
+    }
+
+    #[test]
+    fn cursor_keys() {
+        let mut vt = VtEncoder::new();
+        assert_eq!(vt.encode(KeyboardEvent::ArrowUp), b"\x1b[A");
+        assert_eq!(vt.encode(KeyboardEvent::ArrowDown), b"\x1b[B");
+        assert_eq!(vt.encode(KeyboardEvent::ArrowRight), b"\x1b[C");
+        assert_eq!(vt.encode(KeyboardEvent::ArrowLeft), b"\x1b[D");
+        assert_eq!(vt.encode(KeyboardEvent::Home), b"\x1b[H");
+        assert_eq!(vt.encode(KeyboardEvent::End), b"\x1b[F");
+
+        vt.cursor_keys = CursorKeyMode::Application;
+        assert_eq!(vt.encode(KeyboardEvent::ArrowUp), b"\x1bOA");
+        assert_eq!(vt.encode(KeyboardEvent::ArrowLeft), b"\x1bOD");
+        assert_eq!(vt.encode(KeyboardEvent::Home), b"\x1bOH");
+        assert_eq!(vt.encode(KeyboardEvent::End), b"\x1bOF");
+        // The editing keys are the same in both modes.
+        assert_eq!(vt.encode(KeyboardEvent::PageUp), b"\x1b[5~");
+    }
+
+    #[test]
+    fn editing_and_function_keys() {
+        let vt = VtEncoder::new();
+        assert_eq!(vt.encode(KeyboardEvent::Insert), b"\x1b[2~");
+        assert_eq!(vt.encode(KeyboardEvent::Delete), b"\x1b[3~");
+        assert_eq!(vt.encode(KeyboardEvent::PageUp), b"\x1b[5~");
+        assert_eq!(vt.encode(KeyboardEvent::PageDown), b"\x1b[6~");
+
+        let expected: [&[u8]; 12] = [
+            b"\x1bOP",
+            b"\x1bOQ",
+            b"\x1bOR",
+            b"\x1bOS",
+            b"\x1b[15~",
+            b"\x1b[17~",
+            b"\x1b[18~",
+            b"\x1b[19~",
+            b"\x1b[20~",
+            b"\x1b[21~",
+            b"\x1b[23~",
+            b"\x1b[24~",
+        ];
+        for (n, bytes) in (1..=12).zip(expected) {
+            assert_eq!(vt.encode(KeyboardEvent::F(n)), bytes, "F{}", n);
+        }
+        assert!(vt.encode(KeyboardEvent::F(13)).is_empty());
+    }
+}
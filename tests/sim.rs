@@ -0,0 +1,112 @@
+#![cfg(feature = "sim")]
+
+use std::fs;
+use std::path::PathBuf;
+
+use cardputer::backlight::MIN_BRIGHTNESS;
+use cardputer::dirty::Region;
+use cardputer::keyboard::Key;
+use cardputer::os::menu::{display_name, MenuItem};
+use cardputer::os::storage::temp_dir;
+use cardputer::sim::{SimDisplay, Simulator};
+use cardputer::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// A fresh directory laid out like an SD card with a few apps.
+fn sd_card(name: &str) -> PathBuf {
+    let root = temp_dir(&format!("sim-{}", name));
+    fs::create_dir_all(root.join("apps/demos")).unwrap();
+    fs::write(root.join("apps/demos/cube.bin"), b"cube").unwrap();
+    fs::write(root.join("apps/weather.bin"), b"weather").unwrap();
+    fs::write(root.join("apps/.hidden.bin"), b"hidden").unwrap();
+    fs::write(root.join("apps/notes.txt"), b"notes").unwrap();
+    root
+}
+
+#[test]
+fn starts_in_apps_and_lists_dirs_first() {
+    let root = sd_card("list");
+    let mut sim = Simulator::new(&root, SimDisplay::new());
+    sim.run();
+
+    let menu = sim.launcher().menu();
+    assert_eq!(menu.current, root.join("apps"));
+    let names: Vec<String> = menu.items.iter().map(display_name).collect();
+    assert_eq!(names, ["..", "[demos]", "weather.bin"]);
+
+    let frame = sim.display().last_frame().unwrap();
+    assert!(frame
+        .pixels()
+        .iter()
+        .any(|&pixel| pixel != Default::default()));
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn navigates_into_demos_and_launches() {
+    let root = sd_card("launch");
+    let mut sim = Simulator::new(&root, SimDisplay::new());
+    sim.tap_all(&[Key::Period, Key::Enter]);
+    sim.run();
+    assert_eq!(sim.launcher().menu().current, root.join("apps/demos"));
+    assert!(matches!(
+        sim.launcher().menu().selected_item(),
+        Some(MenuItem::Back)
+    ));
+
+    let frames = sim.display().frame_count();
+    sim.tap_all(&[Key::Period, Key::Enter]);
+    sim.run();
+    assert_eq!(sim.launched(), [root.join("apps/demos/cube.bin")]);
+    assert!(sim.display().frame_count() > frames);
+
+    sim.tap(Key::Backspace);
+    sim.run();
+    assert_eq!(sim.launcher().menu().current, root.join("apps"));
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn writes_frames_as_png() {
+    let root = sd_card("png");
+    let frames_dir = root.join("frames");
+    fs::create_dir_all(&frames_dir).unwrap();
+    let mut sim = Simulator::new(&root, SimDisplay::with_output_dir(&frames_dir));
+    sim.tap(Key::Period);
+    sim.run();
+
+    let count = sim.display().frame_count();
+    assert_eq!(count, 2);
+    assert!(frames_dir.join("frame-0002.png").is_file());
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn updates_only_what_changed() {
+    let root = sd_card("dirty");
+    let mut sim = Simulator::new(&root, SimDisplay::new());
+    sim.run();
+    sim.tap(Key::Period);
+    sim.run();
+
+    let regions = sim.display().regions();
+    assert_eq!(regions.len(), 2);
+    assert_eq!(regions[0], Region::full(SCREEN_WIDTH, SCREEN_HEIGHT));
+    // Moving the selection redraws two rows of the list, not the status bar.
+    assert!(regions[1].y > 0);
+    assert!(regions[1].height < SCREEN_HEIGHT / 2);
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn brightness_keys_step_and_clamp() {
+    let root = sd_card("backlight");
+    let mut sim = Simulator::new(&root, SimDisplay::new());
+    sim.tap_all(&[Key::Equal, Key::Underscore, Key::Underscore]);
+    sim.run();
+    assert_eq!(sim.backlight().brightness, 80);
+
+    sim.tap_all(&[Key::Underscore; 12]);
+    sim.run();
+    assert_eq!(sim.backlight().brightness, MIN_BRIGHTNESS);
+    fs::remove_dir_all(root).unwrap();
+}
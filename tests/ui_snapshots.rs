@@ -0,0 +1,92 @@
+#![cfg(feature = "sim")]
+
+use std::path::{Path, PathBuf};
+
+use cardputer::os::app::AppContext;
+use cardputer::os::menu::{MenuItem, MenuState};
+use cardputer::os::status::StatusSnapshot;
+use cardputer::os::ui::{draw_menu, draw_status, FlashProgress};
+use cardputer::sim::{assert_snapshot, Frame};
+
+fn snapshots() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots")
+}
+
+fn status() -> StatusSnapshot {
+    StatusSnapshot {
+        clock_text: "12:34:56".to_string(),
+        wifi_text: "AP Cardputer @ 192.168.4.1".to_string(),
+        battery_text: "Batt 87%".to_string(),
+    }
+}
+
+fn menu(current: &str, items: Vec<MenuItem>, selected: usize) -> MenuState {
+    MenuState {
+        root: PathBuf::from("/sdcard"),
+        current: PathBuf::from(current),
+        items,
+        selected,
+    }
+}
+
+fn app(path: &str) -> MenuItem {
+    MenuItem::App(PathBuf::from(path))
+}
+
+#[test]
+fn menu_with_dirs_and_apps() {
+    let menu = menu(
+        "/sdcard/apps",
+        vec![
+            MenuItem::Back,
+            MenuItem::Dir(PathBuf::from("/sdcard/apps/demos")),
+            app("/sdcard/apps/rink.bin"),
+            app("/sdcard/apps/weather.bin"),
+        ],
+        2,
+    );
+    let mut frame = Frame::new();
+    draw_menu(&mut frame, &menu, &AppContext::new(true, true), &status());
+    assert_snapshot(&snapshots(), "menu", &frame);
+}
+
+#[test]
+fn menu_scrolled_to_the_end() {
+    let items = (0..20)
+        .map(|i| app(&format!("/sdcard/apps/app{:02}.bin", i)))
+        .collect();
+    let menu = menu("/sdcard/apps", items, 18);
+    let mut frame = Frame::new();
+    draw_menu(&mut frame, &menu, &AppContext::new(true, true), &status());
+    assert_snapshot(&snapshots(), "menu_scrolled", &frame);
+}
+
+#[test]
+fn empty_menu_without_sd_card() {
+    let menu = menu("/sdcard", Vec::new(), 0);
+    let mut frame = Frame::new();
+    draw_menu(&mut frame, &menu, &AppContext::new(false, true), &status());
+    assert_snapshot(&snapshots(), "menu_no_sd", &frame);
+}
+
+#[test]
+fn status_with_progress() {
+    let mut frame = Frame::new();
+    let progress = FlashProgress {
+        written: 420,
+        total: Some(1000),
+    };
+    draw_status(&mut frame, "Flashing", &["weather.bin"], Some(progress));
+    assert_snapshot(&snapshots(), "status_progress", &frame);
+}
+
+#[test]
+fn status_lines() {
+    let mut frame = Frame::new();
+    let lines = [
+        "No OTA partitions found.",
+        "Update partitions.csv and rebuild.",
+    ];
+    draw_status(&mut frame, "Launch Error", &lines, None);
+    assert_snapshot(&snapshots(), "status_lines", &frame);
+}